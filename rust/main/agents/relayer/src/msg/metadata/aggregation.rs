@@ -179,6 +179,10 @@ impl MetadataBuilder for AggregationIsmMetadataBuilder {
         let metadata = Metadata::new(Self::format_metadata(&mut valid_metas, ism_addresses.len()));
         Ok(metadata)
     }
+
+    fn supports(&self, module_type: ModuleType) -> bool {
+        module_type == ModuleType::Aggregation
+    }
 }
 
 #[cfg(test)]
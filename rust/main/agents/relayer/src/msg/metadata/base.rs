@@ -27,14 +27,44 @@ pub enum MetadataBuildError {
     /// Unable to fetch metadata, but no error occurred
     #[error("Could not fetch metadata")]
     CouldNotFetch,
+    /// The offchain gateways of a CCIP-read ISM were queried but none
+    /// returned metadata yet, e.g. because they are down
+    #[error("Awaiting offchain gateway data")]
+    AwaitingOffchainData,
     #[error("Unknown or invalid module type ({0})")]
     UnsupportedModuleType(ModuleType),
+    /// A CCIP-read ISM was expected, e.g. by a routing ISM's configuration,
+    /// but the ISM has another module type
+    #[error("Not a CCIP-read ISM, module type is {0}")]
+    NotCcipReadIsm(ModuleType),
     #[error("Exceeded max depth when building metadata ({0})")]
     MaxIsmDepthExceeded(u32),
     #[error("Exceeded max count when building metadata ({0})")]
     MaxIsmCountReached(u32),
     #[error("Aggregation threshold not met ({0})")]
     AggregationThresholdNotMet(u32),
+    #[error("Timed out building metadata after {0:?}")]
+    TimedOut(Duration),
+}
+
+impl MetadataBuildError {
+    /// Whether building metadata for the message again is bound to fail the
+    /// same way until the ISM or the relayer's configuration changes, rather
+    /// than possibly succeeding on the next attempt
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            MetadataBuildError::Refused(_)
+            | MetadataBuildError::UnsupportedModuleType(_)
+            | MetadataBuildError::NotCcipReadIsm(_)
+            | MetadataBuildError::MaxIsmDepthExceeded(_)
+            | MetadataBuildError::MaxIsmCountReached(_) => true,
+            MetadataBuildError::FailedToBuild(_)
+            | MetadataBuildError::CouldNotFetch
+            | MetadataBuildError::AwaitingOffchainData
+            | MetadataBuildError::AggregationThresholdNotMet(_)
+            | MetadataBuildError::TimedOut(_) => false,
+        }
+    }
 }
 
 #[derive(Clone, Debug, new)]
@@ -55,6 +85,13 @@ pub trait MetadataBuilder: Send + Sync {
         message: &HyperlaneMessage,
         params: MessageMetadataBuildParams,
     ) -> Result<Metadata, MetadataBuildError>;
+
+    /// Whether this builder can build metadata for ISMs of `module_type`, so
+    /// it isn't asked to for ISMs it can't produce metadata for. Builders for
+    /// a single module type narrow this down.
+    fn supports(&self, _module_type: ModuleType) -> bool {
+        true
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -23,7 +23,7 @@ use hyperlane_core::{
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
-use super::IsmAwareAppContextClassifier;
+use super::{CcipReadContext, IsmAwareAppContextClassifier};
 
 /// Base metadata builder with types used by higher level metadata builders.
 #[allow(clippy::too_many_arguments)]
@@ -37,6 +37,7 @@ pub struct BaseMetadataBuilder {
     metrics: Arc<CoreMetrics>,
     db: HyperlaneRocksDB,
     app_context_classifier: IsmAwareAppContextClassifier,
+    ccip_read_context: Arc<CcipReadContext>,
 }
 
 impl Debug for BaseMetadataBuilder {
@@ -54,6 +55,7 @@ pub trait BuildsBaseMetadata: Send + Sync + Debug {
     fn origin_domain(&self) -> &HyperlaneDomain;
     fn destination_domain(&self) -> &HyperlaneDomain;
     fn app_context_classifier(&self) -> &IsmAwareAppContextClassifier;
+    fn ccip_read_context(&self) -> &CcipReadContext;
 
     async fn get_proof(&self, leaf_index: u32, checkpoint: Checkpoint) -> eyre::Result<Proof>;
     async fn highest_known_leaf_index(&self) -> Option<u32>;
@@ -64,6 +66,10 @@ pub trait BuildsBaseMetadata: Send + Sync + Debug {
     async fn build_multisig_ism(&self, address: H256) -> eyre::Result<Box<dyn MultisigIsm>>;
     async fn build_aggregation_ism(&self, address: H256) -> eyre::Result<Box<dyn AggregationIsm>>;
     async fn build_ccip_read_ism(&self, address: H256) -> eyre::Result<Box<dyn CcipReadIsm>>;
+    /// Root of the latest checkpoint of the destination's merkle tree hook
+    async fn destination_checkpoint_root(&self) -> eyre::Result<H256>;
+    /// Number of the latest block of the destination chain
+    async fn destination_block_number(&self) -> eyre::Result<u64>;
     async fn build_checkpoint_syncer(
         &self,
         message: &HyperlaneMessage,
@@ -84,6 +90,9 @@ impl BuildsBaseMetadata for BaseMetadataBuilder {
     fn app_context_classifier(&self) -> &IsmAwareAppContextClassifier {
         &self.app_context_classifier
     }
+    fn ccip_read_context(&self) -> &CcipReadContext {
+        &self.ccip_read_context
+    }
 
     async fn get_proof(&self, leaf_index: u32, checkpoint: Checkpoint) -> eyre::Result<Proof> {
         const CTX: &str = "When fetching message proof";
@@ -148,6 +157,29 @@ impl BuildsBaseMetadata for BaseMetadataBuilder {
             .await
     }
 
+    async fn destination_checkpoint_root(&self) -> eyre::Result<H256> {
+        let merkle_tree_hook = self
+            .destination_chain_setup
+            .build_merkle_tree_hook(&self.metrics)
+            .await?;
+        let checkpoint = merkle_tree_hook
+            .latest_checkpoint(&self.destination_chain_setup.reorg_period)
+            .await?;
+        Ok(checkpoint.root)
+    }
+
+    async fn destination_block_number(&self) -> eyre::Result<u64> {
+        let provider = self
+            .destination_chain_setup
+            .build_provider(&self.metrics)
+            .await?;
+        let chain_info = provider
+            .get_chain_metrics()
+            .await?
+            .ok_or_else(|| eyre::eyre!("Destination chain doesn't report its latest block"))?;
+        Ok(chain_info.latest_block.number)
+    }
+
     async fn build_checkpoint_syncer(
         &self,
         message: &HyperlaneMessage,
@@ -0,0 +1,522 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use hyperlane_core::{HyperlaneMessage, H256};
+
+use super::{
+    clock::{Clock, SystemClock},
+    metrics::CacheMetrics,
+};
+
+/// Identifies a CCIP-read lookup by the ISM, the function called on it and
+/// the message being verified. The message's domains are part of the key
+/// since caches are shared by all chains, and the same ISM address may be
+/// deployed on several of them.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct LookupKey {
+    pub origin_domain: u32,
+    pub destination_domain: u32,
+    pub ism_address: H256,
+    pub fn_name: &'static str,
+    pub message_id: H256,
+}
+
+impl LookupKey {
+    pub fn new(ism_address: H256, fn_name: &'static str, message: &HyperlaneMessage) -> Self {
+        Self {
+            origin_domain: message.origin,
+            destination_domain: message.destination,
+            ism_address,
+            fn_name,
+            message_id: message.id(),
+        }
+    }
+}
+
+/// A cached entry for a lookup, as listed by `CcipReadContext::dump_cache`.
+/// Values are summarized rather than included, since gateway URLs may hold
+/// credentials and metadata can be large.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct CacheEntryInfo {
+    /// cache holding the entry: `offchain_lookups`, `metadata` or `negative`
+    pub cache: String,
+    pub origin_domain: u32,
+    pub destination_domain: u32,
+    pub ism_address: H256,
+    pub fn_name: String,
+    pub message_id: H256,
+    /// milliseconds until the entry expires, zero if it's only kept while stale
+    pub expires_in_ms: u64,
+    /// number of gateway URLs of a cached `OffchainLookup`
+    pub gateway_urls: Option<usize>,
+    /// size of cached metadata, in bytes
+    pub metadata_len: Option<usize>,
+    /// host of the gateway cached metadata came from
+    pub gateway_host: Option<String>,
+}
+
+impl CacheEntryInfo {
+    pub fn new(cache: &str, key: &LookupKey, expires_in: Duration) -> Self {
+        Self {
+            cache: cache.to_owned(),
+            origin_domain: key.origin_domain,
+            destination_domain: key.destination_domain,
+            ism_address: key.ism_address,
+            fn_name: key.fn_name.to_owned(),
+            message_id: key.message_id,
+            expires_in_ms: expires_in.as_millis() as u64,
+            gateway_urls: None,
+            metadata_len: None,
+            gateway_host: None,
+        }
+    }
+}
+
+/// A map whose entries expire a TTL after being inserted, optionally
+/// shortened by a random jitter. Once it holds `max_entries`, inserting a new
+/// key evicts the least recently used entry. A zero TTL or `max_entries`
+/// disables the cache. Expired entries may be kept for a grace period, to be
+/// read with `get_stale`. Entries past that period are dropped when read, or
+/// by a sweep on insert at most once per TTL, so each access is logarithmic
+/// in the number of entries.
+#[derive(Debug)]
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    /// How long entries are kept after they expire
+    stale_grace: Duration,
+    /// Up to this fraction of the TTL is randomly taken off each entry's
+    /// lifetime, so entries inserted together don't all expire together
+    ttl_jitter: f64,
+    max_entries: usize,
+    entries: Mutex<Entries<K, V>>,
+    /// Incremented on every access, to order entries by recency of use
+    accesses: AtomicU64,
+    /// Entries expire by this clock
+    clock: Arc<dyn Clock>,
+    metrics: Option<CacheMetrics>,
+}
+
+/// The entries of a `TtlCache`, indexed by when they were last used
+#[derive(Debug)]
+struct Entries<K, V> {
+    map: HashMap<K, Entry<V>>,
+    /// Key of each entry by the access it was last used at, so the least
+    /// recently used entry comes first
+    recency: BTreeMap<u64, K>,
+    /// When entries past their stale grace period are next swept, `None`
+    /// until the first insert
+    next_sweep: Option<Instant>,
+}
+
+impl<K: Hash + Eq + Clone, V> Entries<K, V> {
+    fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+            recency: BTreeMap::new(),
+            next_sweep: None,
+        }
+    }
+
+    fn insert(&mut self, key: K, entry: Entry<V>) {
+        self.recency.insert(entry.last_used, key.clone());
+        if let Some(replaced) = self.map.insert(key, entry) {
+            self.recency.remove(&replaced.last_used);
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Entry<V>> {
+        let entry = self.map.remove(key)?;
+        self.recency.remove(&entry.last_used);
+        Some(entry)
+    }
+
+    /// Marks the entry for `key` as used at `access`
+    fn touch(&mut self, key: &K, access: u64) {
+        if let Some(entry) = self.map.get_mut(key) {
+            self.recency.remove(&entry.last_used);
+            entry.last_used = access;
+            self.recency.insert(access, key.clone());
+        }
+    }
+
+    fn evict_least_recently_used(&mut self) -> Option<K> {
+        let (_, key) = self.recency.pop_first()?;
+        self.map.remove(&key);
+        Some(key)
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&K, &Entry<V>) -> bool) {
+        let recency = &mut self.recency;
+        self.map.retain(|key, entry| {
+            let kept = keep(key, entry);
+            if !kept {
+                recency.remove(&entry.last_used);
+            }
+            kept
+        });
+    }
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+impl<V> Entry<V> {
+    fn expired(&self, now: Instant) -> bool {
+        now >= self.expires_at
+    }
+
+    /// Whether the entry expired more than `grace` ago
+    fn discarded(&self, now: Instant, grace: Duration) -> bool {
+        now >= self.expires_at + grace
+    }
+}
+
+/// Remembers lookups for which no gateway returned metadata, so that
+/// repeated attempts within the TTL don't query dead gateways again.
+pub type NegativeCache = TtlCache<LookupKey, ()>;
+
+/// Remembers the metadata gateways returned for lookups, along with the host
+/// of the gateway that returned it, so that retries shortly after a
+/// successful fetch don't query the gateways again.
+pub type MetadataCache = TtlCache<LookupKey, (Vec<u8>, String)>;
+
+impl<K: Hash + Eq + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            stale_grace: Duration::ZERO,
+            ttl_jitter: 0.0,
+            max_entries,
+            entries: Mutex::new(Entries::new()),
+            accesses: AtomicU64::new(0),
+            clock: Arc::new(SystemClock),
+            metrics: None,
+        }
+    }
+
+    /// Reports the size of the cache and its evictions to `metrics`
+    pub fn with_metrics(self, metrics: CacheMetrics) -> Self {
+        Self {
+            metrics: Some(metrics),
+            ..self
+        }
+    }
+
+    /// Shortens the TTL of each entry by a random fraction of up to
+    /// `ttl_jitter`, which must be below one
+    pub fn with_ttl_jitter(self, ttl_jitter: f64) -> Self {
+        Self { ttl_jitter, ..self }
+    }
+
+    /// Expires entries by `clock` instead of the system clock
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Keeps entries for `stale_grace` after they expire, for `get_stale`
+    pub fn with_stale_grace(self, stale_grace: Duration) -> Self {
+        Self {
+            stale_grace,
+            ..self
+        }
+    }
+
+    /// The value for `key` if it hasn't expired yet
+    pub async fn get(&self, key: &K) -> Option<V> {
+        self.get_usable(key, |entry, now| !entry.expired(now)).await
+    }
+
+    /// The value for `key`, even if it expired, as long as it did so within
+    /// the stale grace period
+    pub async fn get_stale(&self, key: &K) -> Option<V> {
+        self.get_usable(key, |_, _| true).await
+    }
+
+    /// The value for `key` if `usable`, dropping the entry if it is past its
+    /// stale grace period
+    async fn get_usable(&self, key: &K, usable: impl Fn(&Entry<V>, Instant) -> bool) -> Option<V> {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().await;
+        let entry = entries.map.get(key)?;
+        if entry.discarded(now, self.stale_grace) {
+            entries.remove(key);
+            self.record_len(entries.map.len());
+            return None;
+        }
+        if !usable(entry, now) {
+            return None;
+        }
+        let value = entry.value.clone();
+        entries.touch(key, self.tick());
+        Some(value)
+    }
+
+    pub async fn contains(&self, key: &K) -> bool {
+        self.get(key).await.is_some()
+    }
+
+    /// Inserts `value`, starting its TTL now. The least recently used entry
+    /// is evicted if the cache is full.
+    pub async fn insert(&self, key: K, value: V) {
+        self.insert_with_ttl(key, value, self.entry_ttl()).await
+    }
+
+    /// Inserts `value` like `insert`, but expiring after `ttl` instead of the
+    /// cache's TTL. Entries are still not inserted if the cache is disabled.
+    pub async fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) {
+        if self.ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().await;
+        if entries.next_sweep.map_or(true, |sweep_at| now >= sweep_at) {
+            entries.retain(|_, entry| !entry.discarded(now, self.stale_grace));
+            entries.next_sweep = Some(now + self.ttl);
+        }
+        if !entries.map.contains_key(&key)
+            && entries.map.len() >= self.max_entries
+            && entries.evict_least_recently_used().is_some()
+        {
+            if let Some(metrics) = &self.metrics {
+                metrics.evictions.inc();
+            }
+        }
+        let entry = Entry {
+            value,
+            expires_at: now + ttl,
+            last_used: self.tick(),
+        };
+        entries.insert(key, entry);
+        self.record_len(entries.map.len());
+    }
+
+    pub async fn remove(&self, key: &K) {
+        let mut entries = self.entries.lock().await;
+        entries.remove(key);
+        self.record_len(entries.map.len());
+    }
+
+    /// Removes all entries whose key matches `predicate`, returning how many
+    /// unexpired entries were removed
+    pub async fn remove_matching(&self, predicate: impl Fn(&K) -> bool) -> usize {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().await;
+        let mut removed = 0;
+        entries.retain(|key, entry| {
+            if !predicate(key) {
+                return true;
+            }
+            if !entry.expired(now) {
+                removed += 1;
+            }
+            false
+        });
+        self.record_len(entries.map.len());
+        removed
+    }
+
+    /// Every entry still held, with how long until it expires, which is
+    /// zero for entries only kept for the stale grace period. Doesn't count
+    /// as using the entries.
+    pub async fn snapshot(&self) -> Vec<(K, V, Duration)> {
+        let now = self.clock.now();
+        let entries = self.entries.lock().await;
+        entries
+            .map
+            .iter()
+            .filter(|(_, entry)| !entry.discarded(now, self.stale_grace))
+            .map(|(key, entry)| {
+                let expires_in = entry.expires_at.saturating_duration_since(now);
+                (key.clone(), entry.value.clone(), expires_in)
+            })
+            .collect()
+    }
+
+    fn entry_ttl(&self) -> Duration {
+        if self.ttl_jitter <= 0.0 {
+            return self.ttl;
+        }
+        let jitter = rand::thread_rng().gen_range(0.0..self.ttl_jitter);
+        self.ttl.mul_f64(1.0 - jitter)
+    }
+
+    fn tick(&self) -> u64 {
+        self.accesses.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn record_len(&self, len: usize) {
+        if let Some(metrics) = &self.metrics {
+            metrics.entries.set(len as i64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::mock_clock::MockClock;
+
+    use super::*;
+
+    fn key(ism_address: H256) -> LookupKey {
+        LookupKey {
+            origin_domain: 1,
+            destination_domain: 2,
+            ism_address,
+            fn_name: "getOffchainVerifyInfo",
+            message_id: H256::zero(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_entries_expire() {
+        let clock = Arc::new(MockClock::default());
+        let cache = NegativeCache::new(Duration::from_secs(60), 10).with_clock(clock.clone());
+        cache.insert(key(H256::zero()), ()).await;
+        assert!(cache.contains(&key(H256::zero())).await);
+
+        clock.advance(Duration::from_secs(59));
+        assert!(cache.contains(&key(H256::zero())).await);
+        clock.advance(Duration::from_secs(1));
+        assert!(!cache.contains(&key(H256::zero())).await);
+    }
+
+    #[tokio::test]
+    async fn test_entry_ttl_overrides_cache_ttl() {
+        let clock = Arc::new(MockClock::default());
+        let cache = NegativeCache::new(Duration::from_secs(60), 10).with_clock(clock.clone());
+        cache
+            .insert_with_ttl(key(H256::zero()), (), Duration::from_secs(10))
+            .await;
+        assert!(cache.contains(&key(H256::zero())).await);
+
+        clock.advance(Duration::from_secs(10));
+        assert!(!cache.contains(&key(H256::zero())).await);
+    }
+
+    #[tokio::test]
+    async fn test_zero_ttl_disables_cache() {
+        let cache = NegativeCache::new(Duration::ZERO, 10);
+        cache.insert(key(H256::zero()), ()).await;
+        assert!(!cache.contains(&key(H256::zero())).await);
+    }
+
+    #[tokio::test]
+    async fn test_remove_matching() {
+        let cache = TtlCache::new(Duration::from_secs(60), 10);
+        cache.insert(key(H256::zero()), 1).await;
+        cache.insert(key(H256::repeat_byte(1)), 2).await;
+
+        let removed = cache
+            .remove_matching(|key| key.ism_address == H256::zero())
+            .await;
+        assert_eq!(removed, 1);
+        assert_eq!(cache.get(&key(H256::zero())).await, None);
+        assert_eq!(cache.get(&key(H256::repeat_byte(1))).await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used_entry() {
+        let cache = TtlCache::new(Duration::from_secs(60), 2);
+        cache.insert(key(H256::from_low_u64_be(1)), 1).await;
+        cache.insert(key(H256::from_low_u64_be(2)), 2).await;
+        // Using the oldest entry makes the second one least recently used
+        assert_eq!(cache.get(&key(H256::from_low_u64_be(1))).await, Some(1));
+
+        cache.insert(key(H256::from_low_u64_be(3)), 3).await;
+        assert_eq!(cache.get(&key(H256::from_low_u64_be(1))).await, Some(1));
+        assert_eq!(cache.get(&key(H256::from_low_u64_be(2))).await, None);
+        assert_eq!(cache.get(&key(H256::from_low_u64_be(3))).await, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_jitter_spreads_expiry_of_entries_inserted_together() {
+        let ttl = Duration::from_secs(60);
+        let cache = NegativeCache::new(ttl, 10).with_ttl_jitter(0.1);
+        let before = Instant::now();
+        cache.insert(key(H256::from_low_u64_be(1)), ()).await;
+        cache.insert(key(H256::from_low_u64_be(2)), ()).await;
+        let after = Instant::now();
+
+        let entries = cache.entries.lock().await;
+        let expiries: Vec<_> = entries.map.values().map(|entry| entry.expires_at).collect();
+        assert_ne!(expiries[0], expiries[1]);
+        for expires_at in expiries {
+            assert!(expires_at >= before + ttl.mul_f64(0.9));
+            assert!(expires_at <= after + ttl);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_kept_for_stale_grace() {
+        let cache =
+            TtlCache::new(Duration::from_secs(60), 10).with_stale_grace(Duration::from_secs(60));
+        cache
+            .insert_with_ttl(key(H256::zero()), 1, Duration::ZERO)
+            .await;
+        cache.insert(key(H256::repeat_byte(1)), 2).await;
+
+        assert_eq!(cache.get(&key(H256::zero())).await, None);
+        assert_eq!(cache.get_stale(&key(H256::zero())).await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_lists_held_entries() {
+        let clock = Arc::new(MockClock::default());
+        let cache = TtlCache::new(Duration::from_secs(60), 10)
+            .with_stale_grace(Duration::from_secs(60))
+            .with_clock(clock.clone());
+        cache.insert(key(H256::zero()), 1).await;
+        clock.advance(Duration::from_secs(30));
+        cache.insert(key(H256::repeat_byte(1)), 2).await;
+        clock.advance(Duration::from_secs(40));
+
+        let mut snapshot = cache.snapshot().await;
+        snapshot.sort_by_key(|(_, value, _)| *value);
+        assert_eq!(
+            snapshot,
+            vec![
+                (key(H256::zero()), 1, Duration::ZERO),
+                (key(H256::repeat_byte(1)), 2, Duration::from_secs(20)),
+            ]
+        );
+
+        // Past its stale grace period
+        clock.advance(Duration::from_secs(50));
+        assert_eq!(cache.snapshot().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_discarded_entries_are_dropped_when_read() {
+        let clock = Arc::new(MockClock::default());
+        let cache = NegativeCache::new(Duration::from_secs(60), 10).with_clock(clock.clone());
+        cache.insert(key(H256::zero()), ()).await;
+        cache.insert(key(H256::repeat_byte(1)), ()).await;
+
+        // Before the next sweep, which is a TTL after the first insert
+        clock.advance(Duration::from_secs(59));
+        cache
+            .insert_with_ttl(key(H256::repeat_byte(2)), (), Duration::from_secs(1))
+            .await;
+        clock.advance(Duration::from_secs(1));
+        assert!(!cache.contains(&key(H256::repeat_byte(2))).await);
+
+        let entries = cache.entries.lock().await;
+        assert_eq!(entries.map.len(), 2);
+        assert_eq!(entries.recency.len(), 2);
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use prometheus::IntGaugeVec;
+use tracing::{info, warn};
+
+use super::clock::{Clock, SystemClock};
+
+/// State of the circuit for a single gateway host
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent as usual
+    Closed,
+    /// The host failed consistently, so requests are short-circuited
+    Open { opened_at: Instant },
+    /// The cooldown has passed and a single probe request is in flight
+    HalfOpen { probe_started_at: Instant },
+}
+
+impl CircuitState {
+    /// Value of the state metric
+    fn metric_value(&self) -> i64 {
+        match self {
+            Self::Closed => 0,
+            Self::Open { .. } => 1,
+            Self::HalfOpen { .. } => 2,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HostCircuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    first_failure_at: Instant,
+}
+
+/// Stops querying gateway hosts that are down. A host's circuit opens after
+/// `failure_threshold` consecutive failures within `window`, short-circuiting
+/// requests for `cooldown`. After that a single probe is let through, which
+/// closes the circuit if it succeeds and opens it again otherwise.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// Zero disables the breaker
+    failure_threshold: u32,
+    window: Duration,
+    cooldown: Duration,
+    hosts: Mutex<HashMap<String, HostCircuit>>,
+    clock: Arc<dyn Clock>,
+    /// Labels:
+    /// - `host`: host of the gateway URL
+    state_metric: Option<IntGaugeVec>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold,
+            window,
+            cooldown,
+            hosts: Mutex::new(HashMap::new()),
+            clock: Arc::new(SystemClock),
+            state_metric: None,
+        }
+    }
+
+    /// Times windows and cooldowns by `clock` instead of the system clock
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Reports the state of each host's circuit to `state_metric`
+    pub fn with_metrics(self, state_metric: IntGaugeVec) -> Self {
+        Self {
+            state_metric: Some(state_metric),
+            ..self
+        }
+    }
+
+    /// Whether a request may be sent to `host`. Once an open circuit has
+    /// cooled down this lets a probe through, whose outcome must be recorded.
+    pub fn allows(&self, host: &str) -> bool {
+        if self.failure_threshold == 0 {
+            return true;
+        }
+        let mut hosts = self.hosts.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(circuit) = hosts.get_mut(host) else {
+            return true;
+        };
+        // A probe that never reported back, e.g. because it was cancelled,
+        // is given up on after another cooldown
+        let cooled_down_since = match circuit.state {
+            CircuitState::Closed => return true,
+            CircuitState::Open { opened_at } => opened_at,
+            CircuitState::HalfOpen { probe_started_at } => probe_started_at,
+        };
+        let now = self.clock.now();
+        if now.duration_since(cooled_down_since) < self.cooldown {
+            return false;
+        }
+        info!(
+            host,
+            "Probing CCIP-read gateway host after circuit cooldown"
+        );
+        self.set_state(
+            host,
+            circuit,
+            CircuitState::HalfOpen {
+                probe_started_at: now,
+            },
+        );
+        true
+    }
+
+    /// Records whether a request to `host` reached a working gateway
+    pub fn record(&self, host: &str, success: bool) {
+        if self.failure_threshold == 0 {
+            return;
+        }
+        let mut hosts = self.hosts.lock().unwrap_or_else(PoisonError::into_inner);
+        if success {
+            if let Some(mut circuit) = hosts.remove(host) {
+                if circuit.state != CircuitState::Closed {
+                    info!(host, "CCIP-read gateway host recovered, closing circuit");
+                    self.set_state(host, &mut circuit, CircuitState::Closed);
+                }
+            }
+            return;
+        }
+
+        let now = self.clock.now();
+        let circuit = hosts.entry(host.to_owned()).or_insert(HostCircuit {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            first_failure_at: now,
+        });
+        if now.duration_since(circuit.first_failure_at) > self.window {
+            circuit.consecutive_failures = 0;
+            circuit.first_failure_at = now;
+        }
+        circuit.consecutive_failures = circuit.consecutive_failures.saturating_add(1);
+        let open = match circuit.state {
+            CircuitState::Closed => circuit.consecutive_failures >= self.failure_threshold,
+            CircuitState::HalfOpen { .. } => true,
+            CircuitState::Open { .. } => false,
+        };
+        if open {
+            warn!(
+                host,
+                failures = circuit.consecutive_failures,
+                cooldown = ?self.cooldown,
+                "CCIP-read gateway host keeps failing, opening circuit"
+            );
+            self.set_state(host, circuit, CircuitState::Open { opened_at: now });
+        }
+    }
+
+    fn set_state(&self, host: &str, circuit: &mut HostCircuit, state: CircuitState) {
+        circuit.state = state;
+        if let Some(metric) = &self.state_metric {
+            metric.with_label_values(&[host]).set(state.metric_value());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::mock_clock::MockClock;
+
+    use super::*;
+
+    const HOST: &str = "gateway.example.com";
+
+    #[test]
+    fn test_half_open_probe_closes_or_reopens_circuit() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60), Duration::ZERO);
+        breaker.record(HOST, false);
+        assert!(breaker.allows(HOST));
+        breaker.record(HOST, false);
+
+        // With no cooldown the next request is a probe right away
+        assert!(breaker.allows(HOST));
+        breaker.record(HOST, false);
+        assert!(breaker.allows(HOST));
+        breaker.record(HOST, true);
+        assert!(breaker.allows(HOST));
+        assert!(breaker.hosts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_failures_outside_window_are_not_consecutive() {
+        let breaker = CircuitBreaker::new(2, Duration::ZERO, Duration::from_secs(60));
+        breaker.record(HOST, false);
+        std::thread::sleep(Duration::from_millis(1));
+        breaker.record(HOST, false);
+        assert!(breaker.allows(HOST));
+    }
+
+    #[test]
+    fn test_open_circuit_lets_probe_through_after_cooldown() {
+        let clock = Arc::new(MockClock::default());
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60), Duration::from_secs(30))
+            .with_clock(clock.clone());
+        breaker.record(HOST, false);
+        assert!(!breaker.allows(HOST));
+
+        clock.advance(Duration::from_secs(30));
+        assert!(breaker.allows(HOST));
+        // Only a single probe is let through
+        assert!(!breaker.allows(HOST));
+    }
+}
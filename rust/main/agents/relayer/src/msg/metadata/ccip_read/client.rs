@@ -0,0 +1,1092 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    fs,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::utils::keccak256;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE, RETRY_AFTER},
+    redirect::{Attempt, Policy},
+    tls::TlsInfo,
+    Client, Identity, NoProxy, Proxy, Response, StatusCode, Url,
+};
+use serde_json::Value;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::timeout,
+};
+use tracing::{debug, error, info, warn};
+
+use hyperlane_core::utils::bytes_to_hex;
+
+use crate::settings::{
+    ccip_read::{load_gateway_headers, CcipReadConf, GatewayResponseFormat},
+    host_filter::HostFilter,
+};
+
+use super::{clock::Clock, pinning::pinning_tls_config, url_host, GatewayError};
+
+/// Sends requests to offchain gateways
+#[async_trait]
+pub trait GatewayClient: Send + Sync + Debug {
+    /// POSTs `body` to `url` as JSON, or sends a GET request if there is no
+    /// body, returning the body of a successful response. The `request_id`,
+    /// if any, is sent as `X-Request-Id`.
+    async fn fetch(
+        &self,
+        url: &str,
+        body: Option<&Value>,
+        request_id: Option<&str>,
+    ) -> Result<Vec<u8>, GatewayError>;
+
+    /// Checks that the gateway at `url` responds, returning the status it
+    /// responded with whatever it is. Sends a full GET request by default.
+    async fn probe(&self, url: &str) -> Result<StatusCode, GatewayError> {
+        self.fetch(url, None, None).await.map(|_| StatusCode::OK)
+    }
+}
+
+/// Queries gateways over HTTP with `reqwest`
+#[derive(Clone, Debug)]
+pub struct ReqwestGatewayClient {
+    client: Client,
+    timeout: Duration,
+    max_response_bytes: usize,
+    /// Responses whose content type can't hold this format are rejected
+    /// before being decoded
+    response_format: GatewayResponseFormat,
+    headers: GatewayHeaders,
+    /// Caching proxy requests for a message are sent to instead
+    mirror: Option<Url>,
+    /// Hosts only connected to over TLS, presenting a certificate matching
+    /// one of their pins
+    pinned_hosts: HashSet<String>,
+    /// Certificates expiring within this long are warned about
+    cert_expiry_warning: Duration,
+    /// When each host was last warned about, so the warning isn't logged
+    /// for every request
+    expiry_warnings: Arc<Mutex<HashMap<String, Instant>>>,
+    buffer_budget: Option<ResponseBufferBudget>,
+    /// Times certificate expiry, its warnings and `Retry-After` dates
+    clock: Arc<dyn Clock>,
+}
+
+/// Extra headers for each gateway host, e.g. credentials
+#[derive(Clone, Debug)]
+pub struct GatewayHeaders {
+    /// Values are marked sensitive so they never show up in logs.
+    headers: HashMap<String, HeaderMap>,
+    /// Headers from the gateway headers file, taking precedence
+    file: Option<Arc<GatewayHeadersFile>>,
+}
+
+impl GatewayHeaders {
+    /// The headers file is checked for changes by `clock`
+    pub fn new(conf: &CcipReadConf, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            headers: conf.gateway_headers.clone(),
+            file: conf.gateway_headers_file.clone().map(|path| {
+                Arc::new(GatewayHeadersFile::new(
+                    path,
+                    conf.gateway_headers_reload_interval,
+                    clock,
+                ))
+            }),
+        }
+    }
+
+    /// The configured headers for the host `url` points at, if any
+    pub fn for_url(&self, url: &str) -> Option<HeaderMap> {
+        let host = url_host(url)?;
+        if let Some(headers) = self
+            .file
+            .as_ref()
+            .and_then(|file| file.headers().get(&host).cloned())
+        {
+            return Some(headers);
+        }
+        self.headers.get(&host).cloned()
+    }
+}
+
+/// Gateway headers read from a file, which is read again once its size or
+/// modification time changes so credentials can be rotated at runtime
+#[derive(Debug)]
+struct GatewayHeadersFile {
+    path: PathBuf,
+    reload_interval: Duration,
+    state: Mutex<GatewayHeadersFileState>,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug, Default)]
+struct GatewayHeadersFileState {
+    checked_at: Option<Instant>,
+    /// Size and modification time of the file when it was last read
+    version: Option<(u64, SystemTime)>,
+    headers: Arc<HashMap<String, HeaderMap>>,
+}
+
+impl GatewayHeadersFile {
+    fn new(path: PathBuf, reload_interval: Duration, clock: Arc<dyn Clock>) -> Self {
+        let file = Self {
+            path,
+            reload_interval,
+            state: Default::default(),
+            clock,
+        };
+        file.headers();
+        file
+    }
+
+    /// The headers in the file, reading it again if it changed since it was
+    /// last checked. The previous headers are kept if it can't be read.
+    fn headers(&self) -> Arc<HashMap<String, HeaderMap>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = self.clock.now();
+        if state.checked_at.map_or(false, |checked_at| {
+            now.saturating_duration_since(checked_at) < self.reload_interval
+        }) {
+            return state.headers.clone();
+        }
+        state.checked_at = Some(now);
+        let version = fs::metadata(&self.path)
+            .and_then(|metadata| Ok((metadata.len(), metadata.modified()?)))
+            .ok();
+        if version.is_none() || version == state.version {
+            return state.headers.clone();
+        }
+        match load_gateway_headers(&self.path) {
+            Ok(headers) => {
+                if state.version.is_some() {
+                    info!(path = %self.path.display(), "Reloaded CCIP-read gateway headers");
+                }
+                state.version = version;
+                state.headers = Arc::new(headers);
+            }
+            Err(err) => {
+                warn!(path = %self.path.display(), ?err, "Failed to reload CCIP-read gateway headers, keeping the previous ones")
+            }
+        }
+        state.headers.clone()
+    }
+}
+
+/// Bytes that may be buffered across all response bodies being read at once
+#[derive(Clone, Debug)]
+struct ResponseBufferBudget {
+    bytes: Arc<Semaphore>,
+    max: u32,
+}
+
+impl ResponseBufferBudget {
+    fn new(max: usize) -> Self {
+        // Permits are acquired as `u32`s
+        let max = max.min(u32::MAX as usize) as u32;
+        Self {
+            bytes: Arc::new(Semaphore::new(max as usize)),
+            max,
+        }
+    }
+
+    /// Waits until `len` bytes may be buffered, until the permit is dropped.
+    /// Reads larger than the whole budget reserve all of it, so they wait
+    /// for every other read to finish rather than forever.
+    async fn reserve(&self, len: usize) -> OwnedSemaphorePermit {
+        let len = len.min(self.max as usize) as u32;
+        self.bytes
+            .clone()
+            .acquire_many_owned(len)
+            .await
+            .expect("the budget semaphore is never closed")
+    }
+}
+
+impl ReqwestGatewayClient {
+    /// How long an idle connection to a gateway is kept in the pool.
+    const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+    /// How much of an error response body is included in logs.
+    const MAX_LOGGED_BODY_LEN: usize = 256;
+    /// Header correlating a gateway request with the message it is for.
+    const REQUEST_ID_HEADER: &'static str = "X-Request-Id";
+    /// Header telling the gateway mirror which gateway a request is for.
+    const GATEWAY_URL_HEADER: &'static str = "X-Gateway-Url";
+    /// Header the gateway mirror caches responses by, see [`cache_key`].
+    const CACHE_KEY_HEADER: &'static str = "X-Cache-Key";
+    /// How often a certificate about to expire is warned about per host.
+    const CERT_EXPIRY_WARNING_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    /// Compressed responses are advertised with `Accept-Encoding` and
+    /// transparently decompressed, with the size limit applying to the
+    /// decompressed body. With pinned certificates, connections are made
+    /// with rustls, which checks the pins during the handshake.
+    pub fn new(conf: &CcipReadConf, clock: Arc<dyn Clock>) -> eyre::Result<Self> {
+        let mut builder = Client::builder()
+            .pool_idle_timeout(Self::POOL_IDLE_TIMEOUT)
+            .user_agent(conf.user_agent.clone())
+            .gzip(true)
+            .deflate(true)
+            .brotli(true)
+            .redirect(redirect_policy(
+                conf.max_redirects,
+                conf.gateway_hosts.clone(),
+                conf.pinned_certificates.keys().cloned().collect(),
+            ));
+        // Without an explicit proxy, reqwest uses the one from the environment
+        if let Some(proxy) = &conf.proxy {
+            let no_proxy = match &conf.no_proxy {
+                Some(no_proxy) => NoProxy::from_string(no_proxy),
+                None => NoProxy::from_env(),
+            };
+            builder = builder.proxy(Proxy::all(proxy.clone())?.no_proxy(no_proxy));
+        }
+        // DNS has no notion of ports, so the port of the URL is used
+        for (host, addresses) in &conf.host_overrides {
+            let addresses: Vec<_> = addresses
+                .iter()
+                .map(|address| SocketAddr::new(*address, 0))
+                .collect();
+            builder = builder.resolve_to_addrs(host, &addresses);
+        }
+        if !conf.pinned_certificates.is_empty() {
+            builder = builder.use_preconfigured_tls(pinning_tls_config(
+                conf.pinned_certificates.clone(),
+                conf.client_identity.as_ref(),
+            )?);
+        } else if let Some(identity) = &conf.client_identity {
+            builder = builder.identity(Identity::from_pkcs8_pem(
+                &identity.cert_pem,
+                &identity.key_pem,
+            )?);
+        }
+        // Exposes the peer certificate on responses, to check its expiry
+        if !conf.cert_expiry_warning.is_zero() {
+            builder = builder.tls_info(true);
+        }
+        Ok(Self::with_client(builder.build()?, conf, clock))
+    }
+
+    /// Uses the provided client for all gateway requests
+    pub fn with_client(client: Client, conf: &CcipReadConf, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            client,
+            timeout: conf.gateway_timeout,
+            max_response_bytes: conf.max_response_bytes,
+            response_format: conf.response_format,
+            headers: GatewayHeaders::new(conf, clock.clone()),
+            pinned_hosts: conf.pinned_certificates.keys().cloned().collect(),
+            cert_expiry_warning: conf.cert_expiry_warning,
+            expiry_warnings: Default::default(),
+            mirror: conf.gateway_mirror.clone(),
+            buffer_budget: conf
+                .max_buffered_response_bytes
+                .map(ResponseBufferBudget::new),
+            clock,
+        }
+    }
+
+    /// Whether the host `url` points at has pinned certificates
+    fn is_pinned(&self, url: &str) -> bool {
+        url_host(url).map_or(false, |host| self.pinned_hosts.contains(&host))
+    }
+
+    /// Refuses to send a request to a pinned host other than over HTTPS, as
+    /// the pins are only checked during the TLS handshake
+    fn check_pinned_scheme(&self, url: &str) -> Result<(), GatewayError> {
+        if !self.is_pinned(url) || url.starts_with("https://") {
+            return Ok(());
+        }
+        error!(
+            host = url_host(url).unwrap_or_default(),
+            "Refusing to send a CCIP-read request to a pinned gateway without TLS"
+        );
+        Err(GatewayError::CertificatePinMismatch)
+    }
+
+    /// Warns if the certificate the response came with is about to expire,
+    /// at most once an interval per host
+    fn check_certificate_expiry(&self, url: &str, res: &Response) {
+        if self.cert_expiry_warning.is_zero() {
+            return;
+        }
+        let Some(certificate) = res
+            .extensions()
+            .get::<TlsInfo>()
+            .and_then(TlsInfo::peer_certificate)
+        else {
+            return;
+        };
+        let now = self.clock.unix_time().as_secs() as i64;
+        let Some(expires_at) = expires_within(certificate, self.cert_expiry_warning, now) else {
+            return;
+        };
+        let host = url_host(url).unwrap_or_default();
+        {
+            let mut warnings = self
+                .expiry_warnings
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let now = self.clock.now();
+            let warned_recently = warnings.get(&host).map_or(false, |warned| {
+                now.saturating_duration_since(*warned) < Self::CERT_EXPIRY_WARNING_INTERVAL
+            });
+            if warned_recently {
+                return;
+            }
+            warnings.insert(host.clone(), now);
+        }
+        warn!(
+            host,
+            expires_at,
+            expires_in_secs = expires_at - now,
+            "CCIP-read gateway certificate expires soon"
+        );
+    }
+
+    /// Reads the response body, bailing out as soon as it exceeds the size
+    /// limit so a misbehaving gateway can't make us buffer unbounded data.
+    /// With a buffer budget, the body is only read once the bytes it may
+    /// take are reserved, which are released once it's returned.
+    async fn read_body(&self, mut res: Response) -> Result<Vec<u8>, GatewayError> {
+        let limit = self.max_response_bytes;
+        if res.content_length().map_or(false, |len| len > limit as u64) {
+            return Err(GatewayError::ResponseTooLarge(limit));
+        }
+        let _reserved = match &self.buffer_budget {
+            Some(budget) => {
+                let len = res.content_length().map_or(limit, |len| len as usize);
+                Some(
+                    timeout(self.timeout, budget.reserve(len))
+                        .await
+                        .map_err(|_| GatewayError::Timeout)?,
+                )
+            }
+            None => None,
+        };
+        let mut body = Vec::new();
+        while let Some(chunk) = res.chunk().await? {
+            if body.len() + chunk.len() > limit {
+                return Err(GatewayError::ResponseTooLarge(limit));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+}
+
+#[async_trait]
+impl GatewayClient for ReqwestGatewayClient {
+    async fn fetch(
+        &self,
+        url: &str,
+        body: Option<&Value>,
+        request_id: Option<&str>,
+    ) -> Result<Vec<u8>, GatewayError> {
+        // Only requests for a message have a key to be cached by. Requests
+        // to pinned gateways are sent directly, as the mirror's certificate
+        // can't be checked against the gateway's pins.
+        let mirror = self
+            .mirror
+            .as_ref()
+            .filter(|_| request_id.is_some() && !self.is_pinned(url));
+        let target = mirror.map_or(url, Url::as_str);
+        self.check_pinned_scheme(target)?;
+        let mut builder = match body {
+            Some(body) => self
+                .client
+                .post(target)
+                .header("Content-Type", "application/json")
+                .json(body),
+            None => self.client.get(target),
+        };
+        if let Some(request_id) = request_id {
+            builder = builder.header(Self::REQUEST_ID_HEADER, request_id);
+            if mirror.is_some() {
+                // The URL may carry credentials
+                let mut gateway_url = HeaderValue::from_str(url).map_err(|err| {
+                    GatewayError::Transport(format!(
+                        "Gateway URL can't be sent to the mirror: {err}"
+                    ))
+                })?;
+                gateway_url.set_sensitive(true);
+                builder = builder
+                    .header(Self::GATEWAY_URL_HEADER, gateway_url)
+                    .header(Self::CACHE_KEY_HEADER, cache_key(url, body));
+            }
+        }
+        // Still the gateway's headers, for the mirror to forward
+        if let Some(headers) = self.headers.for_url(url) {
+            builder = builder.headers(headers);
+        }
+        let res = builder.timeout(self.timeout).send().await?;
+        self.check_certificate_expiry(target, &res);
+        let status = res.status();
+        let retry_after = (status == StatusCode::TOO_MANY_REQUESTS)
+            .then(|| res.headers().get(RETRY_AFTER)?.to_str().ok())
+            .flatten()
+            .and_then(|value| {
+                parse_retry_after(value, (UNIX_EPOCH + self.clock.unix_time()).into())
+            });
+        if !status.is_success() {
+            // Error pages are often HTML, so only a prefix is logged
+            let body = self.read_body(res).await.unwrap_or_default();
+            let body = String::from_utf8_lossy(&body);
+            debug!(
+                host = url_host(url).unwrap_or_default(),
+                %status,
+                body = truncate(&body, Self::MAX_LOGGED_BODY_LEN),
+                "CCIP-read gateway returned an error status"
+            );
+            return Err(match retry_after {
+                Some(retry_after) => GatewayError::RetryAfter(retry_after),
+                None => GatewayError::Status(status),
+            });
+        }
+        // E.g. the login page of a captive portal, which would otherwise
+        // fail to decode with a confusing error
+        if let Some(content_type) = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        {
+            if !accepts_content_type(content_type, self.response_format) {
+                debug!(
+                    host = url_host(url).unwrap_or_default(),
+                    content_type, "CCIP-read gateway responded with an unexpected content type"
+                );
+                return Err(GatewayError::InvalidResponse(format!(
+                    "Unexpected content type `{content_type}`"
+                )));
+            }
+        }
+
+        self.read_body(res).await
+    }
+
+    /// Sends a HEAD request, so no response body is transferred
+    async fn probe(&self, url: &str) -> Result<StatusCode, GatewayError> {
+        self.check_pinned_scheme(url)?;
+        let mut builder = self.client.head(url);
+        if let Some(headers) = self.headers.for_url(url) {
+            builder = builder.headers(headers);
+        }
+        let res = builder.timeout(self.timeout).send().await?;
+        self.check_certificate_expiry(url, &res);
+        Ok(res.status())
+    }
+}
+
+/// Follows at most `max_redirects` redirects, and only to URLs permitted by
+/// `gateway_hosts`, so a gateway can't bounce requests to an internal host.
+/// Redirects to `pinned_hosts` are only followed over HTTPS.
+fn redirect_policy(
+    max_redirects: usize,
+    gateway_hosts: HostFilter,
+    pinned_hosts: HashSet<String>,
+) -> Policy {
+    Policy::custom(move |attempt: Attempt| {
+        // The previous URLs include the one originally requested
+        if attempt.previous().len() > max_redirects {
+            return attempt.error(format!(
+                "CCIP-read gateway redirected more than {max_redirects} times"
+            ));
+        }
+        if !gateway_hosts.permits(attempt.url().as_str()) {
+            warn!(
+                host = attempt.url().host_str().unwrap_or_default(),
+                "Refusing to follow a CCIP-read gateway redirect to a disallowed host"
+            );
+            return attempt.error("CCIP-read gateway redirected to a disallowed host");
+        }
+        let pinned =
+            url_host(attempt.url().as_str()).map_or(false, |host| pinned_hosts.contains(&host));
+        if pinned && attempt.url().scheme() != "https" {
+            return attempt.error("CCIP-read gateway redirected to a pinned host without TLS");
+        }
+        attempt.follow()
+    })
+}
+
+/// When the DER encoded `certificate` expires, as a unix timestamp, if
+/// that's within `window` of `now`
+fn expires_within(certificate: &[u8], window: Duration, now: i64) -> Option<i64> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(certificate).ok()?;
+    let expires_at = certificate.validity().not_after.timestamp();
+    let window = i64::try_from(window.as_secs()).unwrap_or(i64::MAX);
+    (expires_at.saturating_sub(now) < window).then_some(expires_at)
+}
+
+/// Whether a response with `content_type` may hold metadata in `format`.
+/// JSON may always hold it, as an envelope or a JSON-encoded hex string,
+/// and plain text or bytes unless only JSON is expected.
+fn accepts_content_type(content_type: &str, format: GatewayResponseFormat) -> bool {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let json = media_type == "application/json" || media_type.ends_with("+json");
+    let text = media_type == "text/plain" || media_type == "application/octet-stream";
+    match format {
+        GatewayResponseFormat::Json => json,
+        GatewayResponseFormat::RawHex | GatewayResponseFormat::Auto => json || text,
+    }
+}
+
+/// Key the gateway mirror caches the response to a request by: the hex
+/// keccak256 hash of its method, gateway URL and, for POST requests, JSON
+/// body, so the different lookups of a message don't share a response
+fn cache_key(url: &str, body: Option<&Value>) -> String {
+    let key = match body {
+        Some(body) => format!("POST {url}\n{body}"),
+        None => format!("GET {url}"),
+    };
+    bytes_to_hex(&keccak256(key))[2..].to_owned()
+}
+
+/// Parses a `Retry-After` header given either as delta-seconds or as an
+/// HTTP-date, into how long to wait from `now`
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(date.signed_duration_since(now).to_std().unwrap_or_default())
+}
+
+/// Returns at most the first `max_len` bytes of `s`, cut at a char boundary
+fn truncate(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+
+    use axum::{
+        body::StreamBody,
+        http::{
+            header::{ACCEPT_ENCODING, CONTENT_ENCODING, LOCATION},
+            Uri,
+        },
+        response::{Html, IntoResponse},
+        routing::get,
+        Router,
+    };
+    use base64::Engine;
+    use futures::{stream, FutureExt};
+    use hyperlane_core::H256;
+    use reqwest::StatusCode;
+
+    use crate::{
+        msg::metadata::SystemClock,
+        settings::ccip_read::ClientIdentity,
+        test_utils::{
+            client_identity::{CLIENT_CERT_PEM, CLIENT_KEY_PEM},
+            gateway_certificate::{SHORT_LIVED_CERT_NOT_AFTER, SHORT_LIVED_CERT_PEM},
+            mock_clock::MockClock,
+        },
+    };
+
+    use super::*;
+
+    /// `{"data":"0x1234"}`, gzipped
+    const GZIPPED_RESPONSE: [u8; 37] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xab, 0x56, 0x4a, 0x49, 0x2c,
+        0x49, 0x54, 0xb2, 0x52, 0x32, 0xa8, 0x30, 0x34, 0x32, 0x36, 0x51, 0xaa, 0x05, 0x00, 0xdf,
+        0x20, 0x35, 0x93, 0x11, 0x00, 0x00, 0x00,
+    ];
+
+    #[tokio::test]
+    async fn test_gzipped_responses_are_decompressed() {
+        let router = Router::new().route(
+            "/",
+            get(|headers: HeaderMap| async move {
+                let accepts_gzip = headers
+                    .get(ACCEPT_ENCODING)
+                    .and_then(|value| value.to_str().ok())
+                    .map_or(false, |value| value.contains("gzip"));
+                if !accepts_gzip {
+                    return StatusCode::NOT_ACCEPTABLE.into_response();
+                }
+                ([(CONTENT_ENCODING, "gzip")], GZIPPED_RESPONSE.to_vec()).into_response()
+            }),
+        );
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let client =
+            ReqwestGatewayClient::new(&CcipReadConf::default(), Arc::new(SystemClock)).unwrap();
+        let body = client
+            .fetch(&format!("http://{addr}/"), None, None)
+            .await
+            .unwrap();
+        assert_eq!(body, br#"{"data":"0x1234"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_requests_go_through_configured_proxy() {
+        let proxy = Router::new().fallback(|| async { "proxied" });
+        let proxy =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(proxy.into_make_service());
+        let proxy_addr = proxy.local_addr();
+        tokio::spawn(proxy);
+        let gateway = Router::new().fallback(|| async { "direct" });
+        let gateway =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(gateway.into_make_service());
+        let gateway_addr = gateway.local_addr();
+        tokio::spawn(gateway);
+
+        let conf = CcipReadConf {
+            proxy: Some(format!("http://{proxy_addr}").parse().unwrap()),
+            no_proxy: Some("localhost".to_owned()),
+            ..Default::default()
+        };
+        let client = ReqwestGatewayClient::new(&conf, Arc::new(SystemClock)).unwrap();
+        let body = client
+            .fetch("http://gateway.invalid/0x01", None, None)
+            .await
+            .unwrap();
+        assert_eq!(body, b"proxied");
+
+        // Hosts excluded from the proxy are queried directly
+        let body = client
+            .fetch(
+                &format!("http://localhost:{}/", gateway_addr.port()),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(body, b"direct");
+    }
+
+    #[tokio::test]
+    async fn test_host_overrides_skip_dns() {
+        let gateway = Router::new().fallback(|| async { "overridden" });
+        let gateway =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(gateway.into_make_service());
+        let gateway_addr = gateway.local_addr();
+        tokio::spawn(gateway);
+
+        let conf = CcipReadConf {
+            host_overrides: HashMap::from([(
+                "gateway.invalid".to_owned(),
+                vec![gateway_addr.ip()],
+            )]),
+            ..Default::default()
+        };
+        let client = ReqwestGatewayClient::new(&conf, Arc::new(SystemClock)).unwrap();
+        let body = client
+            .fetch(
+                &format!("http://gateway.invalid:{}/", gateway_addr.port()),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(body, b"overridden");
+    }
+
+    #[tokio::test]
+    async fn test_html_response_is_rejected_with_its_content_type() {
+        let gateway = Router::new().fallback(|| async { Html("<html>Sign in</html>") });
+        let gateway =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(gateway.into_make_service());
+        let gateway_addr = gateway.local_addr();
+        tokio::spawn(gateway);
+
+        let client =
+            ReqwestGatewayClient::new(&CcipReadConf::default(), Arc::new(SystemClock)).unwrap();
+        let res = client
+            .fetch(&format!("http://{gateway_addr}/"), None, None)
+            .await;
+        let Err(GatewayError::InvalidResponse(reason)) = res else {
+            panic!("Expected the HTML response to be rejected, got {res:?}");
+        };
+        assert!(reason.contains("text/html"), "reason: {reason}");
+    }
+
+    #[test]
+    fn test_accepts_content_type() {
+        use GatewayResponseFormat::*;
+
+        assert!(accepts_content_type(
+            "application/json; charset=utf-8",
+            Json
+        ));
+        assert!(accepts_content_type("application/vnd.api+json", Json));
+        assert!(!accepts_content_type("text/plain", Json));
+        assert!(accepts_content_type("Text/Plain", Auto));
+        assert!(accepts_content_type("application/octet-stream", RawHex));
+        for format in [Auto, Json, RawHex] {
+            assert!(!accepts_content_type("text/html; charset=utf-8", format));
+        }
+    }
+
+    #[test]
+    fn test_client_is_built_with_configured_identity() {
+        let conf = CcipReadConf {
+            client_identity: Some(ClientIdentity {
+                cert_pem: CLIENT_CERT_PEM.as_bytes().to_vec(),
+                key_pem: CLIENT_KEY_PEM.as_bytes().to_vec(),
+            }),
+            ..Default::default()
+        };
+        assert!(ReqwestGatewayClient::new(&conf, Arc::new(SystemClock)).is_ok());
+
+        let conf = CcipReadConf {
+            client_identity: Some(ClientIdentity {
+                cert_pem: CLIENT_CERT_PEM.as_bytes().to_vec(),
+                key_pem: b"not a key".to_vec(),
+            }),
+            ..Default::default()
+        };
+        assert!(ReqwestGatewayClient::new(&conf, Arc::new(SystemClock)).is_err());
+    }
+
+    #[test]
+    fn test_cache_key_covers_method_url_and_body() {
+        let url = "https://gateway.example.com/lookup";
+        let body = serde_json::json!({ "sender": "0x01", "data": "0x010203" });
+        let other_body = serde_json::json!({ "sender": "0x01", "data": "0x040506" });
+        let keys = HashSet::from([
+            cache_key(url, None),
+            cache_key(url, Some(&body)),
+            cache_key(url, Some(&other_body)),
+            cache_key("https://gateway.example.com/other", Some(&body)),
+        ]);
+        assert_eq!(keys.len(), 4);
+        assert_eq!(cache_key(url, Some(&body)), cache_key(url, Some(&body)));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(parse_retry_after("2", now), Some(Duration::from_secs(2)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_truncate_respects_char_boundaries() {
+        assert_eq!(truncate("hello", 10), "hello");
+        assert_eq!(truncate("hello", 3), "hel");
+        assert_eq!(truncate("héllo", 2), "h");
+    }
+
+    #[tokio::test]
+    async fn test_pinned_host_with_mismatched_certificate_is_rejected() {
+        let router = Router::new().fallback(|| async { "0x1234" });
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let conf = CcipReadConf {
+            pinned_certificates: HashMap::from([("127.0.0.1".to_owned(), vec![[0xab; 32]])]),
+            ..Default::default()
+        };
+        let client = ReqwestGatewayClient::new(&conf, Arc::new(SystemClock)).unwrap();
+        // Served without TLS, so no certificate can match the pin
+        let url = format!("http://{addr}/");
+        let res = client.fetch(&url, None, None).await;
+        assert!(matches!(res, Err(GatewayError::CertificatePinMismatch)));
+
+        // Unpinned hosts are unaffected
+        let url = format!("http://localhost:{}/", addr.port());
+        assert_eq!(client.fetch(&url, None, None).await.unwrap(), b"0x1234");
+    }
+
+    #[tokio::test]
+    async fn test_redirects_to_disallowed_hosts_are_not_followed() {
+        let internal = Router::new().fallback(|| async { "0x1234" });
+        let internal =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(internal.into_make_service());
+        let internal_url = format!("http://localhost:{}/", internal.local_addr().port());
+        tokio::spawn(internal);
+
+        let location = internal_url.clone();
+        let gateway = Router::new().fallback(move || async move {
+            (StatusCode::FOUND, [(LOCATION, location)]).into_response()
+        });
+        let gateway =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(gateway.into_make_service());
+        let gateway_addr = gateway.local_addr();
+        tokio::spawn(gateway);
+
+        let conf = CcipReadConf {
+            gateway_hosts: HostFilter {
+                allowed: vec!["127.0.0.1".parse().unwrap()],
+                denied: vec![],
+            },
+            ..Default::default()
+        };
+        let client = ReqwestGatewayClient::new(&conf, Arc::new(SystemClock)).unwrap();
+        let res = client
+            .fetch(&format!("http://{gateway_addr}/"), None, None)
+            .await;
+        assert!(matches!(res, Err(GatewayError::Transport(_))));
+
+        // Followed once the target is allowed
+        let client =
+            ReqwestGatewayClient::new(&CcipReadConf::default(), Arc::new(SystemClock)).unwrap();
+        let body = client
+            .fetch(&format!("http://{gateway_addr}/"), None, None)
+            .await
+            .unwrap();
+        assert_eq!(body, b"0x1234");
+    }
+
+    #[tokio::test]
+    async fn test_redirect_loops_are_bounded() {
+        let router = Router::new()
+            .fallback(|| async { (StatusCode::FOUND, [(LOCATION, "/")]).into_response() });
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let client =
+            ReqwestGatewayClient::new(&CcipReadConf::default(), Arc::new(SystemClock)).unwrap();
+        let res = client.fetch(&format!("http://{addr}/"), None, None).await;
+        assert!(matches!(res, Err(GatewayError::Transport(_))));
+    }
+
+    #[test]
+    fn test_certificate_near_expiry_is_detected() {
+        let encoded: String = SHORT_LIVED_CERT_PEM
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let der = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap();
+        let window = Duration::from_secs(2 * 24 * 60 * 60);
+
+        // Issued for a single day, so it's within the window from the start
+        let now = SHORT_LIVED_CERT_NOT_AFTER - 24 * 60 * 60;
+        assert_eq!(
+            expires_within(&der, window, now),
+            Some(SHORT_LIVED_CERT_NOT_AFTER)
+        );
+        let long_before = SHORT_LIVED_CERT_NOT_AFTER - 30 * 24 * 60 * 60;
+        assert_eq!(expires_within(&der, window, long_before), None);
+        assert_eq!(expires_within(b"not a certificate", window, now), None);
+    }
+
+    #[test]
+    fn test_buffer_budget_holds_back_reads_past_it() {
+        let budget = ResponseBufferBudget::new(1000);
+        let first = budget.reserve(600).now_or_never().unwrap();
+        assert!(budget.reserve(600).now_or_never().is_none());
+        drop(first);
+        let second = budget.reserve(600).now_or_never().unwrap();
+        // Reads larger than the budget wait for all of it
+        assert!(budget.reserve(5000).now_or_never().is_none());
+        drop(second);
+        assert!(budget.reserve(5000).now_or_never().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_buffer_budget_throttles_concurrent_reads() {
+        // Without a content length, every read reserves the whole budget
+        let router = Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    StreamBody::new(stream::once(async {
+                        tokio::time::sleep(Duration::from_millis(300)).await;
+                        Ok::<_, Infallible>(r#"{"data":"0x01"}"#)
+                    }))
+                }),
+            )
+            .route(
+                "/fast",
+                get(|| async {
+                    StreamBody::new(stream::once(async {
+                        Ok::<_, Infallible>(r#"{"data":"0x02"}"#)
+                    }))
+                }),
+            );
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let client = ReqwestGatewayClient::new(
+            &CcipReadConf {
+                max_buffered_response_bytes: Some(1024),
+                ..Default::default()
+            },
+            Arc::new(SystemClock),
+        )
+        .unwrap();
+        let slow_url = format!("http://{addr}/slow");
+        let fast_url = format!("http://{addr}/fast");
+        let slow = client.fetch(&slow_url, None, None);
+        let fast = client.fetch(&fast_url, None, None);
+        tokio::pin!(slow, fast);
+
+        // The slow body is being read, holding the whole budget
+        assert!(timeout(Duration::from_millis(100), &mut slow)
+            .await
+            .is_err());
+        assert!(timeout(Duration::from_millis(100), &mut fast)
+            .await
+            .is_err());
+        assert_eq!(slow.await.unwrap(), br#"{"data":"0x01"}"#);
+        assert_eq!(fast.await.unwrap(), br#"{"data":"0x02"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_rotated_credentials_are_sent_with_the_next_request() {
+        let router = Router::new().route(
+            "/",
+            get(|headers: HeaderMap| async move {
+                headers
+                    .get("x-api-key")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default()
+                    .to_owned()
+            }),
+        );
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let path = std::env::temp_dir().join(format!(
+            "ccip-read-gateway-headers-{}.json",
+            std::process::id()
+        ));
+        let write_key = |key: &str| {
+            let headers = serde_json::json!([{
+                "host": addr.ip().to_string(),
+                "headers": [{ "name": "X-Api-Key", "value": key }]
+            }]);
+            fs::write(&path, headers.to_string()).unwrap();
+        };
+        write_key("old-key");
+        let clock = Arc::new(MockClock::default());
+        let client = ReqwestGatewayClient::new(
+            &CcipReadConf {
+                gateway_headers_file: Some(path.clone()),
+                gateway_headers_reload_interval: Duration::from_secs(60),
+                ..Default::default()
+            },
+            clock.clone(),
+        )
+        .unwrap();
+        let url = format!("http://{addr}/");
+        assert_eq!(client.fetch(&url, None, None).await.unwrap(), b"old-key");
+
+        // The file is only checked for changes once the interval elapsed
+        write_key("rotated-key");
+        assert_eq!(client.fetch(&url, None, None).await.unwrap(), b"old-key");
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(
+            client.fetch(&url, None, None).await.unwrap(),
+            b"rotated-key"
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lookups_are_sent_through_gateway_mirror() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let router = Router::new().fallback({
+            let seen = seen.clone();
+            move |uri: Uri, headers: HeaderMap| async move {
+                let header = |name: &str| {
+                    headers
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_owned)
+                };
+                seen.lock().unwrap().push((
+                    uri.path().to_owned(),
+                    header("x-gateway-url"),
+                    header("x-cache-key"),
+                ));
+                axum::Json(serde_json::json!({ "data": "0x01" }))
+            }
+        });
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let client = ReqwestGatewayClient::new(
+            &CcipReadConf {
+                gateway_mirror: Some(format!("http://{addr}/mirror").parse().unwrap()),
+                pinned_certificates: HashMap::from([("localhost".to_owned(), vec![[0xab; 32]])]),
+                ..Default::default()
+            },
+            Arc::new(SystemClock),
+        )
+        .unwrap();
+        let message_id = format!("{:?}", H256::repeat_byte(1));
+        client
+            .fetch(
+                "https://gateway.example.com/0x010203",
+                None,
+                Some(&message_id),
+            )
+            .await
+            .unwrap();
+        // Not for a message, so sent directly
+        client
+            .fetch(&format!("http://{addr}/webhook"), None, None)
+            .await
+            .unwrap();
+        // Pinned, so not sent through the mirror, and refused without TLS
+        let res = client
+            .fetch(
+                &format!("http://localhost:{}/0x010203", addr.port()),
+                None,
+                Some(&message_id),
+            )
+            .await;
+        assert!(matches!(res, Err(GatewayError::CertificatePinMismatch)));
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (
+                    "/mirror".to_owned(),
+                    Some("https://gateway.example.com/0x010203".to_owned()),
+                    Some(cache_key("https://gateway.example.com/0x010203", None)),
+                ),
+                ("/webhook".to_owned(), None, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,31 @@
+use std::{
+    fmt::Debug,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Source of the current time for TTLs, cooldowns, back-offs and expiries,
+/// so tests can move time forward instead of sleeping
+pub trait Clock: Send + Sync + Debug {
+    fn now(&self) -> Instant;
+
+    /// Time since the Unix epoch, for expiries that are absolute or outlive
+    /// the process, such as persisted lookups and metadata expiring at a
+    /// timestamp
+    fn unix_time(&self) -> Duration;
+}
+
+/// Tells the time with `Instant::now` and `SystemTime::now`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn unix_time(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,80 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use super::GatewayError;
+
+/// Whether `url` is an RFC 2397 `data:` URI, which carries the gateway
+/// response inline instead of pointing at a gateway
+pub fn is_data_uri(url: &str) -> bool {
+    url.get(..5)
+        .map_or(false, |scheme| scheme.eq_ignore_ascii_case("data:"))
+}
+
+/// Decodes the payload of a `data:[<media type>][;base64],<data>` URI into
+/// the bytes a gateway would have responded with
+pub fn decode_data_uri(uri: &str) -> Result<Vec<u8>, GatewayError> {
+    let invalid =
+        |reason: &str| GatewayError::InvalidResponse(format!("Invalid data URI: {reason}"));
+    let Some((header, data)) = uri.get(5..).and_then(|rest| rest.split_once(',')) else {
+        return Err(invalid("missing `,` before the data"));
+    };
+    let data = percent_decode(data).ok_or_else(|| invalid("bad percent encoding"))?;
+    if header.to_ascii_lowercase().ends_with(";base64") {
+        STANDARD
+            .decode(data)
+            .map_err(|err| invalid(&err.to_string()))
+    } else {
+        Ok(data)
+    }
+}
+
+fn percent_decode(data: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut bytes = data.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+        let hex = [bytes.next()?, bytes.next()?];
+        let hex = std::str::from_utf8(&hex).ok()?;
+        decoded.push(u8::from_str_radix(hex, 16).ok()?);
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decodes_base64_payload() {
+        let uri = format!(
+            "data:application/json;base64,{}",
+            STANDARD.encode(r#"{"data":"0x0102"}"#)
+        );
+        assert!(is_data_uri(&uri));
+        assert_eq!(decode_data_uri(&uri).unwrap(), br#"{"data":"0x0102"}"#);
+    }
+
+    #[test]
+    fn test_decodes_percent_encoded_payload() {
+        let uri = "data:application/json,%7B%22data%22%3A%220x0102%22%7D";
+        assert_eq!(decode_data_uri(uri).unwrap(), br#"{"data":"0x0102"}"#);
+        assert_eq!(decode_data_uri("DATA:,0x0102").unwrap(), b"0x0102");
+    }
+
+    #[test]
+    fn test_rejects_malformed_uris() {
+        assert!(!is_data_uri("https://example.com/data:,0x01"));
+        for uri in [
+            "data:application/json",
+            "data:;base64,not base64!",
+            "data:,%zz",
+        ] {
+            assert!(matches!(
+                decode_data_uri(uri),
+                Err(GatewayError::InvalidResponse(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::{fmt::Debug, io::ErrorKind, path::PathBuf};
+
+use async_trait::async_trait;
+use tracing::warn;
+
+use hyperlane_core::{HyperlaneMessage, H256};
+
+/// Source of metadata consulted once no gateway returned usable metadata,
+/// e.g. a locally maintained store during gateway maintenance. Metadata from
+/// it is checked like gateway responses before it is used.
+#[async_trait]
+pub trait FallbackMetadataSource: Send + Sync + Debug {
+    /// Metadata for `message` on the ISM at `ism_address`, if it has any
+    async fn metadata(&self, ism_address: H256, message: &HyperlaneMessage) -> Option<Vec<u8>>;
+}
+
+/// Serves metadata from files in a directory, each holding the raw metadata
+/// of the message whose hex id (without `0x`) it is named after
+#[derive(Debug)]
+pub struct DirectoryMetadataSource {
+    dir: PathBuf,
+}
+
+impl DirectoryMetadataSource {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl FallbackMetadataSource for DirectoryMetadataSource {
+    async fn metadata(&self, _ism_address: H256, message: &HyperlaneMessage) -> Option<Vec<u8>> {
+        let path = self.dir.join(format!("{:x}", message.id()));
+        match tokio::fs::read(&path).await {
+            Ok(metadata) => Some(metadata),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => {
+                warn!(path = %path.display(), ?err, "Failed to read fallback CCIP-read metadata");
+                None
+            }
+        }
+    }
+}
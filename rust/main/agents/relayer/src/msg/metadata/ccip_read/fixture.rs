@@ -0,0 +1,108 @@
+use std::{io::ErrorKind, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use ethers::utils::keccak256;
+use reqwest::StatusCode;
+use serde_json::Value;
+use tracing::debug;
+
+use hyperlane_core::utils::bytes_to_hex;
+
+use super::{GatewayClient, GatewayError};
+
+/// Serves gateway responses from files in a directory instead of the
+/// network, to replay past lookups deterministically. Each response is the
+/// raw body of the file named after the request's [`fixture_name`].
+#[derive(Debug)]
+pub struct FixtureGatewayClient {
+    dir: PathBuf,
+    /// Client requests without a fixture are sent with, if allowed. Without
+    /// one, such requests fail as if the gateway responded with a 404.
+    live: Option<Arc<dyn GatewayClient>>,
+}
+
+impl FixtureGatewayClient {
+    pub fn new(dir: PathBuf, live: Option<Arc<dyn GatewayClient>>) -> Self {
+        Self { dir, live }
+    }
+
+    fn fixture_path(&self, url: &str, body: Option<&Value>) -> PathBuf {
+        self.dir.join(fixture_name(url, body))
+    }
+}
+
+/// Name of the fixture file for a request: the hex keccak256 hash of the URL,
+/// followed by a newline and the JSON body for POST requests, since POSTed
+/// gateways are often queried at the same URL for every message
+pub fn fixture_name(url: &str, body: Option<&Value>) -> String {
+    let key = match body {
+        Some(body) => format!("{url}\n{body}"),
+        None => url.to_owned(),
+    };
+    bytes_to_hex(&keccak256(key))[2..].to_owned()
+}
+
+#[async_trait]
+impl GatewayClient for FixtureGatewayClient {
+    async fn fetch(
+        &self,
+        url: &str,
+        body: Option<&Value>,
+        request_id: Option<&str>,
+    ) -> Result<Vec<u8>, GatewayError> {
+        let path = self.fixture_path(url, body);
+        match tokio::fs::read(&path).await {
+            Ok(response) => {
+                debug!(path = %path.display(), "Replaying CCIP-read gateway response from fixture");
+                Ok(response)
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => match &self.live {
+                Some(live) => {
+                    debug!(path = %path.display(), "No fixture for CCIP-read gateway request, sending it");
+                    live.fetch(url, body, request_id).await
+                }
+                None => {
+                    debug!(path = %path.display(), "No fixture for CCIP-read gateway request");
+                    Err(GatewayError::Status(StatusCode::NOT_FOUND))
+                }
+            },
+            Err(err) => Err(GatewayError::Transport(format!(
+                "Failed to read fixture {}: {err}",
+                path.display()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_requests_without_fixture_are_not_sent() {
+        let dir = std::env::temp_dir().join(format!("ccip-read-no-fixture-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let client = FixtureGatewayClient::new(dir.clone(), None);
+
+        let res = client
+            .fetch("https://gateway.example.com/0x01", None, None)
+            .await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            res,
+            Err(GatewayError::Status(StatusCode::NOT_FOUND))
+        ));
+    }
+
+    #[test]
+    fn test_posted_bodies_have_their_own_fixtures() {
+        let url = "https://gateway.example.com/";
+        let first = fixture_name(url, Some(&json!({ "data": "0x01" })));
+        let second = fixture_name(url, Some(&json!({ "data": "0x02" })));
+        assert_ne!(first, second);
+        assert_ne!(first, fixture_name(url, None));
+        assert_eq!(first.len(), 64);
+    }
+}
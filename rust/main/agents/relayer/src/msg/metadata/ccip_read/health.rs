@@ -0,0 +1,54 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+/// Reachability of the gateways behind a set of CCIP-read URLs
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct GatewayHealth {
+    /// One entry per probed URL, in the order given
+    pub gateways: Vec<GatewayProbe>,
+}
+
+impl GatewayHealth {
+    /// Whether every probed gateway responded
+    pub fn all_reachable(&self) -> bool {
+        self.gateways.iter().all(|probe| probe.reachable)
+    }
+}
+
+/// Outcome of probing a single gateway
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct GatewayProbe {
+    /// The URL template as listed by the ISM
+    pub url: String,
+    /// Whether the gateway responded at all, whatever the status
+    pub reachable: bool,
+    /// Status the gateway responded with, if it was queried over HTTP
+    pub status: Option<u16>,
+    /// Time until the gateway responded or the probe failed
+    pub latency_ms: u64,
+    /// Why the gateway is unreachable
+    pub error: Option<String>,
+}
+
+impl GatewayProbe {
+    pub fn new(url: String, started: Instant, res: Result<Option<u16>, String>) -> Self {
+        let latency_ms = started.elapsed().as_millis() as u64;
+        match res {
+            Ok(status) => Self {
+                url,
+                reachable: true,
+                status,
+                latency_ms,
+                error: None,
+            },
+            Err(error) => Self {
+                url,
+                reachable: false,
+                status: None,
+                latency_ms,
+                error: Some(error),
+            },
+        }
+    }
+}
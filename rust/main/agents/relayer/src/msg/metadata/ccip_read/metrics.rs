@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+use hyperlane_base::CoreMetrics;
+use hyperlane_core::H256;
+use prometheus::{Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec};
+
+use super::GatewayError;
+
+/// Metrics on the requests made to CCIP-read gateways
+#[derive(Clone, Debug)]
+pub struct CcipReadMetrics {
+    /// Labels:
+    /// - `host`: host of the gateway URL
+    /// - `outcome`: one of `success`, `http_error`, `parse_error`, `no_data`,
+    ///   `timeout`, `circuit_open` or `pin_mismatch`
+    gateway_requests: IntCounterVec,
+    /// Time taken by each attempt at a gateway request, in seconds.
+    ///
+    /// Labels:
+    /// - `host`: host of the gateway URL
+    /// - `outcome`: same as for `gateway_requests`
+    gateway_latency: HistogramVec,
+    /// Position, starting at 1, of the gateway metadata was found at among
+    /// the URLs of a lookup. Often being above 1 means the first gateways
+    /// are unhealthy.
+    successful_gateway_position: Histogram,
+    /// Labels:
+    /// - `fn_name`: the ISM function whose result is cached
+    /// - `result`: `hit` or `miss`
+    call_cache_lookups: IntCounterVec,
+    /// Labels:
+    /// - `cache`: which of the CCIP-read caches
+    cache_entries: IntGaugeVec,
+    /// Labels:
+    /// - `cache`: which of the CCIP-read caches
+    cache_evictions: IntCounterVec,
+    /// 0 if closed, 1 if open and 2 if half-open.
+    ///
+    /// Labels:
+    /// - `host`: host of the gateway URL
+    circuit_state: IntGaugeVec,
+    /// Labels:
+    /// - `ism`: address of the ISM
+    /// - `reason`: `did_not_revert` if `getOffchainVerifyInfo` succeeded,
+    ///   `no_offchain_lookup` if it reverted with another error or
+    ///   `sender_mismatch` if the `OffchainLookup` names another sender
+    ism_misconfigurations: IntCounterVec,
+    /// Labels:
+    /// - `operation`: `read` or `write`
+    offchain_lookup_store_failures: IntCounterVec,
+}
+
+/// Size and evictions of a single cache
+#[derive(Clone, Debug)]
+pub struct CacheMetrics {
+    pub entries: IntGauge,
+    pub evictions: IntCounter,
+}
+
+impl CcipReadMetrics {
+    /// Spans fast cached responses up to the default gateway timeout
+    const LATENCY_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+    /// ISMs rarely list more than a handful of gateways
+    const POSITION_BUCKETS: [f64; 6] = [1.0, 2.0, 3.0, 4.0, 5.0, 10.0];
+
+    pub fn new(metrics: &CoreMetrics) -> Self {
+        let gateway_requests = metrics
+            .new_int_counter(
+                "ccip_read_gateway_requests",
+                "Number of CCIP-read gateway lookups, by gateway host and outcome",
+                &["host", "outcome"],
+            )
+            .expect("failed to register ccip_read_gateway_requests metric");
+        let gateway_latency = metrics
+            .new_histogram(
+                "ccip_read_gateway_latency_seconds",
+                "Latency of CCIP-read gateway request attempts, by gateway host and outcome",
+                &["host", "outcome"],
+                Self::LATENCY_BUCKETS.to_vec(),
+            )
+            .expect("failed to register ccip_read_gateway_latency_seconds metric");
+        let successful_gateway_position = metrics
+            .new_histogram(
+                "ccip_read_successful_gateway_position",
+                "Position among the URLs of a CCIP-read lookup of the gateway metadata was found at, starting at 1",
+                &[],
+                Self::POSITION_BUCKETS.to_vec(),
+            )
+            .expect("failed to register ccip_read_successful_gateway_position metric")
+            .with_label_values(&[]);
+        let call_cache_lookups = metrics
+            .new_int_counter(
+                "ccip_read_call_cache_lookups",
+                "Number of lookups in the cache of CCIP-read ISM calls, by function and result",
+                &["fn_name", "result"],
+            )
+            .expect("failed to register ccip_read_call_cache_lookups metric");
+        let cache_entries = metrics
+            .new_int_gauge(
+                "ccip_read_cache_entries",
+                "Number of entries held by a CCIP-read cache",
+                &["cache"],
+            )
+            .expect("failed to register ccip_read_cache_entries metric");
+        let cache_evictions = metrics
+            .new_int_counter(
+                "ccip_read_cache_evictions",
+                "Number of entries evicted from a full CCIP-read cache",
+                &["cache"],
+            )
+            .expect("failed to register ccip_read_cache_evictions metric");
+        let circuit_state = metrics
+            .new_int_gauge(
+                "ccip_read_gateway_circuit_state",
+                "State of the circuit breaker of a CCIP-read gateway host: 0 if closed, 1 if open and 2 if half-open",
+                &["host"],
+            )
+            .expect("failed to register ccip_read_gateway_circuit_state metric");
+        let ism_misconfigurations = metrics
+            .new_int_counter(
+                "ccip_read_ism_misconfigurations",
+                "Number of calls to getOffchainVerifyInfo that showed the CCIP-read ISM is misconfigured, by ISM and reason",
+                &["ism", "reason"],
+            )
+            .expect("failed to register ccip_read_ism_misconfigurations metric");
+        let offchain_lookup_store_failures = metrics
+            .new_int_counter(
+                "ccip_read_offchain_lookup_store_failures",
+                "Number of failed reads and writes of persisted CCIP-read OffchainLookups, by operation",
+                &["operation"],
+            )
+            .expect("failed to register ccip_read_offchain_lookup_store_failures metric");
+        Self {
+            gateway_requests,
+            gateway_latency,
+            successful_gateway_position,
+            call_cache_lookups,
+            cache_entries,
+            cache_evictions,
+            circuit_state,
+            ism_misconfigurations,
+            offchain_lookup_store_failures,
+        }
+    }
+
+    /// Records that the ISM at `ism_address` didn't respond to
+    /// `getOffchainVerifyInfo` as a CCIP-read ISM should, for `reason`
+    pub fn record_ism_misconfiguration(&self, ism_address: H256, reason: &str) {
+        self.ism_misconfigurations
+            .with_label_values(&[&format!("{ism_address:?}"), reason])
+            .inc();
+    }
+
+    /// Records that metadata was found at the gateway at `position`, starting
+    /// at 1, among the URLs of a lookup
+    pub fn observe_successful_gateway_position(&self, position: usize) {
+        self.successful_gateway_position.observe(position as f64);
+    }
+
+    /// Records whether the result of calling `fn_name` was found in the cache
+    pub fn record_call_cache_lookup(&self, fn_name: &str, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.call_cache_lookups
+            .with_label_values(&[fn_name, result])
+            .inc();
+    }
+
+    pub fn cache_metrics(&self, cache: &str) -> CacheMetrics {
+        CacheMetrics {
+            entries: self.cache_entries.with_label_values(&[cache]),
+            evictions: self.cache_evictions.with_label_values(&[cache]),
+        }
+    }
+
+    pub fn circuit_state(&self) -> IntGaugeVec {
+        self.circuit_state.clone()
+    }
+
+    pub fn offchain_lookup_store_failures(&self) -> IntCounterVec {
+        self.offchain_lookup_store_failures.clone()
+    }
+
+    /// Records the outcome of querying the gateway at `host`
+    pub fn record_outcome<T>(&self, host: &str, res: &Result<T, GatewayError>) {
+        self.gateway_requests
+            .with_label_values(&[host, outcome_label(res)])
+            .inc();
+    }
+
+    /// Records how long a single attempt at querying `host` took
+    pub fn observe_latency<T>(&self, host: &str, res: &Result<T, GatewayError>, elapsed: Duration) {
+        self.gateway_latency
+            .with_label_values(&[host, outcome_label(res)])
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+/// Coarse outcome of a gateway request, as reported in metrics and logs
+pub fn outcome_label<T>(res: &Result<T, GatewayError>) -> &'static str {
+    match res {
+        Ok(_) => "success",
+        Err(GatewayError::Timeout) => "timeout",
+        Err(GatewayError::Transport(_) | GatewayError::Status(_) | GatewayError::RetryAfter(_)) => {
+            "http_error"
+        }
+        Err(GatewayError::InvalidResponse(_) | GatewayError::ResponseTooLarge(_)) => "parse_error",
+        Err(GatewayError::DataNotAvailable) => "no_data",
+        Err(GatewayError::CircuitOpen) => "circuit_open",
+        Err(GatewayError::CertificatePinMismatch) => "pin_mismatch",
+    }
+}
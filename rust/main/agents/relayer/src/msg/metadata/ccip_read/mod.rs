@@ -0,0 +1,5153 @@
+#![allow(clippy::blocks_in_conditions)] // TODO: `rustc` 1.80.1 clippy issue
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use derive_more::Deref;
+use derive_new::new;
+use ethers::{
+    abi::{self, Token},
+    utils::keccak256,
+};
+use futures::{
+    stream::{self, FuturesUnordered},
+    StreamExt,
+};
+use reqwest::{StatusCode, Url};
+use serde_json::{json, Value};
+use tokio::time::timeout;
+use tracing::{debug, field, info, instrument, warn, Span};
+
+use hyperlane_core::{
+    utils::bytes_to_hex, HyperlaneMessage, InterchainSecurityModule, ModuleType,
+    RawHyperlaneMessage, H256,
+};
+use hyperlane_ethereum::OffchainLookup;
+
+use crate::settings::{
+    ccip_read::{CcipReadConf, GatewayMethod, GatewayUrlOverride, MetadataExpiry, MetadataLength},
+    host_filter::{normalize_host, HostFilter},
+};
+
+pub use self::{
+    cache::CacheEntryInfo,
+    client::{GatewayClient, ReqwestGatewayClient},
+    clock::{Clock, SystemClock},
+    health::GatewayHealth,
+    metrics::CcipReadMetrics,
+    store::OffchainLookupStore,
+};
+
+use self::{
+    cache::{LookupKey, MetadataCache, NegativeCache, TtlCache},
+    circuit_breaker::CircuitBreaker,
+    data_uri::{decode_data_uri, is_data_uri},
+    fallback::{DirectoryMetadataSource, FallbackMetadataSource},
+    fixture::FixtureGatewayClient,
+    health::GatewayProbe,
+    metrics::outcome_label,
+    notifier::StuckMessageNotifier,
+    response::ResponseDecoder,
+    retry::{retry_with_backoff, RetryPolicy},
+    revert::parse_offchain_lookup,
+    throttle::{HostPermit, HostThrottle},
+    websocket::{is_websocket_url, WebSocketGatewayClient},
+};
+
+use super::{
+    base::{MessageMetadataBuildParams, MetadataBuildError},
+    message_builder::MessageMetadataBuilder,
+    Metadata, MetadataBuilder,
+};
+
+mod cache;
+mod circuit_breaker;
+mod client;
+mod clock;
+mod data_uri;
+mod fallback;
+mod fixture;
+mod health;
+mod metrics;
+mod notifier;
+mod pinning;
+mod response;
+mod retry;
+mod revert;
+mod store;
+mod throttle;
+mod websocket;
+
+/// A single request to an offchain gateway
+#[derive(Clone, Debug)]
+struct GatewayRequest {
+    /// The URL template from the `OffchainLookup`, safe to log
+    template: String,
+    /// The URL with `{sender}` and `{data}` interpolated
+    url: String,
+    /// JSON body to POST, or `None` to send a GET request
+    body: Option<Value>,
+    /// Lowercase host of `url`, empty if it can't be parsed
+    host: String,
+    /// Sent as `X-Request-Id`, the id of the message the request is for
+    request_id: Option<String>,
+    /// ISM whose lookup the request is for, if any
+    ism_address: Option<H256>,
+    /// Encodes the response as the callback's calldata, for ISMs verifying
+    /// that instead of the response alone
+    callback: Option<LookupCallback>,
+}
+
+/// The relayer's view of the destination chain, substituted in gateway URL
+/// templates for gateways whose attestations depend on it
+#[derive(Clone, Debug, Default)]
+struct DestinationState {
+    /// Root of the latest checkpoint of the destination's merkle tree hook,
+    /// as `{destRoot}`
+    root: Option<H256>,
+    /// Latest block number of the destination chain, as `{destBlock}`
+    block: Option<u64>,
+}
+
+/// The `callbackFunction` and `extraData` of an `OffchainLookup`
+#[derive(Clone, Debug)]
+struct LookupCallback {
+    selector: [u8; 4],
+    extra_data: Vec<u8>,
+}
+
+impl LookupCallback {
+    fn new(lookup: &OffchainLookup) -> Self {
+        Self {
+            selector: lookup.callback_function,
+            extra_data: lookup.extra_data.to_vec(),
+        }
+    }
+
+    /// The calldata of the callback for `response`, as per EIP-3668:
+    /// `callbackFunction ++ abi.encode(bytes response, bytes extraData)`
+    fn encode(&self, response: Vec<u8>) -> Vec<u8> {
+        let args = abi::encode(&[
+            Token::Bytes(response),
+            Token::Bytes(self.extra_data.clone()),
+        ]);
+        [self.selector.as_slice(), &args].concat()
+    }
+}
+
+impl GatewayRequest {
+    /// Requests are considered the same if they use the same method and
+    /// their URLs are equal once normalized, e.g. ignoring the host's case.
+    fn dedup_key(&self) -> (String, bool) {
+        let url = Url::parse(&self.url)
+            .map(|url| url.to_string())
+            .unwrap_or_else(|_| self.url.clone());
+        (url, self.body.is_some())
+    }
+
+    fn new(template: String, url: String, body: Option<Value>) -> Self {
+        let host = url_host(&url).unwrap_or_default();
+        Self {
+            template,
+            url,
+            body,
+            host,
+            request_id: None,
+            ism_address: None,
+            callback: None,
+        }
+    }
+
+    /// One request per URL template of `lookup`, in order. Per EIP-3668,
+    /// `{sender}` is substituted in every template, and a template is
+    /// requested with GET if it contains `{data}` and with POST otherwise,
+    /// unless `methods` forces a method for the URL's host. WebSocket
+    /// gateways are always sent the body. Relative
+    /// templates are resolved against `base_url`, if set.
+    /// `{domain}`, `{nonce}` and `{msgId}` are substituted with the
+    /// destination domain, nonce and id of `message`, and `{destRoot}` and
+    /// `{destBlock}` with the values known in `destination`.
+    fn for_lookup(
+        lookup: &OffchainLookup,
+        message: &HyperlaneMessage,
+        destination: &DestinationState,
+        methods: &HashMap<String, GatewayMethod>,
+        base_url: Option<&Url>,
+    ) -> Vec<Self> {
+        // Need to explicitly convert the sender H160 the hex because the `ToString` implementation
+        // for `H160` truncates the output. (e.g. `0xc66a…7b6f` instead of returning
+        // the full address)
+        let sender_as_bytes = &bytes_to_hex(lookup.sender.as_bytes());
+        // EIP-3668 expects `0x`-prefixed hex, so don't rely on the `Display`
+        // impl of the bytes type either
+        let data_as_bytes = &bytes_to_hex(&lookup.call_data);
+        let domain = &message.destination.to_string();
+        let nonce = &message.nonce.to_string();
+        let msg_id = &bytes_to_hex(message.id().as_bytes());
+        let dest_root = destination.root.map(|root| bytes_to_hex(root.as_bytes()));
+        let dest_block = destination.block.map(|block| block.to_string());
+        let mut values: Vec<(&str, &str)> = vec![
+            ("sender", sender_as_bytes),
+            ("data", data_as_bytes),
+            ("domain", domain),
+            ("nonce", nonce),
+            ("msgId", msg_id),
+        ];
+        values.extend(dest_root.as_deref().map(|root| ("destRoot", root)));
+        values.extend(dest_block.as_deref().map(|block| ("destBlock", block)));
+        lookup
+            .urls
+            .iter()
+            .map(|url| {
+                let interpolated_url = resolve_relative(interpolate(url, &values), base_url);
+                let post = is_websocket_url(&interpolated_url)
+                    || match url_host(&interpolated_url).and_then(|host| methods.get(&host)) {
+                        Some(GatewayMethod::Get) => false,
+                        Some(GatewayMethod::Post) => true,
+                        None => !url.contains("{data}"),
+                    };
+                let body = post.then(|| {
+                    json!({
+                        "sender": sender_as_bytes,
+                        "data": data_as_bytes
+                    })
+                });
+                Self {
+                    request_id: Some(msg_id.clone()),
+                    ism_address: Some(H256::from(lookup.sender)),
+                    ..Self::new(url.clone(), interpolated_url, body)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Substitutes the `{name}` placeholders of `template` with their value in
+/// `values`. Unknown placeholders are left untouched.
+fn interpolate(template: &str, values: &[(&str, &str)]) -> String {
+    let mut interpolated = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(end) = rest.find('}') {
+        let Some(start) = rest[..end].rfind('{') else {
+            interpolated.push_str(&rest[..=end]);
+            rest = &rest[end + 1..];
+            continue;
+        };
+        interpolated.push_str(&rest[..start]);
+        let placeholder = &rest[start..=end];
+        let name = &placeholder[1..placeholder.len() - 1];
+        match values.iter().find(|(key, _)| *key == name) {
+            Some((_, value)) => interpolated.push_str(value),
+            None => {
+                debug!(
+                    placeholder,
+                    "Leaving unknown placeholder in CCIP-read URL template untouched"
+                );
+                interpolated.push_str(placeholder);
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    interpolated.push_str(rest);
+    interpolated
+}
+
+/// Resolves `url` against `base_url` if it is relative. Absolute URLs, and
+/// relative ones without a base URL, are returned as is.
+fn resolve_relative(url: String, base_url: Option<&Url>) -> String {
+    match (Url::parse(&url), base_url) {
+        (Err(url::ParseError::RelativeUrlWithoutBase), Some(base_url)) => {
+            base_url.join(&url).map(String::from).unwrap_or(url)
+        }
+        _ => url,
+    }
+}
+
+/// The host of `url` as normalized by `normalize_host`, e.g. IPv6 literals
+/// without brackets, which unlike the full URL is safe to log
+fn url_host(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(normalize_host)
+}
+
+/// Why a single gateway did not yield usable metadata
+#[derive(Debug, thiserror::Error)]
+enum CandidateFailure {
+    #[error(transparent)]
+    Gateway(#[from] GatewayError),
+    #[error("Metadata failed verification")]
+    FailedVerification,
+    #[error("Metadata is {len} bytes, but the ISM expects {expected}")]
+    UnexpectedLength {
+        len: usize,
+        expected: MetadataLength,
+    },
+    #[error("Metadata hash {actual:?} doesn't match the expected {expected:?}")]
+    HashMismatch { actual: H256, expected: H256 },
+    #[error("Metadata is too short to hold its expiry")]
+    MissingExpiry,
+    #[error("Metadata expired at {expires_at}")]
+    Expired { expires_at: u64 },
+    #[error("Metadata was returned by {agreeing} gateways, but {quorum} must agree")]
+    NoQuorum { agreeing: usize, quorum: usize },
+}
+
+/// Why each queried gateway did not yield metadata, in the order they
+/// failed, so a stuck message can be triaged from a single log line
+#[derive(Debug, Default)]
+struct GatewayFailures(Vec<(String, CandidateFailure)>);
+
+impl Display for GatewayFailures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "No gateways were queried");
+        }
+        for (i, (url, failure)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{url}: {failure}")?;
+        }
+        Ok(())
+    }
+}
+
+impl GatewayFailures {
+    /// Whether every queried gateway appears to be down, rather than having
+    /// rejected the lookup or returned unusable metadata
+    fn are_outages(&self) -> bool {
+        !self.0.is_empty()
+            && self.0.iter().all(|(_, failure)| {
+                matches!(
+                    failure,
+                    CandidateFailure::Gateway(err)
+                        if err.is_outage() || matches!(err, GatewayError::CircuitOpen)
+                )
+            })
+    }
+}
+
+/// Reasons a request to an offchain gateway did not yield metadata
+#[derive(Debug, thiserror::Error)]
+pub enum GatewayError {
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Request failed: {0}")]
+    Transport(String),
+    #[error("Gateway responded with status {0}")]
+    Status(StatusCode),
+    #[error("Invalid gateway response: {0}")]
+    InvalidResponse(String),
+    /// The response has the data field, but it's empty, which gateways
+    /// use while the metadata isn't available yet
+    #[error("Gateway has no data for the lookup yet")]
+    DataNotAvailable,
+    #[error("Response body exceeds the limit of {0} bytes")]
+    ResponseTooLarge(usize),
+    #[error("Gateway is rate limiting requests for another {0:?}")]
+    RetryAfter(Duration),
+    #[error("Gateway host keeps failing, its circuit is open")]
+    CircuitOpen,
+    #[error("Gateway certificate doesn't match the pinned certificate of its host")]
+    CertificatePinMismatch,
+}
+
+impl GatewayError {
+    /// Whether retrying the same request may succeed.
+    /// Timeouts are not retried since they already took the full timeout to
+    /// surface; the next URL is tried instead. Neither are rate limited
+    /// requests with a `Retry-After`, whose host is avoided until then.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Transport(_) | Self::DataNotAvailable => true,
+            Self::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            Self::Timeout
+            | Self::InvalidResponse(_)
+            | Self::ResponseTooLarge(_)
+            | Self::RetryAfter(_)
+            | Self::CircuitOpen
+            | Self::CertificatePinMismatch => false,
+        }
+    }
+
+    /// Whether the gateway appears to be down, rather than rejecting this
+    /// particular request
+    pub fn is_outage(&self) -> bool {
+        match self {
+            Self::Timeout | Self::Transport(_) => true,
+            Self::Status(status) => status.is_server_error(),
+            Self::InvalidResponse(_)
+            | Self::DataNotAvailable
+            | Self::ResponseTooLarge(_)
+            | Self::RetryAfter(_)
+            | Self::CircuitOpen
+            | Self::CertificatePinMismatch => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for GatewayError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Self::Timeout
+        } else if err.is_decode() {
+            Self::InvalidResponse(err.to_string())
+        } else {
+            Self::Transport(err.to_string())
+        }
+    }
+}
+
+/// Shared state used by every `CcipReadIsmMetadataBuilder`.
+/// A single instance is created when the relayer starts so that connections
+/// to offchain gateways are pooled and kept alive across lookups.
+#[derive(Clone, Debug)]
+pub struct CcipReadContext {
+    gateway_client: Arc<dyn GatewayClient>,
+    /// Sends requests to `ws://` and `wss://` URLs, if enabled
+    websocket_client: Option<WebSocketGatewayClient>,
+    gateway_timeout: Duration,
+    retry_policy: RetryPolicy,
+    concurrent_gateways: bool,
+    gateway_quorum: usize,
+    response_decoder: ResponseDecoder,
+    gateway_hosts: HostFilter,
+    gateway_methods: HashMap<String, GatewayMethod>,
+    gateway_base_url: Option<Url>,
+    /// Whether `{destRoot}` and `{destBlock}` are substituted in URL templates
+    include_destination_state: bool,
+    /// Shared by all lookups, so limits hold across messages
+    throttle: Arc<HostThrottle>,
+    /// Read by every gateway request in flight, so shutdown can wait for
+    /// them by taking the write lock
+    in_flight_requests: Arc<tokio::sync::RwLock<()>>,
+    circuit_breaker: Arc<CircuitBreaker>,
+    /// Lookups that recently failed on every gateway
+    negative_cache: Arc<NegativeCache>,
+    /// `OffchainLookup`s returned by `getOffchainVerifyInfo`
+    offchain_lookups: Arc<TtlCache<LookupKey, OffchainLookup>>,
+    /// Module types of the ISMs lookups were started for, by destination
+    /// domain and ISM address
+    module_types: Arc<TtlCache<(u32, H256), ModuleType>>,
+    /// Persisted copy of `offchain_lookups`, if enabled
+    offchain_lookup_store: Option<OffchainLookupStore>,
+    /// Metadata recently returned by a gateway
+    metadata_cache: Arc<MetadataCache>,
+    /// Consulted when no gateway returned usable metadata
+    fallback_source: Option<Arc<dyn FallbackMetadataSource>>,
+    /// Reports messages whose lookups keep failing, if a webhook is set
+    stuck_notifier: Option<Arc<StuckMessageNotifier>>,
+    metrics: CcipReadMetrics,
+    verify_metadata: bool,
+    ism_metadata_lengths: HashMap<H256, MetadataLength>,
+    ism_metadata_expiry: HashMap<H256, MetadataExpiry>,
+    ism_metadata_hashes: HashMap<H256, H256>,
+    /// Bounds on how long metadata is cached for based on its expiry
+    metadata_cache_min_ttl: Duration,
+    metadata_cache_max_ttl: Duration,
+    message_independent_isms: HashSet<H256>,
+    callback_encoded_isms: HashSet<H256>,
+    ism_gateway_urls: HashMap<H256, GatewayUrlOverride>,
+    ism_gateway_priorities: HashMap<H256, Vec<String>>,
+    rotate_gateways: bool,
+    max_gateway_urls: usize,
+    ipfs_gateway: String,
+    max_response_pages: usize,
+    /// Limit on the size of metadata assembled from several pages
+    max_response_bytes: usize,
+    batch_build_concurrency: usize,
+    /// Times caches, back-offs and metadata expiry
+    clock: Arc<dyn Clock>,
+}
+
+impl CcipReadContext {
+    /// Gateway responses are replayed from fixtures instead if a fixture
+    /// directory is configured
+    pub fn new(conf: &CcipReadConf, metrics: CcipReadMetrics) -> eyre::Result<Self> {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let gateway_client: Arc<dyn GatewayClient> =
+            Arc::new(ReqwestGatewayClient::new(conf, clock.clone())?);
+        let gateway_client = match &conf.replay_fixture_dir {
+            Some(dir) => {
+                let live = conf.replay_live_fallback.then_some(gateway_client);
+                Arc::new(FixtureGatewayClient::new(dir.clone(), live))
+            }
+            None => gateway_client,
+        };
+        Ok(Self::with_gateway_client_and_clock(
+            gateway_client,
+            conf,
+            metrics,
+            clock,
+        ))
+    }
+
+    /// Sends all gateway requests through `gateway_client`, e.g. a mock in tests
+    #[cfg(test)]
+    pub fn with_gateway_client(
+        gateway_client: Arc<dyn GatewayClient>,
+        conf: &CcipReadConf,
+        metrics: CcipReadMetrics,
+    ) -> Self {
+        Self::with_gateway_client_and_clock(gateway_client, conf, metrics, Arc::new(SystemClock))
+    }
+
+    /// Sends all gateway requests through `gateway_client`, and times caches,
+    /// back-offs and expiries by `clock`
+    pub fn with_gateway_client_and_clock(
+        gateway_client: Arc<dyn GatewayClient>,
+        conf: &CcipReadConf,
+        metrics: CcipReadMetrics,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let stuck_notifier = conf.stuck_message_webhook.clone().map(|webhook| {
+            Arc::new(StuckMessageNotifier::new(
+                webhook,
+                conf.stuck_message_failures,
+                conf.gateway_timeout,
+                conf.max_cache_entries,
+                clock.clone(),
+            ))
+        });
+        Self {
+            gateway_client,
+            websocket_client: conf
+                .websocket_gateways
+                .then(|| WebSocketGatewayClient::new(conf, clock.clone())),
+            gateway_timeout: conf.gateway_timeout,
+            retry_policy: RetryPolicy::new(conf.max_attempts, conf.retry_base_delay),
+            concurrent_gateways: conf.concurrent_gateways,
+            gateway_quorum: conf.gateway_quorum,
+            response_decoder: conf.response_decoders.iter().fold(
+                ResponseDecoder::new(conf.response_format, conf.response_data_pointer.clone()),
+                |decoder, (scope, encoding)| {
+                    decoder.with_data_decoder(scope.clone(), (*encoding).into())
+                },
+            ),
+            gateway_hosts: conf.gateway_hosts.clone(),
+            gateway_methods: conf.gateway_methods.clone(),
+            gateway_base_url: conf.gateway_base_url.clone(),
+            include_destination_state: conf.include_destination_state,
+            throttle: Arc::new(
+                HostThrottle::new(
+                    conf.max_in_flight_per_host,
+                    conf.max_requests_per_second_per_host,
+                )
+                .with_max_in_flight_total(conf.max_in_flight_total)
+                .with_clock(clock.clone()),
+            ),
+            in_flight_requests: Default::default(),
+            circuit_breaker: Arc::new(
+                CircuitBreaker::new(
+                    conf.circuit_breaker_failures,
+                    conf.circuit_breaker_window,
+                    conf.circuit_breaker_cooldown,
+                )
+                .with_metrics(metrics.circuit_state())
+                .with_clock(clock.clone()),
+            ),
+            negative_cache: Arc::new(
+                NegativeCache::new(conf.negative_cache_ttl, conf.max_cache_entries)
+                    .with_ttl_jitter(conf.cache_ttl_jitter)
+                    .with_clock(clock.clone())
+                    .with_metrics(metrics.cache_metrics("negative")),
+            ),
+            offchain_lookups: Arc::new(
+                TtlCache::new(conf.offchain_lookup_cache_ttl, conf.max_cache_entries)
+                    .with_ttl_jitter(conf.cache_ttl_jitter)
+                    .with_metrics(metrics.cache_metrics("offchain_lookups"))
+                    .with_clock(clock.clone()),
+            ),
+            module_types: Arc::new(
+                TtlCache::new(conf.offchain_lookup_cache_ttl, conf.max_cache_entries)
+                    .with_ttl_jitter(conf.cache_ttl_jitter)
+                    .with_metrics(metrics.cache_metrics("module_types"))
+                    .with_clock(clock.clone()),
+            ),
+            offchain_lookup_store: None,
+            metadata_cache: Arc::new(
+                MetadataCache::new(conf.metadata_cache_ttl, conf.max_cache_entries)
+                    .with_ttl_jitter(conf.cache_ttl_jitter)
+                    .with_stale_grace(conf.metadata_stale_grace)
+                    .with_clock(clock.clone())
+                    .with_metrics(metrics.cache_metrics("metadata")),
+            ),
+            fallback_source: conf.fallback_metadata_dir.clone().map(|dir| {
+                Arc::new(DirectoryMetadataSource::new(dir)) as Arc<dyn FallbackMetadataSource>
+            }),
+            stuck_notifier,
+            metrics,
+            verify_metadata: conf.verify_metadata,
+            ism_metadata_lengths: conf.ism_metadata_lengths.clone(),
+            ism_metadata_expiry: conf.ism_metadata_expiry.clone(),
+            ism_metadata_hashes: conf.ism_metadata_hashes.clone(),
+            metadata_cache_min_ttl: conf.metadata_cache_min_ttl,
+            metadata_cache_max_ttl: conf.metadata_cache_max_ttl,
+            message_independent_isms: conf.message_independent_isms.clone(),
+            callback_encoded_isms: conf.callback_encoded_isms.clone(),
+            ism_gateway_urls: conf.ism_gateway_urls.clone(),
+            ism_gateway_priorities: conf.ism_gateway_priorities.clone(),
+            rotate_gateways: conf.rotate_gateways,
+            max_gateway_urls: conf.max_gateway_urls,
+            ipfs_gateway: conf.ipfs_gateway.clone(),
+            max_response_pages: conf.max_response_pages,
+            max_response_bytes: conf.max_response_bytes,
+            batch_build_concurrency: conf.batch_build_concurrency,
+            clock,
+        }
+    }
+
+    /// Also persists `OffchainLookup`s in `store`, so they survive restarts
+    pub fn with_offchain_lookup_store(self, store: OffchainLookupStore) -> Self {
+        let store = store
+            .with_failure_metric(self.metrics.offchain_lookup_store_failures())
+            .with_clock(self.clock.clone());
+        Self {
+            offchain_lookup_store: Some(store),
+            ..self
+        }
+    }
+
+    /// Waits, for at most the gateway timeout, for gateway requests in
+    /// flight to finish, then for persisted `OffchainLookup`s to be written,
+    /// so shutting down neither cuts requests off nor loses the warm cache
+    pub async fn shutdown(&self) {
+        if timeout(self.gateway_timeout, self.in_flight_requests.write())
+            .await
+            .is_err()
+        {
+            warn!("Gave up waiting for CCIP-read gateway requests in flight to finish");
+        }
+        if let Some(store) = &self.offchain_lookup_store {
+            store.flush().await;
+        }
+        info!("Shut down CCIP-read context");
+    }
+
+    /// Drops cached `OffchainLookup`s so they are fetched from the ISM again,
+    /// either for a single ISM or all of them. Metadata fetched for them and
+    /// the module types of their ISMs are dropped as well. Returns how many
+    /// lookups were dropped from memory.
+    pub async fn invalidate_offchain_lookups(&self, ism_address: Option<H256>) -> usize {
+        if let Some(store) = &self.offchain_lookup_store {
+            store.invalidate(ism_address);
+        }
+        let matches = |key: &LookupKey| ism_address.map_or(true, |ism| key.ism_address == ism);
+        self.metadata_cache.remove_matching(matches).await;
+        self.module_types
+            .remove_matching(|(_, ism)| ism_address.map_or(true, |address| *ism == address))
+            .await;
+        self.offchain_lookups.remove_matching(matches).await
+    }
+
+    /// Drops everything cached for the message with `message_id`, including
+    /// a recent failure to find metadata, so the message's next build calls
+    /// its ISM and queries gateways again. Persisted lookups of the message
+    /// are invalidated too. Returns how many unexpired entries were dropped
+    /// from memory.
+    pub async fn invalidate_message(&self, message_id: H256) -> usize {
+        if let Some(store) = &self.offchain_lookup_store {
+            store.invalidate_message(message_id);
+        }
+        let matches = |key: &LookupKey| key.message_id == message_id;
+        self.negative_cache.remove_matching(matches).await
+            + self.metadata_cache.remove_matching(matches).await
+            + self.offchain_lookups.remove_matching(matches).await
+    }
+
+    /// Summarizes the entries of the lookup, metadata and negative caches,
+    /// to diagnose what is cached. Lookups' gateway URLs are only counted and
+    /// metadata is only measured.
+    pub async fn dump_cache(&self) -> Vec<CacheEntryInfo> {
+        let mut entries = vec![];
+        for (key, lookup, expires_in) in self.offchain_lookups.snapshot().await {
+            entries.push(CacheEntryInfo {
+                gateway_urls: Some(lookup.urls.len()),
+                ..CacheEntryInfo::new("offchain_lookups", &key, expires_in)
+            });
+        }
+        for (key, (metadata, host), expires_in) in self.metadata_cache.snapshot().await {
+            entries.push(CacheEntryInfo {
+                metadata_len: Some(metadata.len()),
+                gateway_host: Some(host),
+                ..CacheEntryInfo::new("metadata", &key, expires_in)
+            });
+        }
+        for (key, (), expires_in) in self.negative_cache.snapshot().await {
+            entries.push(CacheEntryInfo::new("negative", &key, expires_in));
+        }
+        entries
+    }
+
+    /// Records the outcome of a lookup that queried gateways for stuck
+    /// message reports. `failure` is why no metadata was found, if it wasn't.
+    async fn record_lookup_outcome(
+        &self,
+        ism_address: H256,
+        message: &HyperlaneMessage,
+        failure: Option<String>,
+    ) {
+        let Some(notifier) = &self.stuck_notifier else {
+            return;
+        };
+        match failure {
+            Some(reason) => {
+                notifier
+                    .record_failure(message.id(), ism_address, reason)
+                    .await
+            }
+            None => notifier.record_success(message.id()).await,
+        }
+    }
+
+    /// Metadata for `message` from the fallback source, if one is configured
+    /// and has metadata for it that passes `verifier`
+    async fn fetch_fallback(
+        &self,
+        ism_address: H256,
+        message: &HyperlaneMessage,
+        verifier: Option<&MetadataVerifier<'_>>,
+    ) -> Option<Vec<u8>> {
+        let metadata = self
+            .fallback_source
+            .as_ref()?
+            .metadata(ism_address, message)
+            .await?;
+        if let Some(verifier) = verifier {
+            if let Err(failure) = verifier.check(&metadata).await {
+                warn!(%failure, "Skipping unusable fallback CCIP-read metadata");
+                return None;
+            }
+        }
+        Some(metadata)
+    }
+
+    /// Applies the gateway URLs the operator configured for the ISM at
+    /// `ism_address`, if any, to the URLs it listed onchain
+    fn override_gateway_urls(&self, ism_address: H256, mut info: OffchainLookup) -> OffchainLookup {
+        let Some(url_override) = self.ism_gateway_urls.get(&ism_address) else {
+            return info;
+        };
+        warn!(
+            ?ism_address,
+            onchain_urls = ?info.urls,
+            override_urls = ?url_override.urls,
+            append = url_override.append,
+            "Overriding CCIP-read gateway URLs of ISM with configured ones"
+        );
+        if !url_override.append {
+            info.urls.clear();
+        }
+        info.urls.extend(url_override.urls.iter().cloned());
+        info
+    }
+
+    /// Moves the URLs at the hosts the operator prioritized for the ISM at
+    /// `ism_address` to the front, in the configured order. Other URLs keep
+    /// their relative order.
+    fn prioritize_gateway_urls(
+        &self,
+        ism_address: H256,
+        mut info: OffchainLookup,
+    ) -> OffchainLookup {
+        let Some(hosts) = self.ism_gateway_priorities.get(&ism_address) else {
+            return info;
+        };
+        // Stable, so URLs at the same host stay in order
+        info.urls.sort_by_key(|url| {
+            url_host(url)
+                .and_then(|host| hosts.iter().position(|prioritized| *prioritized == host))
+                .unwrap_or(hosts.len())
+        });
+        debug!(?ism_address, urls = ?info.urls, "Reordered CCIP-read gateway URLs by configured priority");
+        info
+    }
+
+    /// Starts `requests` at one picked by `message_id` if gateway rotation
+    /// is enabled and the ISM has no gateway priorities, wrapping around so
+    /// every request is still made
+    fn rotate_requests(
+        &self,
+        ism_address: H256,
+        message_id: H256,
+        mut requests: Vec<GatewayRequest>,
+    ) -> Vec<GatewayRequest> {
+        if !self.rotate_gateways
+            || requests.is_empty()
+            || self.ism_gateway_priorities.contains_key(&ism_address)
+        {
+            return requests;
+        }
+        // Message ids are hashes already, so any of their bytes are uniform
+        let start = (message_id.to_low_u64_be() % requests.len() as u64) as usize;
+        requests.rotate_left(start);
+        requests
+    }
+
+    /// Key the `OffchainLookup` for `lookup_key` is cached under, which
+    /// leaves out the message for ISMs configured as message-independent
+    fn offchain_lookup_key(&self, lookup_key: &LookupKey) -> LookupKey {
+        if self
+            .message_independent_isms
+            .contains(&lookup_key.ism_address)
+        {
+            LookupKey {
+                message_id: H256::zero(),
+                ..lookup_key.clone()
+            }
+        } else {
+            lookup_key.clone()
+        }
+    }
+
+    /// Checks whether the gateways behind `urls`, e.g. those listed by a
+    /// configured ISM, respond, without performing a lookup. Placeholders are
+    /// left in the probed URLs, since most gateways respond even to paths
+    /// they don't serve.
+    pub async fn probe_gateways(&self, urls: &[String]) -> GatewayHealth {
+        let probes = urls.iter().map(|url| async move {
+            let started = Instant::now();
+            let res = self.probe_gateway(url).await;
+            GatewayProbe::new(url.clone(), started, res)
+        });
+        GatewayHealth {
+            gateways: futures::future::join_all(probes).await,
+        }
+    }
+
+    /// The status the gateway at `url` responded with, or `None` for URLs
+    /// served without a request
+    async fn probe_gateway(&self, url: &str) -> Result<Option<u16>, String> {
+        let request = GatewayRequest::new(url.to_owned(), url.to_owned(), None);
+        let Some(request) = self.resolve_ipfs(request) else {
+            return Err("IPFS URL without a CID".to_owned());
+        };
+        if is_data_uri(&request.url) {
+            return Ok(None);
+        }
+        if !self.permits(&request) {
+            return Err("Host is not allowed".to_owned());
+        }
+        self.client_for(&request.url)
+            .probe(&request.url)
+            .await
+            .map(|status| Some(status.as_u16()))
+            .map_err(|err| err.to_string())
+    }
+
+    /// Resolves `ipfs://` URLs and drops duplicate and disallowed requests,
+    /// keeping at most the configured number of gateway URLs
+    fn select_requests(&self, requests: Vec<GatewayRequest>) -> Vec<GatewayRequest> {
+        let mut seen = HashSet::new();
+        let mut selected = Vec::new();
+        for request in requests
+            .into_iter()
+            .filter_map(|req| self.resolve_ipfs(req))
+        {
+            if !seen.insert(request.dedup_key()) || !self.permits(&request) {
+                continue;
+            }
+            if selected.len() == self.max_gateway_urls {
+                warn!(
+                    max_gateway_urls = self.max_gateway_urls,
+                    "ISM lists more CCIP-read gateway URLs than allowed, ignoring the rest"
+                );
+                break;
+            }
+            selected.push(request);
+        }
+        selected
+    }
+
+    /// Rewrites an `ipfs://<cid>/<path>` request to fetch `<cid>/<path>` from
+    /// the configured IPFS HTTP gateway. Other requests are returned as is.
+    fn resolve_ipfs(&self, request: GatewayRequest) -> Option<GatewayRequest> {
+        let Some(content_path) = request.url.strip_prefix("ipfs://") else {
+            return Some(request);
+        };
+        if content_path.is_empty() {
+            warn!(url = %request.template, "Skipping CCIP-read IPFS URL without a CID");
+            return None;
+        }
+        let url = format!("{}{content_path}", self.ipfs_gateway);
+        // Content is fetched by its address, so there is nothing to POST
+        Some(GatewayRequest {
+            request_id: request.request_id,
+            ism_address: request.ism_address,
+            ..GatewayRequest::new(request.template, url, None)
+        })
+    }
+
+    /// Whether `request` may be sent according to the configured host
+    /// allowlist and denylist. Rejections are logged since they may indicate
+    /// an ISM trying to reach internal services.
+    fn permits(&self, request: &GatewayRequest) -> bool {
+        // Served inline, without any network request
+        if is_data_uri(&request.url) {
+            return true;
+        }
+        let permitted = self.gateway_hosts.permits(&request.url);
+        if !permitted {
+            warn!(url = %request.template, "Refusing to query CCIP-read gateway at a disallowed host");
+        }
+        permitted
+    }
+
+    /// Fetches metadata from the first gateway that returns it, either by
+    /// querying them in order or all at once depending on configuration, along
+    /// with that gateway's host. If a `verifier` is given, metadata that fails
+    /// its checks is skipped.
+    async fn fetch_from_gateways(
+        &self,
+        requests: &[GatewayRequest],
+        verifier: Option<&MetadataVerifier<'_>>,
+    ) -> Result<(Vec<u8>, String), GatewayFailures> {
+        if self.gateway_quorum > 1 {
+            self.fetch_with_quorum(requests, verifier).await
+        } else if self.concurrent_gateways {
+            self.fetch_concurrently(requests, verifier).await
+        } else {
+            self.fetch_sequentially(requests, verifier).await
+        }
+    }
+
+    async fn fetch_sequentially(
+        &self,
+        requests: &[GatewayRequest],
+        verifier: Option<&MetadataVerifier<'_>>,
+    ) -> Result<(Vec<u8>, String), GatewayFailures> {
+        let mut failures = GatewayFailures::default();
+        for (position, request) in requests.iter().enumerate() {
+            match self.fetch_candidate(request, verifier).await {
+                Ok(metadata) => return Ok(self.record_gateway(position, request, metadata)),
+                Err(failure) => failures.0.push((request.template.clone(), failure)),
+            }
+        }
+        Err(failures)
+    }
+
+    /// Queries gateways in order until `gateway_quorum` of them returned the
+    /// same metadata. Gateways disagreeing are logged, since one of them may
+    /// have been compromised.
+    async fn fetch_with_quorum(
+        &self,
+        requests: &[GatewayRequest],
+        verifier: Option<&MetadataVerifier<'_>>,
+    ) -> Result<(Vec<u8>, String), GatewayFailures> {
+        let mut failures = GatewayFailures::default();
+        // Each distinct metadata returned, with the URL templates of the
+        // gateways that returned it
+        let mut candidates: Vec<(Vec<u8>, Vec<String>)> = Vec::new();
+        for (position, request) in requests.iter().enumerate() {
+            let metadata = match self.fetch_candidate(request, verifier).await {
+                Ok(metadata) => metadata,
+                Err(failure) => {
+                    failures.0.push((request.template.clone(), failure));
+                    continue;
+                }
+            };
+            let index = match candidates.iter().position(|(other, _)| *other == metadata) {
+                Some(index) => index,
+                None => {
+                    if !candidates.is_empty() {
+                        let others: Vec<_> = candidates.iter().flat_map(|(_, urls)| urls).collect();
+                        warn!(
+                            url = %request.template,
+                            ?others,
+                            "CCIP-read gateway returned different metadata than other gateways, which may be an integrity issue"
+                        );
+                    }
+                    candidates.push((metadata, Vec::new()));
+                    candidates.len() - 1
+                }
+            };
+            let (metadata, agreeing) = &mut candidates[index];
+            agreeing.push(request.template.clone());
+            if agreeing.len() >= self.gateway_quorum {
+                return Ok(self.record_gateway(position, request, std::mem::take(metadata)));
+            }
+        }
+        for (_, agreeing) in candidates {
+            let failure = || CandidateFailure::NoQuorum {
+                agreeing: agreeing.len(),
+                quorum: self.gateway_quorum,
+            };
+            failures
+                .0
+                .extend(agreeing.iter().map(|url| (url.clone(), failure())));
+        }
+        Err(failures)
+    }
+
+    /// Requests still in flight once metadata is found are cancelled by
+    /// dropping their futures.
+    async fn fetch_concurrently(
+        &self,
+        requests: &[GatewayRequest],
+        verifier: Option<&MetadataVerifier<'_>>,
+    ) -> Result<(Vec<u8>, String), GatewayFailures> {
+        let mut in_flight: FuturesUnordered<_> = requests
+            .iter()
+            .enumerate()
+            .map(|(position, request)| async move {
+                (
+                    position,
+                    request,
+                    self.fetch_candidate(request, verifier).await,
+                )
+            })
+            .collect();
+        let mut failures = GatewayFailures::default();
+        while let Some((position, request, res)) = in_flight.next().await {
+            match res {
+                Ok(metadata) => return Ok(self.record_gateway(position, request, metadata)),
+                Err(failure) => failures.0.push((request.template.clone(), failure)),
+            }
+        }
+        Err(failures)
+    }
+
+    /// Fetches metadata from a single gateway, logging why if none is returned.
+    /// Only the URL template is recorded, never the interpolated URL or the
+    /// headers, which may carry credentials.
+    #[instrument(skip_all, fields(url = %request.template))]
+    async fn fetch_candidate(
+        &self,
+        request: &GatewayRequest,
+        verifier: Option<&MetadataVerifier<'_>>,
+    ) -> Result<Vec<u8>, CandidateFailure> {
+        let metadata = self.fetch_with_retries(request).await.map_err(|err| {
+            self.log_failure(request, &err);
+            err
+        })?;
+        if let Some(verifier) = verifier {
+            verifier.check(&metadata).await.map_err(|failure| {
+                info!(url = %request.template, %failure, "CCIP-read gateway returned unusable metadata");
+                failure
+            })?;
+        }
+        Ok(metadata)
+    }
+
+    /// Records the URL template of the gateway that returned metadata on the
+    /// `build` span, and its zero-based `position` among the lookup's URLs
+    fn record_gateway(
+        &self,
+        position: usize,
+        request: &GatewayRequest,
+        metadata: Vec<u8>,
+    ) -> (Vec<u8>, String) {
+        Span::current().record("gateway", field::display(&request.template));
+        self.metrics
+            .observe_successful_gateway_position(position + 1);
+        (metadata, request.host.clone())
+    }
+
+    fn log_failure(&self, request: &GatewayRequest, err: &GatewayError) {
+        match err {
+            GatewayError::Timeout => {
+                warn!(url = %request.template, timeout = ?self.gateway_timeout, "CCIP-read gateway request timed out")
+            }
+            GatewayError::ResponseTooLarge(limit) => {
+                warn!(url = %request.template, limit, "CCIP-read gateway response exceeded the size limit")
+            }
+            GatewayError::InvalidResponse(reason) => {
+                warn!(url = %request.template, %reason, "CCIP-read gateway response is malformed, skipping it")
+            }
+            GatewayError::DataNotAvailable => {
+                info!(url = %request.template, "CCIP-read gateway has no data for the lookup yet")
+            }
+            _ => info!(url = %request.template, ?err, "CCIP-read gateway request failed"),
+        }
+    }
+
+    /// Sends `request`, retrying transient failures according to the retry policy
+    async fn fetch_with_retries(&self, request: &GatewayRequest) -> Result<Vec<u8>, GatewayError> {
+        let res = retry_with_backoff(&self.retry_policy, || self.timed_fetch(request)).await;
+        self.metrics.record_outcome(&request.host, &res);
+        res
+    }
+
+    /// Like `fetch`, once the host's limits allow it, recording the latency
+    /// of the attempt
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(host = %request.host, latency_ms = field::Empty, decode_ms = field::Empty)
+    )]
+    async fn timed_fetch(&self, request: &GatewayRequest) -> Result<Vec<u8>, GatewayError> {
+        let _permit = self.wait_for_host(request).await?;
+        // Only missing while shutdown is waiting, which then doesn't wait
+        // for this request
+        let _in_flight = self.in_flight_requests.try_read().ok();
+        let start = Instant::now();
+        let res = self.fetch(request).await;
+        let latency = start.elapsed();
+        if !is_data_uri(&request.url) {
+            self.record_host_outcome(request, &res);
+        }
+        self.metrics.observe_latency(&request.host, &res, latency);
+        let latency_ms = latency.as_millis() as u64;
+        Span::current().record("latency_ms", latency_ms);
+        let status = match &res {
+            Err(GatewayError::Status(status)) => Some(status.as_u16()),
+            _ => None,
+        };
+        // Fields rather than interpolation, so log pipelines can aggregate
+        // attempts without parsing messages
+        info!(
+            message_id = request.request_id.as_deref(),
+            ism = request.ism_address.map(field::debug),
+            url_host = %request.host,
+            outcome = outcome_label(&res),
+            status,
+            latency_ms,
+            "CCIP-read gateway request attempt finished"
+        );
+        res
+    }
+
+    /// Backs off from hosts that asked for it, and feeds the host's circuit
+    /// breaker
+    fn record_host_outcome<T>(&self, request: &GatewayRequest, res: &Result<T, GatewayError>) {
+        match res {
+            Err(GatewayError::RetryAfter(retry_after)) => {
+                warn!(host = %request.host, ?retry_after, "CCIP-read gateway is rate limiting requests, backing off");
+                self.throttle.back_off(&request.host, *retry_after);
+            }
+            Err(err) if err.is_outage() => self.circuit_breaker.record(&request.host, false),
+            _ => self.circuit_breaker.record(&request.host, true),
+        }
+    }
+
+    /// Waits for the per-host limits to allow sending `request`, for at most
+    /// the gateway timeout. Hosts that are being backed off from or whose
+    /// circuit is open are skipped.
+    async fn wait_for_host(&self, request: &GatewayRequest) -> Result<HostPermit, GatewayError> {
+        if is_data_uri(&request.url) {
+            return Ok(HostPermit::default());
+        }
+        if let Some(remaining) = self.throttle.backing_off(&request.host) {
+            debug!(host = %request.host, ?remaining, "Skipping rate limited CCIP-read gateway host");
+            return Err(GatewayError::RetryAfter(remaining));
+        }
+        let permit = timeout(self.gateway_timeout, self.throttle.acquire(&request.host))
+            .await
+            .map_err(|_| {
+                debug!(host = %request.host, "Timed out waiting for the CCIP-read gateway host limit");
+                GatewayError::Timeout
+            })?;
+        // Checked last, so a half-open circuit's probe is actually sent
+        if !self.circuit_breaker.allows(&request.host) {
+            debug!(host = %request.host, "Skipping CCIP-read gateway host with an open circuit");
+            return Err(GatewayError::CircuitOpen);
+        }
+        Ok(permit)
+    }
+
+    /// The client to send requests to `url` with, which is the WebSocket
+    /// client for `ws://` and `wss://` URLs if WebSocket gateways are enabled
+    fn client_for(&self, url: &str) -> &dyn GatewayClient {
+        match &self.websocket_client {
+            Some(websocket_client) if is_websocket_url(url) => websocket_client,
+            _ => self.gateway_client.as_ref(),
+        }
+    }
+
+    /// Sends `request` once and decodes the metadata out of the response.
+    /// The response to a `data:` URI is the URI's own payload. The metadata
+    /// is encoded as the callback's calldata if the request has one.
+    async fn fetch(&self, request: &GatewayRequest) -> Result<Vec<u8>, GatewayError> {
+        let body = if is_data_uri(&request.url) {
+            decode_data_uri(&request.url)?
+        } else {
+            self.client_for(&request.url)
+                .fetch(
+                    &request.url,
+                    request.body.as_ref(),
+                    request.request_id.as_deref(),
+                )
+                .await?
+        };
+        let decode_start = Instant::now();
+        let mut metadata =
+            self.response_decoder
+                .decode(&body, request.ism_address, &request.host)?;
+        Span::current().record("decode_ms", decode_start.elapsed().as_millis() as u64);
+        if self.max_response_pages > 1 {
+            if let Some(next) = self.response_decoder.next_page(&body) {
+                self.fetch_next_pages(request, next, &mut metadata).await?;
+            }
+        }
+        if let Some(callback) = &request.callback {
+            metadata = callback.encode(metadata);
+        }
+        Ok(metadata)
+    }
+
+    /// Follows the `next` links of a paginated response, starting at `next`,
+    /// appending each page's data to `metadata`. Pages are fetched with GET,
+    /// and a page linking back to one already fetched is rejected.
+    async fn fetch_next_pages(
+        &self,
+        request: &GatewayRequest,
+        mut next: String,
+        metadata: &mut Vec<u8>,
+    ) -> Result<(), GatewayError> {
+        let invalid = GatewayError::InvalidResponse;
+        let mut page_url =
+            Url::parse(&request.url).map_err(|err| invalid(format!("Invalid URL: {err}")))?;
+        let mut fetched = HashSet::from([page_url.to_string()]);
+        loop {
+            if fetched.len() == self.max_response_pages {
+                return Err(invalid(format!(
+                    "Response spans more than {} pages",
+                    self.max_response_pages
+                )));
+            }
+            page_url = page_url
+                .join(&next)
+                .map_err(|err| invalid(format!("Invalid next page URL: {err}")))?;
+            if !fetched.insert(page_url.to_string()) {
+                return Err(invalid(
+                    "Response pages link back to a fetched page".to_owned(),
+                ));
+            }
+            if !self.gateway_hosts.permits(page_url.as_str()) {
+                warn!(url = %request.template, "Refusing to fetch a CCIP-read response page at a disallowed host");
+                return Err(invalid("Next page is at a disallowed host".to_owned()));
+            }
+            // WebSocket gateways can only be sent the lookup, while HTTP
+            // pages are fetched with GET
+            let page_body = if is_websocket_url(page_url.as_str()) {
+                request.body.as_ref()
+            } else {
+                None
+            };
+            let body = self
+                .client_for(page_url.as_str())
+                .fetch(page_url.as_str(), page_body, request.request_id.as_deref())
+                .await?;
+            let page = self
+                .response_decoder
+                .decode(&body, request.ism_address, &request.host)?;
+            if metadata.len() + page.len() > self.max_response_bytes {
+                return Err(GatewayError::ResponseTooLarge(self.max_response_bytes));
+            }
+            metadata.extend_from_slice(&page);
+            match self.response_decoder.next_page(&body) {
+                Some(following) => next = following,
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Checks candidate metadata has the length the ISM is configured to expect
+/// and hasn't expired, and dry runs the ISM's `verify` with it, so metadata
+/// that would make the submission revert isn't returned
+struct MetadataVerifier<'a> {
+    /// ISM whose `verify` is dry run, if enabled
+    ism: Option<&'a dyn InterchainSecurityModule>,
+    expected_length: Option<MetadataLength>,
+    /// Keccak-256 hash of the ISM's static metadata
+    expected_hash: Option<H256>,
+    expiry: Option<MetadataExpiry>,
+    message: &'a HyperlaneMessage,
+    /// Tells whether metadata expired
+    clock: &'a dyn Clock,
+}
+
+impl MetadataVerifier<'_> {
+    /// The length, hash and expiry are checked first since they don't need
+    /// an RPC call
+    async fn check(&self, metadata: &[u8]) -> Result<(), CandidateFailure> {
+        if let Some(expected) = self.expected_length {
+            if !expected.contains(metadata.len()) {
+                return Err(CandidateFailure::UnexpectedLength {
+                    len: metadata.len(),
+                    expected,
+                });
+            }
+        }
+        if let Some(expected) = self.expected_hash {
+            let actual = H256::from(keccak256(metadata));
+            if actual != expected {
+                warn!(
+                    security_event = "metadata_hash_mismatch",
+                    message_id = ?self.message.id(),
+                    ?actual,
+                    ?expected,
+                    "CCIP-read metadata doesn't match its configured hash, it may have been tampered with"
+                );
+                return Err(CandidateFailure::HashMismatch { actual, expected });
+            }
+        }
+        if let Some(expiry) = self.expiry {
+            check_expiry(expiry, metadata, self.clock.unix_time().as_secs())?;
+        }
+        match self.ism {
+            Some(ism) if !self.verifies(ism, metadata).await => {
+                Err(CandidateFailure::FailedVerification)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn verifies(&self, ism: &dyn InterchainSecurityModule, metadata: &[u8]) -> bool {
+        match ism.dry_run_verify(self.message, metadata).await {
+            Ok(gas_estimate) => gas_estimate.is_some(),
+            Err(err) => {
+                warn!(?err, "Failed to dry run verify of CCIP-read metadata");
+                false
+            }
+        }
+    }
+}
+
+/// Fails if `metadata` holds no expiry or expired by `now`
+fn check_expiry(expiry: MetadataExpiry, metadata: &[u8], now: u64) -> Result<(), CandidateFailure> {
+    match expiry.expires_at(metadata) {
+        None => Err(CandidateFailure::MissingExpiry),
+        Some(expires_at) if expires_at <= now => Err(CandidateFailure::Expired { expires_at }),
+        Some(_) => Ok(()),
+    }
+}
+
+/// How long metadata expiring at `expires_at` is cached for at `now`. The
+/// expiry comes from the gateway, so the time is clamped to `min..=max`.
+fn clamped_expiry_ttl(expires_at: u64, now: u64, min: Duration, max: Duration) -> Duration {
+    Duration::from_secs(expires_at.saturating_sub(now)).clamp(min, max)
+}
+
+#[derive(Clone, Debug, new, Deref)]
+pub struct CcipReadIsmMetadataBuilder {
+    base: MessageMetadataBuilder,
+}
+
+impl CcipReadIsmMetadataBuilder {
+    /// Gets the `OffchainLookup` the ISM reverts with for `message`, from the
+    /// cache if it was fetched less than a TTL ago, or from the store if the
+    /// relayer restarted since
+    async fn call_get_offchain_verify_info(
+        &self,
+        ism_address: H256,
+        message: &HyperlaneMessage,
+        lookup_key: &LookupKey,
+    ) -> Result<OffchainLookup, MetadataBuildError> {
+        let context = self.base_builder().ccip_read_context();
+        let span = Span::current();
+        let lookup_key = &context.offchain_lookup_key(lookup_key);
+        if let Some(info) = context.offchain_lookups.get(lookup_key).await {
+            context
+                .metrics
+                .record_call_cache_lookup(lookup_key.fn_name, true);
+            span.record("offchain_lookup", field::display("memory_cache"));
+            return Ok(info);
+        }
+        if let Some(store) = &context.offchain_lookup_store {
+            if let Some(info) = store.get(lookup_key).await {
+                context
+                    .metrics
+                    .record_call_cache_lookup(lookup_key.fn_name, true);
+                span.record("offchain_lookup", field::display("store"));
+                context
+                    .offchain_lookups
+                    .insert(lookup_key.clone(), info.clone())
+                    .await;
+                return Ok(info);
+            }
+        }
+        context
+            .metrics
+            .record_call_cache_lookup(lookup_key.fn_name, false);
+        span.record("offchain_lookup", field::display("ism"));
+
+        self.ensure_ccip_read_ism(message.destination, ism_address)
+            .await?;
+        let ism = self
+            .base_builder()
+            .build_ccip_read_ism(ism_address)
+            .await
+            .map_err(|err| MetadataBuildError::FailedToBuild(err.to_string()))?;
+
+        let response = ism
+            .get_offchain_verify_info(RawHyperlaneMessage::from(message).to_vec())
+            .await;
+        // A misconfigured ISM won't start reverting with an `OffchainLookup`
+        // on its own, so these are refusals rather than failures to fetch
+        let info = match response {
+            Ok(_) => {
+                warn!(
+                    ?ism_address,
+                    misconfiguration = "did_not_revert",
+                    "ISM returned from getOffchainVerifyInfo instead of reverting with an OffchainLookup"
+                );
+                context
+                    .metrics
+                    .record_ism_misconfiguration(ism_address, "did_not_revert");
+                return Err(MetadataBuildError::Refused(
+                    "getOffchainVerifyInfo did not revert".to_owned(),
+                ));
+            }
+            Err(raw_error) => match parse_offchain_lookup(&raw_error.to_string())? {
+                Some(info) => info,
+                None => {
+                    info!(
+                        ?raw_error,
+                        "unable to parse OffchainLookup error out of revert"
+                    );
+                    context
+                        .metrics
+                        .record_ism_misconfiguration(ism_address, "no_offchain_lookup");
+                    return Err(MetadataBuildError::Refused(
+                        "getOffchainVerifyInfo did not revert with OffchainLookup".to_owned(),
+                    ));
+                }
+            },
+        };
+        // Per EIP-3668 the sender is the contract that reverted. Anything else
+        // is a misconfigured or malicious ISM trying to spoof the sender that
+        // is sent to gateways.
+        if H256::from(info.sender) != ism_address {
+            warn!(
+                ?ism_address,
+                sender = ?info.sender,
+                "OffchainLookup sender does not match the ISM, refusing to query gateways"
+            );
+            context
+                .metrics
+                .record_ism_misconfiguration(ism_address, "sender_mismatch");
+            return Err(MetadataBuildError::Refused(
+                "OffchainLookup sender does not match the ISM".to_owned(),
+            ));
+        }
+        context
+            .offchain_lookups
+            .insert(lookup_key.clone(), info.clone())
+            .await;
+        if let Some(store) = &context.offchain_lookup_store {
+            store.insert(lookup_key, info.clone());
+        }
+        Ok(info)
+    }
+
+    /// Checks the module type of the ISM before calling it as a CCIP-read
+    /// ISM, so that an ISM of another type fails with a clear error instead
+    /// of however `getOffchainVerifyInfo` fails on it. Module types are
+    /// cached like `OffchainLookup`s, so this is one call per ISM and TTL.
+    async fn ensure_ccip_read_ism(
+        &self,
+        domain: u32,
+        ism_address: H256,
+    ) -> Result<(), MetadataBuildError> {
+        let context = self.base_builder().ccip_read_context();
+        let module_type = match context.module_types.get(&(domain, ism_address)).await {
+            Some(module_type) => module_type,
+            None => {
+                let module_type = self
+                    .base_builder()
+                    .build_ism(ism_address)
+                    .await
+                    .map_err(|err| MetadataBuildError::FailedToBuild(err.to_string()))?
+                    .module_type()
+                    .await
+                    .map_err(|err| MetadataBuildError::FailedToBuild(err.to_string()))?;
+                context
+                    .module_types
+                    .insert((domain, ism_address), module_type)
+                    .await;
+                module_type
+            }
+        };
+        if !self.supports(module_type) {
+            warn!(
+                ?ism_address,
+                ?module_type,
+                "ISM is not a CCIP-read ISM, not calling getOffchainVerifyInfo"
+            );
+            return Err(MetadataBuildError::NotCcipReadIsm(module_type));
+        }
+        Ok(())
+    }
+
+    /// The state of the destination chain the URL templates of `lookup`
+    /// use, if enabled. Values that fail to be queried are left out, which
+    /// leaves their placeholder in the URL.
+    async fn destination_state(&self, lookup: &OffchainLookup) -> DestinationState {
+        let context = self.base_builder().ccip_read_context();
+        if !context.include_destination_state {
+            return DestinationState::default();
+        }
+        let uses = |placeholder: &str| lookup.urls.iter().any(|url| url.contains(placeholder));
+        let root = if uses("{destRoot}") {
+            self.base_builder()
+                .destination_checkpoint_root()
+                .await
+                .inspect_err(|err| {
+                    warn!(
+                        ?err,
+                        "Failed to get the destination's checkpoint root for CCIP-read gateways"
+                    )
+                })
+                .ok()
+        } else {
+            None
+        };
+        let block = if uses("{destBlock}") {
+            self.base_builder()
+                .destination_block_number()
+                .await
+                .inspect_err(|err| {
+                    warn!(
+                        ?err,
+                        "Failed to get the destination's latest block for CCIP-read gateways"
+                    )
+                })
+                .ok()
+        } else {
+            None
+        };
+        DestinationState { root, block }
+    }
+}
+
+/// Which gateway the metadata built for a message came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetadataSource {
+    /// Lowercase host of the gateway, empty for `data:` URIs and the
+    /// fallback source. Unlike the full URL this can't carry the lookup's
+    /// call data.
+    pub host: String,
+    /// Whether the metadata was reused from an earlier fetch
+    pub cached: bool,
+    /// Whether the metadata came from the fallback source because no
+    /// gateway returned usable metadata
+    pub fallback: bool,
+}
+
+impl CcipReadIsmMetadataBuilder {
+    /// Builds metadata like `build`, also returning which gateway it came
+    /// from, e.g. to audit which gateway vouched for a delivery.
+    /// The span records where the metadata and the `OffchainLookup` came
+    /// from, how many gateway URLs were tried and which one succeeded, and
+    /// how many milliseconds the cache lookup, the `OffchainLookup` and the
+    /// gateway requests took.
+    #[instrument(
+        err,
+        skip(self, message),
+        fields(
+            message_id = ?message.id(),
+            metadata = field::Empty,
+            offchain_lookup = field::Empty,
+            url_count = field::Empty,
+            gateway = field::Empty,
+            cache_lookup_ms = field::Empty,
+            offchain_lookup_ms = field::Empty,
+            gateway_fetch_ms = field::Empty,
+        )
+    )]
+    pub async fn build_with_source(
+        &self,
+        ism_address: H256,
+        message: &HyperlaneMessage,
+    ) -> Result<(Metadata, MetadataSource), MetadataBuildError> {
+        let context = self.base_builder().ccip_read_context();
+        let span = Span::current();
+        let lookup_key = LookupKey::new(ism_address, "getOffchainVerifyInfo", message);
+        let record_ms = |field: &str, start: Instant| {
+            span.record(field, start.elapsed().as_millis() as u64);
+        };
+        let cache_lookup_start = Instant::now();
+        if context.negative_cache.contains(&lookup_key).await {
+            record_ms("cache_lookup_ms", cache_lookup_start);
+            span.record("metadata", field::display("negative_cache"));
+            debug!("No metadata was available from gateways recently, skipping lookup");
+            return Err(MetadataBuildError::AwaitingOffchainData);
+        }
+        let expiry = context.ism_metadata_expiry.get(&ism_address).copied();
+        if let Some((metadata, host)) = context.metadata_cache.get(&lookup_key).await {
+            let now = context.clock.unix_time().as_secs();
+            match expiry.map(|expiry| check_expiry(expiry, &metadata, now)) {
+                Some(Err(failure)) => {
+                    debug!(%failure, "Dropping cached metadata that is no longer usable");
+                    context.metadata_cache.remove(&lookup_key).await;
+                }
+                _ => {
+                    record_ms("cache_lookup_ms", cache_lookup_start);
+                    span.record("metadata", field::display("metadata_cache"));
+                    debug!("Reusing metadata recently returned by a gateway");
+                    let source = MetadataSource {
+                        host,
+                        cached: true,
+                        fallback: false,
+                    };
+                    return Ok((Metadata::new(metadata), source));
+                }
+            }
+        }
+        record_ms("cache_lookup_ms", cache_lookup_start);
+        span.record("metadata", field::display("gateway"));
+
+        let offchain_lookup_start = Instant::now();
+        let info = self
+            .call_get_offchain_verify_info(ism_address, message, &lookup_key)
+            .await;
+        record_ms("offchain_lookup_ms", offchain_lookup_start);
+        let info = info?;
+        let info = context.override_gateway_urls(ism_address, info);
+        let info = context.prioritize_gateway_urls(ism_address, info);
+
+        let destination = self.destination_state(&info).await;
+        let requests = GatewayRequest::for_lookup(
+            &info,
+            message,
+            &destination,
+            &context.gateway_methods,
+            context.gateway_base_url.as_ref(),
+        );
+        let requests = context.select_requests(requests);
+        let mut requests = context.rotate_requests(ism_address, message.id(), requests);
+        if context.callback_encoded_isms.contains(&ism_address) {
+            let callback = LookupCallback::new(&info);
+            for request in &mut requests {
+                request.callback = Some(callback.clone());
+            }
+        }
+        span.record("url_count", requests.len());
+        // Nothing to wait for, so this isn't cached as a gateway failure
+        if requests.is_empty() {
+            warn!("No CCIP-read gateway URL of the ISM may be queried");
+            context
+                .record_lookup_outcome(
+                    ism_address,
+                    message,
+                    Some("No gateway URL of the ISM may be queried".to_owned()),
+                )
+                .await;
+            return Err(MetadataBuildError::CouldNotFetch);
+        }
+
+        let verify_ism = if context.verify_metadata {
+            let ism = self
+                .base_builder()
+                .build_ism(ism_address)
+                .await
+                .map_err(|err| MetadataBuildError::FailedToBuild(err.to_string()))?;
+            Some(ism)
+        } else {
+            None
+        };
+        let expected_length = context.ism_metadata_lengths.get(&ism_address).copied();
+        let expected_hash = context.ism_metadata_hashes.get(&ism_address).copied();
+        let verifier = (verify_ism.is_some()
+            || expected_length.is_some()
+            || expected_hash.is_some()
+            || expiry.is_some())
+        .then(|| MetadataVerifier {
+            ism: verify_ism.as_deref(),
+            expected_length,
+            expected_hash,
+            expiry,
+            message,
+            clock: context.clock.as_ref(),
+        });
+        let gateway_fetch_start = Instant::now();
+        let fetched = context
+            .fetch_from_gateways(&requests, verifier.as_ref())
+            .await;
+        record_ms("gateway_fetch_ms", gateway_fetch_start);
+        let failures = match fetched {
+            Ok((metadata, host)) => {
+                debug!("Fetched metadata from a CCIP-read gateway");
+                context
+                    .record_lookup_outcome(ism_address, message, None)
+                    .await;
+                let cached = (metadata.clone(), host.clone());
+                match expiry.and_then(|expiry| expiry.expires_at(&metadata)) {
+                    Some(expires_at) => {
+                        let ttl = clamped_expiry_ttl(
+                            expires_at,
+                            context.clock.unix_time().as_secs(),
+                            context.metadata_cache_min_ttl,
+                            context.metadata_cache_max_ttl,
+                        );
+                        context
+                            .metadata_cache
+                            .insert_with_ttl(lookup_key, cached, ttl)
+                            .await;
+                    }
+                    None => context.metadata_cache.insert(lookup_key, cached).await,
+                }
+                let source = MetadataSource {
+                    host,
+                    cached: false,
+                    fallback: false,
+                };
+                return Ok((Metadata::new(metadata), source));
+            }
+            // No metadata endpoints or endpoints down
+            Err(failures) => {
+                warn!(
+                    message_id = ?message.id(),
+                    %failures,
+                    "No CCIP-read gateway returned metadata"
+                );
+                failures
+            }
+        };
+
+        // Metadata past its TTL is only used while the gateways are down, not
+        // when they answered that they can't serve the message
+        let stale = match failures.are_outages() {
+            true => context.metadata_cache.get_stale(&lookup_key).await,
+            false => None,
+        };
+        if let Some((metadata, host)) = stale {
+            let usable = match &verifier {
+                Some(verifier) => verifier.check(&metadata).await.is_ok(),
+                None => true,
+            };
+            if usable {
+                span.record("metadata", field::display("stale_metadata_cache"));
+                info!("Using cached metadata past its TTL since no gateway returned any");
+                let source = MetadataSource {
+                    host,
+                    cached: true,
+                    fallback: false,
+                };
+                context
+                    .record_lookup_outcome(ism_address, message, None)
+                    .await;
+                return Ok((Metadata::new(metadata), source));
+            }
+        }
+
+        if let Some(metadata) = context
+            .fetch_fallback(ism_address, message, verifier.as_ref())
+            .await
+        {
+            span.record("metadata", field::display("fallback"));
+            info!("Using fallback CCIP-read metadata since no gateway returned any");
+            let source = MetadataSource {
+                host: String::new(),
+                cached: false,
+                fallback: true,
+            };
+            context
+                .record_lookup_outcome(ism_address, message, None)
+                .await;
+            return Ok((Metadata::new(metadata), source));
+        }
+        context
+            .record_lookup_outcome(ism_address, message, Some(failures.to_string()))
+            .await;
+        context.negative_cache.insert(lookup_key, ()).await;
+        Err(MetadataBuildError::AwaitingOffchainData)
+    }
+
+    /// The `OffchainLookup` the ISM at `ism_address` reverts with for
+    /// `message`, as decoded and before any configured URL overrides, for
+    /// debugging tools. Cached lookups are reused and fresh ones are cached,
+    /// but no gateway is queried. `None` if the ISM isn't a CCIP-read ISM or
+    /// doesn't revert with an `OffchainLookup`.
+    pub async fn offchain_lookup_for(
+        &self,
+        ism_address: H256,
+        message: &HyperlaneMessage,
+    ) -> Result<Option<OffchainLookup>, MetadataBuildError> {
+        let lookup_key = LookupKey::new(ism_address, "getOffchainVerifyInfo", message);
+        match self
+            .call_get_offchain_verify_info(ism_address, message, &lookup_key)
+            .await
+        {
+            Ok(info) => Ok(Some(info)),
+            Err(MetadataBuildError::Refused(_) | MetadataBuildError::NotCcipReadIsm(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Builds the metadata of `message` again right away, e.g. once its
+    /// gateways were fixed. Everything cached for the message is dropped
+    /// first, including a recent failure to find metadata, so its ISM is
+    /// called and its gateways are queried again.
+    pub async fn rebuild_with_source(
+        &self,
+        ism_address: H256,
+        message: &HyperlaneMessage,
+    ) -> Result<(Metadata, MetadataSource), MetadataBuildError> {
+        self.base_builder()
+            .ccip_read_context()
+            .invalidate_message(message.id())
+            .await;
+        self.build_with_source(ism_address, message).await
+    }
+
+    /// Builds metadata for several messages, e.g. a burst of messages for
+    /// the same ISM, with at most the configured number of builds at once.
+    /// Lookups share the context's pooled connections and caches like
+    /// individual builds do. Results are in the order of `items`.
+    pub async fn build_batch(
+        &self,
+        items: &[(H256, &HyperlaneMessage)],
+    ) -> Vec<Result<Metadata, MetadataBuildError>> {
+        let concurrency = self
+            .base_builder()
+            .ccip_read_context()
+            .batch_build_concurrency
+            .max(1);
+        stream::iter(items)
+            .map(|(ism_address, message)| async move {
+                self.build_with_source(*ism_address, message)
+                    .await
+                    .map(|(metadata, _)| metadata)
+            })
+            .buffered(concurrency)
+            .collect()
+            .await
+    }
+
+    /// Fetches and caches the `OffchainLookup`s of a batch of messages for
+    /// the ISM at `ism_address`, with at most the configured number of
+    /// calls at once, so that building their metadata afterwards only has
+    /// to query gateways. Lookups that fail are left for the builds to
+    /// retry and report. Returns how many lookups are cached.
+    pub async fn prefetch_offchain_info(
+        &self,
+        ism_address: H256,
+        messages: &[&HyperlaneMessage],
+    ) -> usize {
+        let concurrency = self
+            .base_builder()
+            .ccip_read_context()
+            .batch_build_concurrency
+            .max(1);
+        stream::iter(messages)
+            .map(|message| async move {
+                let lookup_key = LookupKey::new(ism_address, "getOffchainVerifyInfo", message);
+                match self
+                    .call_get_offchain_verify_info(ism_address, message, &lookup_key)
+                    .await
+                {
+                    Ok(_) => true,
+                    Err(err) => {
+                        debug!(
+                            ?ism_address,
+                            message_id = ?message.id(),
+                            %err,
+                            "Failed to prefetch OffchainLookup"
+                        );
+                        false
+                    }
+                }
+            })
+            .buffer_unordered(concurrency)
+            .fold(0, |cached, prefetched| async move {
+                cached + usize::from(prefetched)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl MetadataBuilder for CcipReadIsmMetadataBuilder {
+    async fn build(
+        &self,
+        ism_address: H256,
+        message: &HyperlaneMessage,
+        _params: MessageMetadataBuildParams,
+    ) -> Result<Metadata, MetadataBuildError> {
+        let (metadata, source) = self.build_with_source(ism_address, message).await?;
+        debug!(
+            message_id = ?message.id(),
+            host = %source.host,
+            cached = source.cached,
+            "Built CCIP-read metadata"
+        );
+        Ok(metadata)
+    }
+
+    fn supports(&self, module_type: ModuleType) -> bool {
+        module_type == ModuleType::CcipRead
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicU32, AtomicUsize, Ordering},
+            Mutex,
+        },
+    };
+
+    use axum::{
+        response::IntoResponse,
+        routing::{get, post},
+        Json, Router,
+    };
+    use ethers::{
+        abi::{AbiEncode, ParamType},
+        types::Address,
+    };
+    use futures::SinkExt;
+    use hyperlane_base::{
+        db::{test_utils, DbError},
+        CoreMetrics,
+    };
+    use hyperlane_core::{ChainCommunicationError, PendingOperationStatus, ReprepareReason, U256};
+    use prometheus::Registry;
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+    use crate::{
+        msg::pending_message::{ISM_MAX_COUNT, ISM_MAX_DEPTH},
+        test_utils::{
+            mock_base_builder::MockBaseMetadataBuilder,
+            mock_ccip_read_ism::MockCcipReadIsm,
+            mock_clock::MockClock,
+            mock_gateway_client::MockGatewayClient,
+            mock_gateway_server::{MockGatewayResponse, MockGatewayServer},
+            mock_ism::MockInterchainSecurityModule,
+        },
+    };
+
+    use super::{store::LookupBackend, *};
+
+    /// The error `getOffchainVerifyInfo` reverts with when pointing at `urls`
+    fn offchain_lookup_revert(urls: &[String]) -> ChainCommunicationError {
+        let lookup = OffchainLookup {
+            sender: Address::zero(),
+            urls: urls.to_vec(),
+            call_data: vec![1, 2, 3].into(),
+            callback_function: [0; 4],
+            extra_data: Default::default(),
+        };
+        ChainCommunicationError::CustomError(format!(
+            "execution reverted: {}",
+            bytes_to_hex(&lookup.encode())
+        ))
+    }
+
+    /// A context whose metrics are registered with a throwaway registry
+    fn test_context(conf: &CcipReadConf) -> CcipReadContext {
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        CcipReadContext::new(conf, CcipReadMetrics::new(&core_metrics)).unwrap()
+    }
+
+    fn ccip_read_builder(urls: &[String], conf: &CcipReadConf) -> CcipReadIsmMetadataBuilder {
+        into_ccip_read_builder(ccip_read_base_builder(urls, conf))
+    }
+
+    /// A base builder for an ISM whose `getOffchainVerifyInfo` points at `urls`
+    fn ccip_read_base_builder(urls: &[String], conf: &CcipReadConf) -> MockBaseMetadataBuilder {
+        let mut base_builder = MockBaseMetadataBuilder::new();
+        base_builder.responses.ccip_read_context = Some(test_context(conf));
+        push_module_type(&base_builder, ModuleType::CcipRead);
+
+        let ism = MockCcipReadIsm::default();
+        ism.responses
+            .get_offchain_verify_info
+            .lock()
+            .unwrap()
+            .push_back(Err(offchain_lookup_revert(urls)));
+        base_builder
+            .responses
+            .build_ccip_read_ism
+            .lock()
+            .unwrap()
+            .push_back(Ok(Box::new(ism)));
+        base_builder
+    }
+
+    /// Makes the next ISM built for the zero address report `module_type`
+    fn push_module_type(base_builder: &MockBaseMetadataBuilder, module_type: ModuleType) {
+        let ism = MockInterchainSecurityModule::new(H256::zero());
+        ism.responses
+            .module_type
+            .lock()
+            .unwrap()
+            .push_back(Ok(module_type));
+        base_builder
+            .responses
+            .push_build_ism_response(H256::zero(), Ok(Box::new(ism)));
+    }
+
+    fn into_ccip_read_builder(base_builder: MockBaseMetadataBuilder) -> CcipReadIsmMetadataBuilder {
+        CcipReadIsmMetadataBuilder::new(MessageMetadataBuilder {
+            base: Arc::new(base_builder),
+            app_context: None,
+            max_ism_depth: ISM_MAX_DEPTH,
+            max_ism_count: ISM_MAX_COUNT,
+        })
+    }
+
+    /// A GET request to `url`, which is also used as the template
+    fn gateway_request(url: String) -> GatewayRequest {
+        GatewayRequest::new(url.clone(), url, None)
+    }
+
+    /// Serves `router` as an offchain gateway in the background
+    fn run_gateway(router: Router) -> SocketAddr {
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_gateway_falls_through_to_next_url() {
+        let gateway = MockGatewayServer::start();
+        gateway
+            .respond(
+                "/slow/0x010203",
+                MockGatewayResponse::data("0x01").delayed(Duration::from_secs(5)),
+            )
+            .respond("/fast/0x010203", MockGatewayResponse::data("0x02"));
+        let urls = vec![gateway.url("/slow/{data}"), gateway.url("/fast/{data}")];
+        let conf = CcipReadConf {
+            gateway_timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        let metadata = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the fast gateway");
+        assert_eq!(metadata.to_vec(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_gateways_return_fastest_response() {
+        let router = Router::new()
+            .route(
+                "/slow/:data",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    Json(json!({ "data": "0x01" }))
+                }),
+            )
+            .route(
+                "/fast/:data",
+                get(|| async { Json(json!({ "data": "0x02" })) }),
+            );
+        let addr = run_gateway(router);
+        let urls = vec![
+            format!("http://{addr}/slow/{{data}}"),
+            format!("http://{addr}/fast/{{data}}"),
+        ];
+        let conf = CcipReadConf {
+            concurrent_gateways: true,
+            ..Default::default()
+        };
+
+        let start = std::time::Instant::now();
+        let metadata = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the fast gateway");
+        assert_eq!(metadata.to_vec(), vec![2]);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_configured_headers_are_sent_to_matching_host() {
+        let router = Router::new().route(
+            "/:data",
+            get(|headers: HeaderMap| async move {
+                if headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok()) == Some("Bearer secret")
+                {
+                    Json(json!({ "data": "0x01" })).into_response()
+                } else {
+                    StatusCode::UNAUTHORIZED.into_response()
+                }
+            }),
+        );
+        let addr = run_gateway(router);
+        let urls = vec![format!("http://{addr}/{{data}}")];
+
+        let mut headers = HeaderMap::new();
+        let mut token = HeaderValue::from_static("Bearer secret");
+        token.set_sensitive(true);
+        headers.insert(AUTHORIZATION, token);
+        let conf = CcipReadConf {
+            gateway_headers: HashMap::from([(addr.ip().to_string(), headers)]),
+            ..Default::default()
+        };
+
+        let metadata = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected the gateway to accept the configured credentials");
+        assert_eq!(metadata.to_vec(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_error_statuses_are_reported() {
+        let router = Router::new()
+            .route(
+                "/missing",
+                get(|| async { (StatusCode::NOT_FOUND, "<html>Not Found</html>") }),
+            )
+            .route(
+                "/broken",
+                get(|| async { (StatusCode::INTERNAL_SERVER_ERROR, "<html>Oops</html>") }),
+            )
+            .route("/garbage", get(|| async { "<html>Hello</html>" }));
+        let addr = run_gateway(router);
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let context = test_context(&conf);
+
+        let res = context
+            .fetch(&gateway_request(format!("http://{addr}/missing")))
+            .await;
+        assert!(matches!(
+            res,
+            Err(GatewayError::Status(StatusCode::NOT_FOUND))
+        ));
+
+        let res = context
+            .fetch(&gateway_request(format!("http://{addr}/broken")))
+            .await;
+        assert!(matches!(
+            res,
+            Err(GatewayError::Status(StatusCode::INTERNAL_SERVER_ERROR))
+        ));
+
+        let res = context
+            .fetch(&gateway_request(format!("http://{addr}/garbage")))
+            .await;
+        assert!(matches!(res, Err(GatewayError::InvalidResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_error_statuses_fall_through_to_next_url() {
+        let gateway = MockGatewayServer::start();
+        gateway
+            .respond(
+                "/missing/0x010203",
+                MockGatewayResponse::status(StatusCode::NOT_FOUND),
+            )
+            .respond(
+                "/broken/0x010203",
+                MockGatewayResponse::status(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .respond(
+                "/garbage/0x010203",
+                MockGatewayResponse::body("<html>Hello</html>"),
+            )
+            .respond("/ok/0x010203", MockGatewayResponse::data("0x03"));
+        let urls = ["missing", "broken", "garbage", "ok"]
+            .map(|path| gateway.url(&format!("/{path}/{{data}}")))
+            .to_vec();
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+
+        let metadata = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the last gateway");
+        assert_eq!(metadata.to_vec(), vec![3]);
+        assert_eq!(
+            gateway.requests(),
+            ["missing", "broken", "garbage", "ok"].map(|path| format!("/{path}/0x010203"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_hex_falls_through_to_next_url() {
+        let router = Router::new()
+            .route(
+                "/invalid/:data",
+                get(|| async { Json(json!({ "data": "0xnothex" })) }),
+            )
+            .route("/raw/:data", get(|| async { "0x04" }));
+        let addr = run_gateway(router);
+        let urls = vec![
+            format!("http://{addr}/invalid/{{data}}"),
+            format!("http://{addr}/raw/{{data}}"),
+        ];
+
+        let metadata = ccip_read_builder(&urls, &CcipReadConf::default())
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the raw hex gateway");
+        assert_eq!(metadata.to_vec(), vec![4]);
+    }
+
+    #[tokio::test]
+    async fn test_missing_data_pointer_falls_through_to_next_url() {
+        let router = Router::new()
+            .route(
+                "/flat/:data",
+                get(|| async { Json(json!({ "data": "0x01" })) }),
+            )
+            .route(
+                "/nested/:data",
+                get(|| async { Json(json!({ "response": { "metadata": "0x05" } })) }),
+            );
+        let addr = run_gateway(router);
+        let urls = vec![
+            format!("http://{addr}/flat/{{data}}"),
+            format!("http://{addr}/nested/{{data}}"),
+        ];
+        let conf = CcipReadConf {
+            response_data_pointer: "/response/metadata".to_owned(),
+            ..Default::default()
+        };
+
+        let metadata = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the nested gateway");
+        assert_eq!(metadata.to_vec(), vec![5]);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_is_rejected() {
+        let router = Router::new()
+            .route(
+                "/large",
+                get(|| async { Json(json!({ "data": format!("0x{}", "ab".repeat(1024)) })) }),
+            )
+            .route("/small", get(|| async { Json(json!({ "data": "0x06" })) }));
+        let addr = run_gateway(router);
+        let conf = CcipReadConf {
+            max_response_bytes: 1024,
+            ..Default::default()
+        };
+        let context = test_context(&conf);
+
+        let res = context
+            .fetch(&gateway_request(format!("http://{addr}/large")))
+            .await;
+        assert!(matches!(res, Err(GatewayError::ResponseTooLarge(1024))));
+
+        let res = context
+            .fetch(&gateway_request(format!("http://{addr}/small")))
+            .await;
+        assert_eq!(res.unwrap(), vec![6]);
+    }
+
+    #[tokio::test]
+    async fn test_denied_hosts_are_skipped() {
+        let router =
+            Router::new().route("/:data", get(|| async { Json(json!({ "data": "0x07" })) }));
+        let addr = run_gateway(router);
+        let urls = vec![
+            format!("http://localhost:{}/{{data}}", addr.port()),
+            format!("http://{addr}/{{data}}"),
+        ];
+        let conf = CcipReadConf {
+            gateway_hosts: HostFilter {
+                allowed: vec![],
+                denied: vec!["localhost".parse().unwrap()],
+            },
+            ..Default::default()
+        };
+
+        let metadata = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the allowed host");
+        assert_eq!(metadata.to_vec(), vec![7]);
+
+        // With every URL denied no request is sent at all
+        let conf = CcipReadConf {
+            gateway_hosts: HostFilter {
+                allowed: vec!["gateway.example.com".parse().unwrap()],
+                denied: vec![],
+            },
+            ..Default::default()
+        };
+        let res = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await;
+        assert!(matches!(res, Err(MetadataBuildError::CouldNotFetch)));
+    }
+
+    #[tokio::test]
+    async fn test_failed_lookup_is_not_repeated_within_ttl() {
+        let hits = Arc::new(AtomicU32::new(0));
+        let router = Router::new().route(
+            "/:data",
+            get({
+                let hits = hits.clone();
+                move || async move {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+            }),
+        );
+        let addr = run_gateway(router);
+        let urls = vec![format!("http://{addr}/{{data}}")];
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let builder = ccip_read_builder(&urls, &conf);
+
+        for _ in 0..2 {
+            let res = builder
+                .build(
+                    H256::zero(),
+                    &HyperlaneMessage::default(),
+                    MessageMetadataBuildParams::default(),
+                )
+                .await;
+            assert!(matches!(res, Err(MetadataBuildError::AwaitingOffchainData)));
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gateway_outcomes_are_counted() {
+        let router = Router::new()
+            .route("/down", get(|| async { StatusCode::BAD_GATEWAY }))
+            .route("/up", get(|| async { Json(json!({ "data": "0x08" })) }));
+        let addr = run_gateway(router);
+        let registry = Registry::new();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, registry.clone()).unwrap();
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let context = CcipReadContext::new(&conf, CcipReadMetrics::new(&core_metrics)).unwrap();
+
+        let requests = [
+            gateway_request(format!("http://{addr}/down")),
+            gateway_request(format!("http://{addr}/up")),
+        ];
+        assert_eq!(
+            context
+                .fetch_from_gateways(&requests, None)
+                .await
+                .ok()
+                .map(|(metadata, _)| metadata),
+            Some(vec![8])
+        );
+
+        let count =
+            |outcome: &str| {
+                registry
+                    .gather()
+                    .iter()
+                    .filter(|family| family.get_name() == "hyperlane_ccip_read_gateway_requests")
+                    .flat_map(|family| family.get_metric())
+                    .filter(|metric| {
+                        metric.get_label().iter().any(|label| {
+                            label.get_name() == "outcome" && label.get_value() == outcome
+                        }) && metric.get_label().iter().any(|label| {
+                            label.get_name() == "host" && label.get_value() == "127.0.0.1"
+                        })
+                    })
+                    .map(|metric| metric.get_counter().get_value())
+                    .sum::<f64>()
+            };
+        assert_eq!(count("http_error"), 1.0);
+        assert_eq!(count("success"), 1.0);
+        assert_eq!(count("timeout"), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_host_is_labelled_without_brackets() {
+        let url = "http://[2001:DB8::1]:8545/up";
+        let gateway_client = MockGatewayClient::default();
+        gateway_client
+            .responses
+            .push_fetch_response(url, Ok(br#"{"data":"0x08"}"#.to_vec()));
+        let registry = Registry::new();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, registry.clone()).unwrap();
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &CcipReadConf::default(),
+            CcipReadMetrics::new(&core_metrics),
+        );
+
+        let request = gateway_request(url.to_owned());
+        assert_eq!(request.host, "2001:db8::1");
+        assert!(context.fetch_from_gateways(&[request], None).await.is_ok());
+        let hosts: Vec<_> = registry
+            .gather()
+            .iter()
+            .filter(|family| family.get_name() == "hyperlane_ccip_read_gateway_requests")
+            .flat_map(|family| family.get_metric())
+            .flat_map(|metric| metric.get_label())
+            .filter(|label| label.get_name() == "host")
+            .map(|label| label.get_value().to_owned())
+            .collect();
+        assert_eq!(hosts, vec!["2001:db8::1"]);
+    }
+
+    #[tokio::test]
+    async fn test_gateway_latency_is_observed_per_attempt() {
+        let router = Router::new().route("/down", get(|| async { StatusCode::BAD_GATEWAY }));
+        let addr = run_gateway(router);
+        let registry = Registry::new();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, registry.clone()).unwrap();
+        let conf = CcipReadConf {
+            max_attempts: 2,
+            retry_base_delay: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let context = CcipReadContext::new(&conf, CcipReadMetrics::new(&core_metrics)).unwrap();
+
+        let requests = [gateway_request(format!("http://{addr}/down"))];
+        assert!(context.fetch_from_gateways(&requests, None).await.is_err());
+
+        let samples: u64 = registry
+            .gather()
+            .iter()
+            .filter(|family| family.get_name() == "hyperlane_ccip_read_gateway_latency_seconds")
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_histogram().get_sample_count())
+            .sum();
+        assert_eq!(samples, 2);
+    }
+
+    #[tokio::test]
+    async fn test_position_of_successful_gateway_is_observed() {
+        let gateway_client = MockGatewayClient::default();
+        let responses = &gateway_client.responses;
+        responses.push_fetch_response(
+            "https://a.example.com/",
+            Err(GatewayError::Status(StatusCode::INTERNAL_SERVER_ERROR)),
+        );
+        responses.push_fetch_response("https://b.example.com/", Err(GatewayError::Timeout));
+        responses.push_fetch_response("https://c.example.com/", Ok(br#"{"data":"0x0c"}"#.to_vec()));
+        let registry = Registry::new();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, registry.clone()).unwrap();
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        );
+
+        let requests =
+            ["a", "b", "c"].map(|host| gateway_request(format!("https://{host}.example.com/")));
+        let (metadata, host) = context.fetch_from_gateways(&requests, None).await.unwrap();
+        assert_eq!((metadata, host.as_str()), (vec![12], "c.example.com"));
+
+        let histogram = registry
+            .gather()
+            .into_iter()
+            .find(|family| family.get_name() == "hyperlane_ccip_read_successful_gateway_position")
+            .unwrap()
+            .get_metric()[0]
+            .get_histogram()
+            .clone();
+        assert_eq!(histogram.get_sample_count(), 1);
+        assert_eq!(histogram.get_sample_sum(), 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_failing_verification_falls_through_to_next_url() {
+        let router = Router::new()
+            .route(
+                "/bad/:data",
+                get(|| async { Json(json!({ "data": "0x09" })) }),
+            )
+            .route(
+                "/good/:data",
+                get(|| async { Json(json!({ "data": "0x0a" })) }),
+            );
+        let addr = run_gateway(router);
+        let urls = vec![
+            format!("http://{addr}/bad/{{data}}"),
+            format!("http://{addr}/good/{{data}}"),
+        ];
+        let conf = CcipReadConf {
+            verify_metadata: true,
+            ..Default::default()
+        };
+
+        let base_builder = ccip_read_base_builder(&urls, &conf);
+        let ism = MockInterchainSecurityModule::new(H256::zero());
+        {
+            let mut dry_run_verify = ism.responses.dry_run_verify.lock().unwrap();
+            dry_run_verify.push_back(Ok(None));
+            dry_run_verify.push_back(Ok(Some(U256::from(100_000))));
+        }
+        base_builder
+            .responses
+            .push_build_ism_response(H256::zero(), Ok(Box::new(ism)));
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected the metadata that passes verification");
+        assert_eq!(metadata.to_vec(), vec![10]);
+    }
+
+    #[tokio::test]
+    async fn test_offchain_lookup_is_refetched_after_ttl() {
+        let router =
+            Router::new().route("/:data", get(|| async { Json(json!({ "data": "0x0b" })) }));
+        let addr = run_gateway(router);
+        let urls = vec![format!("http://{addr}/{{data}}")];
+        let conf = CcipReadConf {
+            offchain_lookup_cache_ttl: Duration::from_millis(100),
+            // Otherwise later builds reuse the metadata without a lookup
+            metadata_cache_ttl: Duration::ZERO,
+            ..Default::default()
+        };
+
+        // Both ISMs share their responses so all contract calls can be counted
+        let verify_info_responses = MockCcipReadIsm::default()
+            .responses
+            .get_offchain_verify_info;
+        let mut base_builder = MockBaseMetadataBuilder::new();
+        base_builder.responses.ccip_read_context = Some(test_context(&conf));
+        for _ in 0..2 {
+            push_module_type(&base_builder, ModuleType::CcipRead);
+            verify_info_responses
+                .lock()
+                .unwrap()
+                .push_back(Err(offchain_lookup_revert(&urls)));
+            let mut ism = MockCcipReadIsm::default();
+            ism.responses.get_offchain_verify_info = verify_info_responses.clone();
+            base_builder
+                .responses
+                .build_ccip_read_ism
+                .lock()
+                .unwrap()
+                .push_back(Ok(Box::new(ism)));
+        }
+        let builder = into_ccip_read_builder(base_builder);
+        let message = HyperlaneMessage::default();
+        let build = || {
+            builder.build(
+                H256::zero(),
+                &message,
+                MessageMetadataBuildParams::default(),
+            )
+        };
+
+        build().await.unwrap();
+        build().await.unwrap();
+        // The second build within the TTL used the cached lookup
+        assert_eq!(verify_info_responses.lock().unwrap().len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        build().await.unwrap();
+        assert_eq!(verify_info_responses.lock().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_urls_are_only_queried_once() {
+        let hits = Arc::new(AtomicU32::new(0));
+        let router = Router::new().route(
+            "/:data",
+            get({
+                let hits = hits.clone();
+                move || async move {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    StatusCode::NOT_FOUND
+                }
+            }),
+        );
+        let addr = run_gateway(router);
+        let url = format!("http://{addr}/{{data}}");
+        let urls = vec![url.clone(), url];
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+
+        let res = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await;
+        assert!(matches!(res, Err(MetadataBuildError::AwaitingOffchainData)));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_gateway_urls_are_capped() {
+        let conf = CcipReadConf {
+            max_gateway_urls: 2,
+            ..Default::default()
+        };
+        let requests = (0..5)
+            .map(|i| gateway_request(format!("https://gateway{i}.example.com/{{data}}")))
+            .collect();
+        let selected = test_context(&conf).select_requests(requests);
+        assert_eq!(selected.len(), 2);
+        assert_eq!(selected[0].host, "gateway0.example.com");
+        assert_eq!(selected[1].host, "gateway1.example.com");
+    }
+
+    #[test]
+    fn test_urls_are_deduplicated_after_normalization() {
+        let requests = vec![
+            gateway_request("https://Gateway.example.com/0x01".to_owned()),
+            gateway_request("https://gateway.example.com/0x01".to_owned()),
+            gateway_request("https://gateway.example.com:443/0x01".to_owned()),
+            gateway_request("https://gateway.example.com/0x02".to_owned()),
+        ];
+        let selected = test_context(&CcipReadConf::default()).select_requests(requests);
+        assert_eq!(selected.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_falls_through_gateways_with_mock_client() {
+        let urls = vec![
+            "https://a.example.com/{sender}/{data}".to_owned(),
+            "https://b.example.com/{sender}".to_owned(),
+            "https://c.example.com/{data}.json".to_owned(),
+        ];
+        let sender = format!("0x{}", "00".repeat(20));
+        let a_url = format!("https://a.example.com/{sender}/0x010203");
+        let b_url = format!("https://b.example.com/{sender}");
+        let c_url = "https://c.example.com/0x010203.json".to_owned();
+
+        let gateway_client = MockGatewayClient::default();
+        let responses = &gateway_client.responses;
+        responses.push_fetch_response(
+            &a_url,
+            Err(GatewayError::Status(StatusCode::INTERNAL_SERVER_ERROR)),
+        );
+        responses.push_fetch_response(&b_url, Ok(b"not metadata".to_vec()));
+        responses.push_fetch_response(&c_url, Ok(br#"{"data":"0x0c"}"#.to_vec()));
+        let requests = gateway_client.requests.clone();
+
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the last gateway");
+        assert_eq!(metadata.to_vec(), vec![12]);
+
+        let requests: Vec<_> = requests.lock().unwrap().drain(..).collect();
+        assert_eq!(
+            requests,
+            vec![
+                (a_url, None),
+                (b_url, Some(json!({ "sender": sender, "data": "0x010203" }))),
+                (c_url, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interpolates_hex_encoded_call_data() {
+        let lookup = OffchainLookup {
+            sender: Address::repeat_byte(0xab),
+            urls: vec![
+                "https://a.example.com/{sender}/{data}.json".to_owned(),
+                "https://b.example.com/".to_owned(),
+            ],
+            call_data: vec![0x00, 0x0f, 0xf0].into(),
+            callback_function: [0; 4],
+            extra_data: Default::default(),
+        };
+        let sender = format!("0x{}", "ab".repeat(20));
+
+        let requests = GatewayRequest::for_lookup(
+            &lookup,
+            &HyperlaneMessage::default(),
+            &DestinationState::default(),
+            &HashMap::new(),
+            None,
+        );
+        assert_eq!(
+            requests[0].url,
+            format!("https://a.example.com/{sender}/0x000ff0.json")
+        );
+        assert_eq!(requests[0].body, None);
+        assert_eq!(requests[1].url, "https://b.example.com/");
+        assert_eq!(
+            requests[1].body,
+            Some(json!({ "sender": sender, "data": "0x000ff0" }))
+        );
+    }
+
+    #[test]
+    fn test_request_method_follows_template() {
+        let lookup = OffchainLookup {
+            sender: Address::repeat_byte(0xab),
+            urls: vec![
+                "https://a.example.com/{sender}".to_owned(),
+                "https://b.example.com/{sender}/{data}".to_owned(),
+                "https://c.example.com/lookup".to_owned(),
+            ],
+            call_data: vec![1, 2, 3].into(),
+            callback_function: [0; 4],
+            extra_data: Default::default(),
+        };
+        let sender = format!("0x{}", "ab".repeat(20));
+        let post_body = json!({ "sender": sender, "data": "0x010203" });
+
+        let requests = GatewayRequest::for_lookup(
+            &lookup,
+            &HyperlaneMessage::default(),
+            &DestinationState::default(),
+            &HashMap::new(),
+            None,
+        );
+        let requests: Vec<_> = requests
+            .iter()
+            .map(|request| (request.url.as_str(), request.body.as_ref()))
+            .collect();
+        assert_eq!(
+            requests,
+            vec![
+                (
+                    format!("https://a.example.com/{sender}").as_str(),
+                    Some(&post_body)
+                ),
+                (
+                    format!("https://b.example.com/{sender}/0x010203").as_str(),
+                    None
+                ),
+                ("https://c.example.com/lookup", Some(&post_body)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_websocket_gateways_are_sent_the_body() {
+        let lookup = OffchainLookup {
+            sender: Address::repeat_byte(0xab),
+            urls: vec!["wss://a.example.com/{data}".to_owned()],
+            call_data: vec![1, 2, 3].into(),
+            callback_function: [0; 4],
+            extra_data: Default::default(),
+        };
+        let methods = HashMap::from([("a.example.com".to_owned(), GatewayMethod::Get)]);
+
+        let requests = GatewayRequest::for_lookup(
+            &lookup,
+            &HyperlaneMessage::default(),
+            &DestinationState::default(),
+            &methods,
+            None,
+        );
+        let sender = format!("0x{}", "ab".repeat(20));
+        assert_eq!(
+            requests[0].body,
+            Some(json!({ "sender": sender, "data": "0x010203" }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gateway_failures_are_summarized() {
+        let gateway_client = MockGatewayClient::default();
+        let responses = &gateway_client.responses;
+        responses.push_fetch_response("https://a.example.com/", Err(GatewayError::Timeout));
+        responses.push_fetch_response(
+            "https://b.example.com/",
+            Err(GatewayError::Status(StatusCode::BAD_GATEWAY)),
+        );
+        responses.push_fetch_response("https://c.example.com/", Ok(b"not metadata".to_vec()));
+
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        );
+        let requests: Vec<_> = ["a", "b", "c"]
+            .into_iter()
+            .map(|host| gateway_request(format!("https://{host}.example.com/")))
+            .collect();
+
+        let failures = context
+            .fetch_from_gateways(&requests, None)
+            .await
+            .expect_err("Expected every gateway to fail");
+        let summary = failures.to_string();
+        assert!(summary.starts_with(
+            "https://a.example.com/: Request timed out; \
+             https://b.example.com/: Gateway responded with status 502 Bad Gateway; \
+             https://c.example.com/: "
+        ));
+        assert_eq!(failures.0.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_misconfigured_ism_is_refused() {
+        let responses = [
+            Ok(()),
+            Err(ChainCommunicationError::CustomError(
+                "execution reverted: 0x08c379a0".to_owned(),
+            )),
+        ];
+        for response in responses {
+            let mut base_builder = MockBaseMetadataBuilder::new();
+            base_builder.responses.ccip_read_context = Some(test_context(&Default::default()));
+            push_module_type(&base_builder, ModuleType::CcipRead);
+            let ism = MockCcipReadIsm::default();
+            ism.responses
+                .get_offchain_verify_info
+                .lock()
+                .unwrap()
+                .push_back(response);
+            base_builder
+                .responses
+                .build_ccip_read_ism
+                .lock()
+                .unwrap()
+                .push_back(Ok(Box::new(ism)));
+
+            let res = into_ccip_read_builder(base_builder)
+                .build(
+                    H256::zero(),
+                    &HyperlaneMessage::default(),
+                    MessageMetadataBuildParams::default(),
+                )
+                .await;
+            assert!(matches!(res, Err(MetadataBuildError::Refused(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ism_returning_from_get_offchain_verify_info_is_counted() {
+        let registry = Registry::new();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, registry.clone()).unwrap();
+        let conf = CcipReadConf::default();
+        let mut base_builder = MockBaseMetadataBuilder::new();
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(MockGatewayClient::default()),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+        push_module_type(&base_builder, ModuleType::CcipRead);
+        let ism = MockCcipReadIsm::default();
+        ism.responses
+            .get_offchain_verify_info
+            .lock()
+            .unwrap()
+            .push_back(Ok(()));
+        base_builder
+            .responses
+            .build_ccip_read_ism
+            .lock()
+            .unwrap()
+            .push_back(Ok(Box::new(ism)));
+
+        let res = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await;
+        assert!(matches!(res, Err(MetadataBuildError::Refused(_))));
+        let count = |reason: &str| {
+            registry
+                .gather()
+                .iter()
+                .filter(|family| family.get_name() == "hyperlane_ccip_read_ism_misconfigurations")
+                .flat_map(|family| family.get_metric())
+                .filter(|metric| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .any(|label| label.get_name() == "reason" && label.get_value() == reason)
+                })
+                .map(|metric| metric.get_counter().get_value())
+                .sum::<f64>()
+        };
+        assert_eq!(count("did_not_revert"), 1.0);
+        assert_eq!(count("no_offchain_lookup"), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_offchain_lookup_for_returns_decoded_lookup() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let builder = ccip_read_builder(&urls, &CcipReadConf::default());
+        let expected = OffchainLookup {
+            sender: Address::zero(),
+            urls: urls.clone(),
+            call_data: vec![1, 2, 3].into(),
+            callback_function: [0; 4],
+            extra_data: Default::default(),
+        };
+
+        // The ISM only responds once, so the second lookup comes from the cache
+        for _ in 0..2 {
+            let lookup = builder
+                .offchain_lookup_for(H256::zero(), &HyperlaneMessage::default())
+                .await
+                .unwrap();
+            assert_eq!(lookup, Some(expected.clone()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metadata_is_reused_within_ttl() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        // A second request would panic for lack of a response
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Ok(br#"{"data":"0x0d"}"#.to_vec()),
+        );
+        let requests = gateway_client.requests.clone();
+
+        let conf = CcipReadConf::default();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+        let builder = into_ccip_read_builder(base_builder);
+
+        for _ in 0..2 {
+            let metadata = builder
+                .build(
+                    H256::zero(),
+                    &HyperlaneMessage::default(),
+                    MessageMetadataBuildParams::default(),
+                )
+                .await
+                .expect("Expected metadata");
+            assert_eq!(metadata.to_vec(), vec![13]);
+        }
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_offchain_lookup_survives_restart() {
+        test_utils::run_test_db(|db| async move {
+            let urls = vec!["https://a.example.com/{data}".to_owned()];
+            let conf = CcipReadConf::default();
+            let store = OffchainLookupStore::new(db, conf.offchain_lookup_cache_ttl);
+            let context = || {
+                let gateway_client = MockGatewayClient::default();
+                gateway_client.responses.push_fetch_response(
+                    "https://a.example.com/0x010203",
+                    Ok(br#"{"data":"0x0e"}"#.to_vec()),
+                );
+                let core_metrics =
+                    CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+                CcipReadContext::with_gateway_client(
+                    Arc::new(gateway_client),
+                    &conf,
+                    CcipReadMetrics::new(&core_metrics),
+                )
+                .with_offchain_lookup_store(store.clone())
+            };
+            let message = HyperlaneMessage::default();
+            let message = &message;
+            let build = move |base_builder| async move {
+                into_ccip_read_builder(base_builder)
+                    .build(H256::zero(), message, MessageMetadataBuildParams::default())
+                    .await
+                    .expect("Expected metadata")
+            };
+
+            let mut base_builder = ccip_read_base_builder(&urls, &conf);
+            base_builder.responses.ccip_read_context = Some(context());
+            assert_eq!(build(base_builder).await.to_vec(), vec![14]);
+
+            // The lookup is written in the background
+            let lookup_key = LookupKey::new(H256::zero(), "getOffchainVerifyInfo", &message);
+            for _ in 0..100 {
+                if store.get(&lookup_key).await.is_some() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+
+            // After a "restart" the ISM isn't called again, which would panic
+            // for lack of a mock response
+            let mut base_builder = MockBaseMetadataBuilder::new();
+            base_builder.responses.ccip_read_context = Some(context());
+            assert_eq!(build(base_builder).await.to_vec(), vec![14]);
+        })
+        .await;
+    }
+
+    /// Fails every read and write, like a database that became unavailable
+    #[derive(Debug)]
+    struct FailingLookupBackend;
+
+    impl LookupBackend for FailingLookupBackend {
+        fn retrieve(&self, _key: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
+            Err(DbError::Other("unavailable".to_owned()))
+        }
+
+        fn store(&self, _key: &[u8], _value: &[u8]) -> Result<(), DbError> {
+            Err(DbError::Other("unavailable".to_owned()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_offchain_lookup_store_falls_back_to_ism() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Ok(br#"{"data":"0x10"}"#.to_vec()),
+        );
+        let conf = CcipReadConf::default();
+        let registry = Registry::new();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, registry.clone()).unwrap();
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        )
+        .with_offchain_lookup_store(OffchainLookupStore::with_backend(
+            Arc::new(FailingLookupBackend),
+            conf.offchain_lookup_cache_ttl,
+        ));
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(context.clone());
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata");
+        assert_eq!(metadata.to_vec(), vec![16]);
+
+        // The lookup is written in the background
+        context.shutdown().await;
+        let failures = |operation: &str| {
+            registry
+                .gather()
+                .iter()
+                .filter(|family| {
+                    family.get_name() == "hyperlane_ccip_read_offchain_lookup_store_failures"
+                })
+                .flat_map(|family| family.get_metric())
+                .filter(|metric| {
+                    metric.get_label().iter().any(|label| {
+                        label.get_name() == "operation" && label.get_value() == operation
+                    })
+                })
+                .map(|metric| metric.get_counter().get_value())
+                .sum::<f64>()
+        };
+        assert_eq!((failures("read"), failures("write")), (1.0, 1.0));
+    }
+
+    #[tokio::test]
+    async fn test_offchain_lookup_cache_hits_and_misses_are_counted() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        for _ in 0..2 {
+            gateway_client.responses.push_fetch_response(
+                "https://a.example.com/0x010203",
+                Ok(br#"{"data":"0x0f"}"#.to_vec()),
+            );
+        }
+        let conf = CcipReadConf {
+            // Otherwise the second build reuses the metadata without a lookup
+            metadata_cache_ttl: Duration::ZERO,
+            ..Default::default()
+        };
+        let registry = Registry::new();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, registry.clone()).unwrap();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+        let builder = into_ccip_read_builder(base_builder);
+
+        let count =
+            |result: &str| {
+                registry
+                    .gather()
+                    .iter()
+                    .filter(|family| family.get_name() == "hyperlane_ccip_read_call_cache_lookups")
+                    .flat_map(|family| family.get_metric())
+                    .filter(|metric| {
+                        metric.get_label().iter().any(|label| {
+                            label.get_name() == "result" && label.get_value() == result
+                        }) && metric.get_label().iter().any(|label| {
+                            label.get_name() == "fn_name"
+                                && label.get_value() == "getOffchainVerifyInfo"
+                        })
+                    })
+                    .map(|metric| metric.get_counter().get_value())
+                    .sum::<f64>()
+            };
+        for (hits, misses) in [(0.0, 1.0), (1.0, 1.0)] {
+            builder
+                .build(
+                    H256::zero(),
+                    &HyperlaneMessage::default(),
+                    MessageMetadataBuildParams::default(),
+                )
+                .await
+                .expect("Expected metadata");
+            assert_eq!((count("hit"), count("miss")), (hits, misses));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_independent_lookup_is_shared_across_messages() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        for _ in 0..2 {
+            gateway_client.responses.push_fetch_response(
+                "https://a.example.com/0x010203",
+                Ok(br#"{"data":"0x0f"}"#.to_vec()),
+            );
+        }
+        let conf = CcipReadConf {
+            message_independent_isms: HashSet::from([H256::zero()]),
+            ..Default::default()
+        };
+        // Only a single ISM is mocked, so the second message must hit the cache
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(
+                &CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap(),
+            ),
+        ));
+        let builder = into_ccip_read_builder(base_builder);
+
+        for nonce in 0..2 {
+            let message = HyperlaneMessage {
+                nonce,
+                ..Default::default()
+            };
+            let metadata = builder
+                .build(
+                    H256::zero(),
+                    &message,
+                    MessageMetadataBuildParams::default(),
+                )
+                .await
+                .expect("Expected metadata");
+            assert_eq!(metadata.to_vec(), vec![0x0f]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_same_ism_address_on_two_domains_does_not_collide() {
+        let conf = CcipReadConf {
+            // Otherwise the message ids alone would tell the lookups apart
+            message_independent_isms: HashSet::from([H256::zero()]),
+            ..Default::default()
+        };
+        let mut base_builder = MockBaseMetadataBuilder::new();
+        base_builder.responses.ccip_read_context = Some(test_context(&conf));
+        let domain_urls = [
+            vec!["https://one.example.com/{data}".to_owned()],
+            vec!["https://two.example.com/{data}".to_owned()],
+        ];
+        for urls in &domain_urls {
+            push_module_type(&base_builder, ModuleType::CcipRead);
+            let ism = MockCcipReadIsm::default();
+            ism.responses
+                .get_offchain_verify_info
+                .lock()
+                .unwrap()
+                .push_back(Err(offchain_lookup_revert(urls)));
+            base_builder
+                .responses
+                .build_ccip_read_ism
+                .lock()
+                .unwrap()
+                .push_back(Ok(Box::new(ism)));
+        }
+        let builder = into_ccip_read_builder(base_builder);
+
+        for (destination, urls) in [1, 2].into_iter().zip(&domain_urls) {
+            let message = HyperlaneMessage {
+                destination,
+                ..Default::default()
+            };
+            let lookup = builder
+                .offchain_lookup_for(H256::zero(), &message)
+                .await
+                .unwrap()
+                .expect("Expected an OffchainLookup");
+            assert_eq!(&lookup.urls, urls, "destination: {destination}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_override_urls_replace_onchain_urls() {
+        let urls = vec!["https://broken.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Ok(br#"{"data":"0x0d"}"#.to_vec()),
+        );
+        let conf = CcipReadConf {
+            ism_gateway_urls: HashMap::from([(
+                H256::zero(),
+                GatewayUrlOverride {
+                    urls: vec!["https://a.example.com/{data}".to_owned()],
+                    append: false,
+                },
+            )]),
+            ..Default::default()
+        };
+        let gateway_client = Arc::new(gateway_client);
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            gateway_client.clone(),
+            &conf,
+            CcipReadMetrics::new(
+                &CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap(),
+            ),
+        ));
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the override URL");
+        assert_eq!(metadata.to_vec(), vec![0x0d]);
+        let requested: Vec<_> = gateway_client
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(url, _)| url.clone())
+            .collect();
+        assert_eq!(requested, vec!["https://a.example.com/0x010203"]);
+    }
+
+    #[tokio::test]
+    async fn test_prioritized_hosts_are_tried_first() {
+        let urls = vec![
+            "https://slow.example.com/{data}".to_owned(),
+            "https://mid.example.com/{data}".to_owned(),
+            "https://fast.example.com/{data}".to_owned(),
+        ];
+        let gateway_client = MockGatewayClient::default();
+        let responses = &gateway_client.responses;
+        for host in ["fast", "mid"] {
+            responses.push_fetch_response(
+                &format!("https://{host}.example.com/0x010203"),
+                Err(GatewayError::Status(StatusCode::INTERNAL_SERVER_ERROR)),
+            );
+        }
+        responses.push_fetch_response(
+            "https://slow.example.com/0x010203",
+            Ok(br#"{"data":"0x0d"}"#.to_vec()),
+        );
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ism_gateway_priorities: HashMap::from([(
+                H256::zero(),
+                vec!["fast.example.com".to_owned(), "mid.example.com".to_owned()],
+            )]),
+            ..Default::default()
+        };
+        let gateway_client = Arc::new(gateway_client);
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            gateway_client.clone(),
+            &conf,
+            CcipReadMetrics::new(
+                &CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap(),
+            ),
+        ));
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the unprioritized gateway");
+        assert_eq!(metadata.to_vec(), vec![0x0d]);
+        let requested: Vec<_> = gateway_client
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(url, _)| url.clone())
+            .collect();
+        assert_eq!(
+            requested,
+            ["fast", "mid", "slow"].map(|host| format!("https://{host}.example.com/0x010203"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ipfs_urls_are_resolved_through_gateway() {
+        let urls = vec![
+            "ipfs://bafymissing/metadata.json".to_owned(),
+            "ipfs://bafyfound/{sender}.json".to_owned(),
+        ];
+        let sender = format!("0x{}", "00".repeat(20));
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://ipfs.example.com/ipfs/bafymissing/metadata.json",
+            Err(GatewayError::Status(StatusCode::NOT_FOUND)),
+        );
+        gateway_client.responses.push_fetch_response(
+            &format!("https://ipfs.example.com/ipfs/bafyfound/{sender}.json"),
+            Ok(br#"{"data":"0x10"}"#.to_vec()),
+        );
+        let requests = gateway_client.requests.clone();
+
+        let conf = CcipReadConf {
+            ipfs_gateway: "https://ipfs.example.com/ipfs/".to_owned(),
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the resolvable CID");
+        assert_eq!(metadata.to_vec(), vec![16]);
+        // Both are sent as GET requests even though neither has `{data}`
+        assert!(requests
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|(_, body)| body.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_data_uris_are_served_inline() {
+        let urls = vec![
+            "data:application/json;base64,not base64!".to_owned(),
+            "data:application/json,%7B%22data%22%3A%220x11%22%7D".to_owned(),
+        ];
+        let gateway_client = MockGatewayClient::default();
+        let requests = gateway_client.requests.clone();
+        let conf = CcipReadConf {
+            // Data URIs have no host to allow
+            gateway_hosts: HostFilter {
+                allowed: vec!["gateway.example.com".parse().unwrap()],
+                denied: vec![],
+            },
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata from the valid data URI");
+        assert_eq!(metadata.to_vec(), vec![17]);
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_burst_of_builds_respects_max_in_flight_per_host() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let router = Router::new().route(
+            "/:data",
+            get({
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+                move || async move {
+                    let now_in_flight = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(now_in_flight, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    Json(json!({ "data": "0x12" }))
+                }
+            }),
+        );
+        let addr = run_gateway(router);
+        let urls = vec![format!("http://{addr}/{{data}}")];
+        let conf = CcipReadConf {
+            max_in_flight_per_host: Some(2),
+            ..Default::default()
+        };
+        // The limit is held by the context, which all builders share
+        let context = test_context(&conf);
+
+        let builds = (0..6).map(|nonce| {
+            let mut base_builder = ccip_read_base_builder(&urls, &conf);
+            base_builder.responses.ccip_read_context = Some(context.clone());
+            let message = HyperlaneMessage {
+                nonce,
+                ..Default::default()
+            };
+            async move {
+                into_ccip_read_builder(base_builder)
+                    .build(
+                        H256::zero(),
+                        &message,
+                        MessageMetadataBuildParams::default(),
+                    )
+                    .await
+            }
+        });
+        for res in futures::future::join_all(builds).await {
+            assert_eq!(res.expect("Expected metadata").to_vec(), vec![0x12]);
+        }
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_burst_of_builds_respects_max_in_flight_total() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let router = Router::new().route(
+            "/:data",
+            get({
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+                move || async move {
+                    let now_in_flight = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(now_in_flight, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    Json(json!({ "data": "0x12" }))
+                }
+            }),
+        );
+        let addr = run_gateway(router);
+        // Two hosts for the same gateway, so no per-host limit could apply
+        let hosts = ["127.0.0.1".to_owned(), "localhost".to_owned()];
+        let conf = CcipReadConf {
+            max_in_flight_total: Some(2),
+            ..Default::default()
+        };
+        let context = test_context(&conf);
+
+        let builds = (0..6).map(|nonce| {
+            let urls = vec![format!(
+                "http://{}:{}/{{data}}",
+                hosts[nonce as usize % 2],
+                addr.port()
+            )];
+            let mut base_builder = ccip_read_base_builder(&urls, &conf);
+            base_builder.responses.ccip_read_context = Some(context.clone());
+            let message = HyperlaneMessage {
+                nonce,
+                ..Default::default()
+            };
+            async move {
+                into_ccip_read_builder(base_builder)
+                    .build(
+                        H256::zero(),
+                        &message,
+                        MessageMetadataBuildParams::default(),
+                    )
+                    .await
+            }
+        });
+        for res in futures::future::join_all(builds).await {
+            assert_eq!(res.expect("Expected metadata").to_vec(), vec![0x12]);
+        }
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_host_is_skipped_until_retry_after() {
+        let hits = Arc::new(AtomicU32::new(0));
+        let router = Router::new()
+            .route(
+                "/limited/:data",
+                get({
+                    let hits = hits.clone();
+                    move || async move {
+                        hits.fetch_add(1, Ordering::SeqCst);
+                        (
+                            StatusCode::TOO_MANY_REQUESTS,
+                            [(reqwest::header::RETRY_AFTER, "2")],
+                        )
+                    }
+                }),
+            )
+            .route(
+                "/ok/:data",
+                get(|| async { Json(json!({ "data": "0x12" })) }),
+            );
+        let addr = run_gateway(router);
+        let urls = vec![
+            format!("http://{addr}/limited/{{data}}"),
+            format!("http://{addr}/ok/{{data}}"),
+        ];
+        let conf = CcipReadConf::default();
+        // The back off is held by the context, which all builders share
+        let context = test_context(&conf);
+        let build = |nonce| {
+            let mut base_builder = ccip_read_base_builder(&urls, &conf);
+            base_builder.responses.ccip_read_context = Some(context.clone());
+            let message = HyperlaneMessage {
+                nonce,
+                ..Default::default()
+            };
+            async move {
+                into_ccip_read_builder(base_builder)
+                    .build(
+                        H256::zero(),
+                        &message,
+                        MessageMetadataBuildParams::default(),
+                    )
+                    .await
+                    .expect("Expected metadata from the other gateway")
+                    .to_vec()
+            }
+        };
+
+        // Rate limited requests are not retried against the same host
+        assert_eq!(build(0).await, vec![0x12]);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        assert_eq!(build(1).await, vec![0x12]);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        assert_eq!(build(2).await, vec![0x12]);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_sender_is_refused() {
+        let gateway_client = MockGatewayClient::default();
+        let requests = gateway_client.requests.clone();
+        let conf = CcipReadConf::default();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        // The `OffchainLookup` names the zero address as its sender
+        let mut base_builder =
+            ccip_read_base_builder(&["https://example.com/{data}".to_owned()], &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+
+        let res = into_ccip_read_builder(base_builder)
+            .build(
+                H256::repeat_byte(1),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await;
+        assert!(matches!(res, Err(MetadataBuildError::Refused(_))));
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failing_host_is_short_circuited() {
+        let hits = Arc::new(AtomicU32::new(0));
+        let router = Router::new().route(
+            "/down",
+            get({
+                let hits = hits.clone();
+                move || async move {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    StatusCode::BAD_GATEWAY
+                }
+            }),
+        );
+        let addr = run_gateway(router);
+        let registry = Registry::new();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, registry.clone()).unwrap();
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            circuit_breaker_failures: 2,
+            ..Default::default()
+        };
+        let context = CcipReadContext::new(&conf, CcipReadMetrics::new(&core_metrics)).unwrap();
+
+        let requests = [gateway_request(format!("http://{addr}/down"))];
+        for _ in 0..2 {
+            assert!(context.fetch_from_gateways(&requests, None).await.is_err());
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        let failures = context
+            .fetch_from_gateways(&requests, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            failures.0.as_slice(),
+            [(_, CandidateFailure::Gateway(GatewayError::CircuitOpen))]
+        ));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        let state: Vec<_> = registry
+            .gather()
+            .iter()
+            .filter(|family| family.get_name() == "hyperlane_ccip_read_gateway_circuit_state")
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_gauge().get_value())
+            .collect();
+        assert_eq!(state, vec![1.0]);
+    }
+
+    #[test]
+    fn test_interpolates_message_placeholders() {
+        let lookup = OffchainLookup {
+            sender: Address::zero(),
+            urls: vec![
+                "https://example.com/{domain}/{nonce}/{msgId}/{data}?v={version}".to_owned(),
+            ],
+            call_data: vec![1].into(),
+            callback_function: [0; 4],
+            extra_data: Default::default(),
+        };
+        let message = HyperlaneMessage {
+            destination: 42,
+            nonce: 7,
+            ..Default::default()
+        };
+        let msg_id = format!("{:?}", message.id());
+        assert_eq!(msg_id.len(), 66);
+
+        let requests = GatewayRequest::for_lookup(
+            &lookup,
+            &message,
+            &DestinationState::default(),
+            &HashMap::new(),
+            None,
+        );
+        assert_eq!(
+            requests[0].url,
+            format!("https://example.com/42/7/{msg_id}/0x01?v={{version}}")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_interpolates_destination_state() {
+        let urls = vec!["https://a.example.com/{destRoot}/{destBlock}/{data}".to_owned()];
+        let root = H256::repeat_byte(0xab);
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            &format!("https://a.example.com/{root:?}/19000000/0x010203"),
+            Ok(br#"{"data":"0x11"}"#.to_vec()),
+        );
+        let requests = gateway_client.requests.clone();
+        let conf = CcipReadConf {
+            include_destination_state: true,
+            ..Default::default()
+        };
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(
+                &CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap(),
+            ),
+        ));
+        let destination_responses = (
+            base_builder.responses.destination_checkpoint_root.clone(),
+            base_builder.responses.destination_block_number.clone(),
+        );
+        destination_responses.0.lock().unwrap().push_back(Ok(root));
+        destination_responses
+            .1
+            .lock()
+            .unwrap()
+            .push_back(Ok(19_000_000));
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata");
+        assert_eq!(metadata.to_vec(), vec![17]);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        // Each is queried once for the lookup
+        assert!(destination_responses.0.lock().unwrap().is_empty());
+        assert!(destination_responses.1.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_interpolates_known_destination_state_only() {
+        let lookup = OffchainLookup {
+            sender: Address::zero(),
+            urls: vec!["https://example.com/{destRoot}/{destBlock}/{data}".to_owned()],
+            call_data: vec![1].into(),
+            callback_function: [0; 4],
+            extra_data: Default::default(),
+        };
+        let destination = DestinationState {
+            root: None,
+            block: Some(42),
+        };
+
+        let requests = GatewayRequest::for_lookup(
+            &lookup,
+            &HyperlaneMessage::default(),
+            &destination,
+            &HashMap::new(),
+            None,
+        );
+        assert_eq!(requests[0].url, "https://example.com/{destRoot}/42/0x01");
+    }
+
+    #[test]
+    fn test_interpolate_leaves_unknown_placeholders_untouched() {
+        let values = [("a", "x")];
+        assert_eq!(interpolate("{a}{b}{{a}}}{", &values), "x{b}{x}}{");
+        assert_eq!(interpolate("no placeholders", &values), "no placeholders");
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_build_span_records_lookup_without_secrets() {
+        let router =
+            Router::new().route("/:data", get(|| async { Json(json!({ "data": "0x12" })) }));
+        let addr = run_gateway(router);
+        let urls = vec![format!("http://{addr}/{{data}}")];
+        let mut headers = HeaderMap::new();
+        let mut token = HeaderValue::from_static("Bearer secret-token");
+        token.set_sensitive(true);
+        headers.insert(AUTHORIZATION, token);
+        let conf = CcipReadConf {
+            gateway_headers: HashMap::from([(addr.ip().to_string(), headers)]),
+            ..Default::default()
+        };
+
+        let metadata = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(metadata.to_vec(), vec![0x12]);
+
+        assert!(logs_contain(&format!(
+            "message_id={:?}",
+            HyperlaneMessage::default().id()
+        )));
+        assert!(logs_contain("metadata=gateway"));
+        assert!(logs_contain("offchain_lookup=ism"));
+        assert!(logs_contain("url_count=1"));
+        assert!(logs_contain(&format!("gateway=http://{addr}/{{data}}")));
+        assert!(logs_contain("latency_ms="));
+        assert!(!logs_contain("secret-token"));
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_gateway_attempts_are_logged_with_structured_fields() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Err(GatewayError::Status(StatusCode::SERVICE_UNAVAILABLE)),
+        );
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(
+                &CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap(),
+            ),
+        ));
+        let message = HyperlaneMessage::default();
+        into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &message,
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect_err("Expected no metadata while the gateway is down");
+
+        let expected = [
+            format!("message_id=\"{}\"", bytes_to_hex(message.id().as_bytes())),
+            format!("ism={:?}", H256::zero()),
+            "url_host=a.example.com".to_owned(),
+            "outcome=\"http_error\"".to_owned(),
+            "status=503".to_owned(),
+            "latency_ms=".to_owned(),
+        ];
+        logs_assert(|lines: &[&str]| {
+            let attempts: Vec<_> = lines
+                .iter()
+                .filter(|line| line.contains("CCIP-read gateway request attempt finished"))
+                .collect();
+            if attempts.len() != 1 {
+                return Err(format!("Expected one attempt, got {}", attempts.len()));
+            }
+            match expected
+                .iter()
+                .find(|field| !attempts[0].contains(field.as_str()))
+            {
+                Some(field) => Err(format!("Missing {field} in {}", attempts[0])),
+                None => Ok(()),
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_probe_reports_reachability_per_gateway() {
+        let router =
+            Router::new().route("/:data", get(|| async { Json(json!({ "data": "0x01" })) }));
+        let addr = run_gateway(router);
+        // Nothing listens on a port once its listener is dropped
+        let closed_addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let urls = vec![
+            format!("http://{addr}/{{data}}"),
+            format!("http://{closed_addr}/{{data}}"),
+        ];
+
+        let health = test_context(&Default::default())
+            .probe_gateways(&urls)
+            .await;
+        assert!(!health.all_reachable());
+        let [reachable, unreachable] = health.gateways.as_slice() else {
+            panic!("Expected one probe per URL, got {health:?}");
+        };
+        assert_eq!(reachable.url, urls[0]);
+        assert!(reachable.reachable);
+        // The gateway serves GET but responds to the HEAD request all the same
+        assert!(reachable.status.is_some());
+        assert_eq!(unreachable.url, urls[1]);
+        assert!(!unreachable.reachable);
+        assert!(unreachable.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_build_reports_host_of_winning_gateway() {
+        let urls = vec![
+            "https://a.example.com/{data}".to_owned(),
+            "https://b.example.com/{data}?key=secret".to_owned(),
+        ];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Err(GatewayError::Status(StatusCode::INTERNAL_SERVER_ERROR)),
+        );
+        gateway_client.responses.push_fetch_response(
+            "https://b.example.com/0x010203?key=secret",
+            Ok(br#"{"data":"0x0d"}"#.to_vec()),
+        );
+
+        let conf = CcipReadConf::default();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+        let builder = into_ccip_read_builder(base_builder);
+
+        let (metadata, source) = builder
+            .build_with_source(H256::zero(), &HyperlaneMessage::default())
+            .await
+            .expect("Expected metadata");
+        assert_eq!(metadata.to_vec(), vec![13]);
+        assert_eq!(
+            source,
+            MetadataSource {
+                host: "b.example.com".to_owned(),
+                cached: false,
+                fallback: false,
+            }
+        );
+
+        // The cached metadata is still attributed to the gateway it came from
+        let (_, source) = builder
+            .build_with_source(H256::zero(), &HyperlaneMessage::default())
+            .await
+            .expect("Expected metadata");
+        assert_eq!(source.host, "b.example.com");
+        assert!(source.cached);
+    }
+
+    fn lookup_with_urls(urls: &[&str]) -> OffchainLookup {
+        OffchainLookup {
+            sender: Address::repeat_byte(0xab),
+            urls: urls.iter().map(|url| url.to_string()).collect(),
+            call_data: vec![1, 2, 3].into(),
+            callback_function: [0; 4],
+            extra_data: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_forced_get_overrides_post_for_templates_without_data() {
+        let lookup = lookup_with_urls(&[
+            "https://Get.Example.com/{sender}",
+            "https://other.example.com/{sender}",
+        ]);
+        let methods = HashMap::from([("get.example.com".to_owned(), GatewayMethod::Get)]);
+        let sender = format!("0x{}", "ab".repeat(20));
+
+        let requests = GatewayRequest::for_lookup(
+            &lookup,
+            &HyperlaneMessage::default(),
+            &DestinationState::default(),
+            &methods,
+            None,
+        );
+        assert_eq!(requests[0].url, format!("https://Get.Example.com/{sender}"));
+        assert_eq!(requests[0].body, None);
+        // Gateways without an override still follow the template
+        assert!(requests[1].body.is_some());
+    }
+
+    #[test]
+    fn test_forced_post_overrides_get_for_templates_with_data() {
+        let lookup = lookup_with_urls(&[
+            "https://post.example.com/{data}",
+            "https://other.example.com/{data}",
+        ]);
+        let methods = HashMap::from([("post.example.com".to_owned(), GatewayMethod::Post)]);
+        let sender = format!("0x{}", "ab".repeat(20));
+
+        let requests = GatewayRequest::for_lookup(
+            &lookup,
+            &HyperlaneMessage::default(),
+            &DestinationState::default(),
+            &methods,
+            None,
+        );
+        assert_eq!(requests[0].url, "https://post.example.com/0x010203");
+        assert_eq!(
+            requests[0].body,
+            Some(json!({ "sender": sender, "data": "0x010203" }))
+        );
+        assert_eq!(requests[1].body, None);
+    }
+
+    #[tokio::test]
+    async fn test_gateways_down_is_reported_as_awaiting_offchain_data() {
+        let router =
+            Router::new().route("/:data", get(|| async { StatusCode::SERVICE_UNAVAILABLE }));
+        let addr = run_gateway(router);
+        let urls = vec![format!("http://{addr}/{{data}}")];
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+
+        let err = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect_err("Expected no metadata while the gateway is down");
+        assert_eq!(err, MetadataBuildError::AwaitingOffchainData);
+
+        // The queue length metric is labelled with the status of the operation
+        let status = PendingOperationStatus::Retry(ReprepareReason::AwaitingOffchainGatewayData);
+        assert_eq!(status.to_string(), format!("Retry({err})"));
+        assert_eq!(status.to_string(), "Retry(Awaiting offchain gateway data)");
+    }
+
+    #[derive(Debug)]
+    struct StaticMetadataSource(Vec<u8>);
+
+    #[async_trait]
+    impl FallbackMetadataSource for StaticMetadataSource {
+        async fn metadata(&self, _: H256, _: &HyperlaneMessage) -> Option<Vec<u8>> {
+            Some(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_supplies_metadata_when_gateways_fail() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Err(GatewayError::Status(StatusCode::SERVICE_UNAVAILABLE)),
+        );
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(
+                &CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap(),
+            ),
+        );
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext {
+            fallback_source: Some(Arc::new(StaticMetadataSource(vec![0x0e]))),
+            ..context
+        });
+        let builder = into_ccip_read_builder(base_builder);
+
+        let (metadata, source) = builder
+            .build_with_source(H256::zero(), &HyperlaneMessage::default())
+            .await
+            .expect("Expected metadata from the fallback");
+        assert_eq!(metadata.to_vec(), vec![0x0e]);
+        assert!(source.fallback);
+        assert!(!source.cached);
+    }
+
+    #[tokio::test]
+    async fn test_stuck_message_is_reported_once() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let reports: Arc<Mutex<Vec<Value>>> = Default::default();
+        let router = {
+            let reports = reports.clone();
+            Router::new().route(
+                "/stuck",
+                post(move |Json(report): Json<Value>| async move {
+                    reports.lock().unwrap().push(report);
+                }),
+            )
+        };
+        let webhook = format!("http://{}/stuck", run_gateway(router));
+        let gateway_client = Arc::new(MockGatewayClient::default());
+        for _ in 0..3 {
+            gateway_client.responses.push_fetch_response(
+                "https://a.example.com/0x010203",
+                Err(GatewayError::Status(StatusCode::SERVICE_UNAVAILABLE)),
+            );
+        }
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            negative_cache_ttl: Duration::ZERO,
+            stuck_message_webhook: Some(Url::parse(&webhook).unwrap()),
+            stuck_message_failures: 2,
+            ..Default::default()
+        };
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            gateway_client,
+            &conf,
+            CcipReadMetrics::new(
+                &CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap(),
+            ),
+        ));
+        let builder = into_ccip_read_builder(base_builder);
+        let message = HyperlaneMessage::default();
+
+        for reported in [0, 1, 1] {
+            let err = builder
+                .build(
+                    H256::zero(),
+                    &message,
+                    MessageMetadataBuildParams::default(),
+                )
+                .await
+                .expect_err("Expected no metadata while the gateway is down");
+            assert_eq!(err, MetadataBuildError::AwaitingOffchainData);
+            // Reports are sent in the background
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert_eq!(reports.lock().unwrap().len(), reported);
+        }
+
+        let report = reports.lock().unwrap()[0].clone();
+        assert_eq!(report["messageId"], format!("{:?}", message.id()));
+        assert_eq!(report["ismAddress"], format!("{:?}", H256::zero()));
+        assert_eq!(report["failures"], 2);
+        assert_eq!(report["reasons"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_paginated_response_is_assembled() {
+        let router = Router::new()
+            .route(
+                "/pages/:data",
+                get(|| async { Json(json!({ "data": "0x0102", "next": "/pages/2/more" })) }),
+            )
+            .route(
+                "/pages/2/more",
+                get(|| async { Json(json!({ "data": "0x03" })) }),
+            )
+            .route(
+                "/loop/:data",
+                get(|| async { Json(json!({ "data": "0x04", "next": "/loop/0x010203" })) }),
+            );
+        let addr = run_gateway(router);
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            max_response_pages: 4,
+            ..Default::default()
+        };
+        let context = test_context(&conf);
+
+        let requests = [gateway_request(format!("http://{addr}/pages/0x010203"))];
+        let (metadata, _) = context
+            .fetch_from_gateways(&requests, None)
+            .await
+            .expect("Expected metadata assembled from both pages");
+        assert_eq!(metadata, vec![1, 2, 3]);
+
+        // A page linking back to itself is not fetched again
+        let requests = [gateway_request(format!("http://{addr}/loop/0x010203"))];
+        let failures = context
+            .fetch_from_gateways(&requests, None)
+            .await
+            .expect_err("Expected the pagination loop to be rejected");
+        assert!(matches!(
+            failures.0.as_slice(),
+            [(
+                _,
+                CandidateFailure::Gateway(GatewayError::InvalidResponse(_))
+            )]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_paginated_websocket_response_is_assembled() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let pages = [
+                json!({ "data": "0x0102", "next": "/2" }),
+                json!({ "data": "0x03" }),
+            ];
+            for page in pages {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                if let Some(Ok(_)) = socket.next().await {
+                    socket
+                        .send(tokio_tungstenite::tungstenite::Message::Text(
+                            page.to_string(),
+                        ))
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            max_response_pages: 4,
+            websocket_gateways: true,
+            ..Default::default()
+        };
+        let context = test_context(&conf);
+
+        let url = format!("ws://{addr}/0x010203");
+        let body = json!({ "sender": "0x01", "data": "0x010203" });
+        let requests = [GatewayRequest::new(url.clone(), url, Some(body))];
+        let (metadata, _) = context
+            .fetch_from_gateways(&requests, None)
+            .await
+            .expect("Expected metadata assembled from both pages");
+        assert_eq!(metadata, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_requests_identify_relayer_and_message() {
+        let seen = Arc::new(std::sync::Mutex::new(None));
+        let router = Router::new().route(
+            "/:data",
+            get({
+                let seen = seen.clone();
+                move |headers: HeaderMap| async move {
+                    let header = |name: &str| {
+                        headers
+                            .get(name)
+                            .and_then(|value| value.to_str().ok())
+                            .map(str::to_owned)
+                    };
+                    *seen.lock().unwrap() = Some((header("user-agent"), header("x-request-id")));
+                    Json(json!({ "data": "0x01" }))
+                }
+            }),
+        );
+        let addr = run_gateway(router);
+        let urls = vec![format!("http://{addr}/{{data}}")];
+        let conf = CcipReadConf {
+            user_agent: "test-relayer/1.0".to_owned(),
+            ..Default::default()
+        };
+        let message = HyperlaneMessage::default();
+
+        ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &message,
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected metadata");
+        let (user_agent, request_id) = seen.lock().unwrap().clone().unwrap();
+        assert_eq!(user_agent.as_deref(), Some("test-relayer/1.0"));
+        assert_eq!(request_id, Some(format!("{:?}", message.id())));
+    }
+
+    #[tokio::test]
+    async fn test_batch_build_returns_results_in_order() {
+        let urls = vec!["https://a.example.com/{nonce}/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0/0x010203",
+            Ok(br#"{"data":"0x01"}"#.to_vec()),
+        );
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/1/0x010203",
+            Err(GatewayError::Status(StatusCode::NOT_FOUND)),
+        );
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/2/0x010203",
+            Ok(br#"{"data":"0x03"}"#.to_vec()),
+        );
+
+        let conf = CcipReadConf {
+            batch_build_concurrency: 2,
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let mut base_builder = MockBaseMetadataBuilder::new();
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+        // Each lookup calls the ISM once. Concurrent lookups may all check
+        // its module type before it's cached.
+        for _ in 0..3 {
+            push_module_type(&base_builder, ModuleType::CcipRead);
+            let ism = MockCcipReadIsm::default();
+            ism.responses
+                .get_offchain_verify_info
+                .lock()
+                .unwrap()
+                .push_back(Err(offchain_lookup_revert(&urls)));
+            base_builder
+                .responses
+                .build_ccip_read_ism
+                .lock()
+                .unwrap()
+                .push_back(Ok(Box::new(ism)));
+        }
+        let builder = into_ccip_read_builder(base_builder);
+
+        let messages: Vec<_> = (0..3)
+            .map(|nonce| HyperlaneMessage {
+                nonce,
+                ..Default::default()
+            })
+            .collect();
+        let items: Vec<_> = messages
+            .iter()
+            .map(|message| (H256::zero(), message))
+            .collect();
+        let results: Vec<_> = builder
+            .build_batch(&items)
+            .await
+            .into_iter()
+            .map(|res| res.map(|metadata| metadata.to_vec()))
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                Ok(vec![1]),
+                Err(MetadataBuildError::AwaitingOffchainData),
+                Ok(vec![3]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_ccip_read_ism_is_not_called() {
+        let mut base_builder = MockBaseMetadataBuilder::new();
+        base_builder.responses.ccip_read_context = Some(test_context(&Default::default()));
+        push_module_type(&base_builder, ModuleType::Routing);
+        // No `build_ccip_read_ism` response is set, so building the
+        // CCIP-read ISM would panic
+        let res = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await;
+        assert_eq!(
+            res.unwrap_err(),
+            MetadataBuildError::NotCcipReadIsm(ModuleType::Routing)
+        );
+    }
+
+    #[test]
+    fn test_relative_templates_are_resolved_against_base_url() {
+        let lookup =
+            lookup_with_urls(&["/ccip/{sender}/{data}", "https://other.example.com/{data}"]);
+        let base_url = Url::parse("https://gateway.example.com/api/").unwrap();
+        let sender = format!("0x{}", "ab".repeat(20));
+
+        let requests = GatewayRequest::for_lookup(
+            &lookup,
+            &HyperlaneMessage::default(),
+            &DestinationState::default(),
+            &HashMap::new(),
+            Some(&base_url),
+        );
+        assert_eq!(
+            requests[0].url,
+            format!("https://gateway.example.com/ccip/{sender}/0x010203")
+        );
+        assert_eq!(requests[0].host, "gateway.example.com");
+        // The template is logged instead of the URL, so it stays as is
+        assert_eq!(requests[0].template, "/ccip/{sender}/{data}");
+        assert_eq!(requests[1].url, "https://other.example.com/0x010203");
+    }
+
+    #[test]
+    fn test_relative_templates_without_base_url_are_untouched() {
+        let lookup = lookup_with_urls(&["ccip/{data}"]);
+        let requests = GatewayRequest::for_lookup(
+            &lookup,
+            &HyperlaneMessage::default(),
+            &DestinationState::default(),
+            &HashMap::new(),
+            None,
+        );
+        assert_eq!(requests[0].url, "ccip/0x010203");
+    }
+
+    #[tokio::test]
+    async fn test_replays_gateway_responses_from_fixtures() {
+        // Nothing listens there, so only a fixture can provide the metadata
+        let urls = vec!["http://127.0.0.1:1/{data}".to_owned()];
+        let dir = std::env::temp_dir().join(format!("ccip-read-fixtures-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(fixture::fixture_name("http://127.0.0.1:1/0x010203", None)),
+            r#"{"data":"0x54"}"#,
+        )
+        .unwrap();
+        let conf = CcipReadConf {
+            replay_fixture_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+
+        let res = ccip_read_builder(&urls, &conf)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            res.expect("Expected the fixture metadata").to_vec(),
+            vec![0x54]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metadata_of_unexpected_length_is_skipped() {
+        let urls = vec![
+            "https://short.example.com/{data}".to_owned(),
+            "https://good.example.com/{data}".to_owned(),
+        ];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://short.example.com/0x010203",
+            Ok(br#"{"data":"0x0102"}"#.to_vec()),
+        );
+        gateway_client.responses.push_fetch_response(
+            "https://good.example.com/0x010203",
+            Ok(br#"{"data":"0x010203"}"#.to_vec()),
+        );
+        let conf = CcipReadConf {
+            ism_metadata_lengths: HashMap::from([(
+                H256::zero(),
+                MetadataLength { min: 3, max: 3 },
+            )]),
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected the metadata of the expected length");
+        assert_eq!(metadata.to_vec(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_expired_attestation_is_skipped() {
+        let urls = vec![
+            "https://stale.example.com/{data}".to_owned(),
+            "https://fresh.example.com/{data}".to_owned(),
+        ];
+        // The attestations lead with their expiry as a `uint64`
+        let attestation = |expires_at: u64| {
+            let metadata = [expires_at.to_be_bytes().as_slice(), &[0xaa]].concat();
+            format!(r#"{{"data":"{}"}}"#, bytes_to_hex(&metadata)).into_bytes()
+        };
+        let clock = Arc::new(MockClock::default());
+        let now = clock.unix_time().as_secs();
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://stale.example.com/0x010203",
+            Ok(attestation(now - 1)),
+        );
+        gateway_client.responses.push_fetch_response(
+            "https://fresh.example.com/0x010203",
+            Ok(attestation(now + 600)),
+        );
+        let conf = CcipReadConf {
+            ism_metadata_expiry: HashMap::from([(
+                H256::zero(),
+                MetadataExpiry {
+                    offset: 0,
+                    length: 8,
+                },
+            )]),
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context =
+            Some(CcipReadContext::with_gateway_client_and_clock(
+                Arc::new(gateway_client),
+                &conf,
+                CcipReadMetrics::new(&core_metrics),
+                clock,
+            ));
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected the unexpired attestation");
+        assert_eq!(metadata.to_vec()[..8], (now + 600).to_be_bytes());
+    }
+
+    #[test]
+    fn test_check_expiry() {
+        let expiry = MetadataExpiry {
+            offset: 0,
+            length: 1,
+        };
+        assert!(check_expiry(expiry, &[10], 9).is_ok());
+        assert!(matches!(
+            check_expiry(expiry, &[10], 10),
+            Err(CandidateFailure::Expired { expires_at: 10 })
+        ));
+        assert!(matches!(
+            check_expiry(expiry, &[], 0),
+            Err(CandidateFailure::MissingExpiry)
+        ));
+    }
+
+    #[test]
+    fn test_expiry_derived_cache_ttl_is_clamped() {
+        let (min, max) = (Duration::from_secs(1), Duration::from_secs(300));
+        let now = 1_000;
+        assert_eq!(
+            clamped_expiry_ttl(now + 60, now, min, max),
+            Duration::from_secs(60)
+        );
+        // An attestation claiming to be valid practically forever
+        assert_eq!(clamped_expiry_ttl(u64::MAX, now, min, max), max);
+        // Already expired, which is caught when the metadata is reused
+        assert_eq!(clamped_expiry_ttl(now - 1, now, min, max), min);
+    }
+
+    #[test]
+    fn test_supports_only_ccip_read_module_type() {
+        let builder = into_ccip_read_builder(MockBaseMetadataBuilder::new());
+        assert!(builder.supports(ModuleType::CcipRead));
+        for module_type in [
+            ModuleType::Unused,
+            ModuleType::Routing,
+            ModuleType::Aggregation,
+            ModuleType::LegacyMultisig,
+            ModuleType::MerkleRootMultisig,
+            ModuleType::MessageIdMultisig,
+            ModuleType::Null,
+        ] {
+            assert!(!builder.supports(module_type), "{module_type:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotated_gateways_start_by_message_id() {
+        let urls = ["a", "b", "c"].map(|host| format!("https://{host}.example.com/"));
+        let gateway_client = MockGatewayClient::default();
+        for url in &urls {
+            for _ in 0..2 {
+                gateway_client
+                    .responses
+                    .push_fetch_response(url, Err(GatewayError::Timeout));
+            }
+        }
+        let requests = gateway_client.requests.clone();
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            rotate_gateways: true,
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        );
+
+        let mut tried = Vec::new();
+        for message_id in [H256::from_low_u64_be(3), H256::from_low_u64_be(4)] {
+            let rotated = context.rotate_requests(
+                H256::zero(),
+                message_id,
+                urls.iter().cloned().map(gateway_request).collect(),
+            );
+            assert!(context.fetch_from_gateways(&rotated, None).await.is_err());
+            let urls: Vec<_> = requests
+                .lock()
+                .unwrap()
+                .drain(..)
+                .map(|(url, _)| url)
+                .collect();
+            tried.push(urls);
+        }
+        let [a, b, c] = urls;
+        assert_eq!(tried[0], [a.clone(), b.clone(), c.clone()]);
+        assert_eq!(tried[1], [b, c, a]);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_agreed_on_by_quorum_is_used() {
+        let gateway_client = MockGatewayClient::default();
+        let responses = &gateway_client.responses;
+        responses.push_fetch_response("https://a.example.com/", Ok(br#"{"data":"0x0a"}"#.to_vec()));
+        responses.push_fetch_response("https://b.example.com/", Ok(br#"{"data":"0x0c"}"#.to_vec()));
+        responses.push_fetch_response("https://c.example.com/", Ok(br#"{"data":"0x0c"}"#.to_vec()));
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            gateway_quorum: 2,
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        );
+
+        let requests =
+            ["a", "b", "c"].map(|host| gateway_request(format!("https://{host}.example.com/")));
+        let (metadata, host) = context.fetch_from_gateways(&requests, None).await.unwrap();
+        assert_eq!((metadata, host.as_str()), (vec![12], "c.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_metadata_without_quorum_is_not_used() {
+        let gateway_client = MockGatewayClient::default();
+        let responses = &gateway_client.responses;
+        responses.push_fetch_response("https://a.example.com/", Ok(br#"{"data":"0x0a"}"#.to_vec()));
+        responses.push_fetch_response("https://b.example.com/", Ok(br#"{"data":"0x0c"}"#.to_vec()));
+        let conf = CcipReadConf {
+            max_attempts: 1,
+            gateway_quorum: 2,
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        );
+
+        let requests =
+            ["a", "b"].map(|host| gateway_request(format!("https://{host}.example.com/")));
+        let failures = context
+            .fetch_from_gateways(&requests, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            failures.0.as_slice(),
+            [
+                (
+                    _,
+                    CandidateFailure::NoQuorum {
+                        agreeing: 1,
+                        quorum: 2
+                    }
+                ),
+                (
+                    _,
+                    CandidateFailure::NoQuorum {
+                        agreeing: 1,
+                        quorum: 2
+                    }
+                ),
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stale_metadata_is_used_while_gateways_are_down() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        for _ in 0..2 {
+            gateway_client
+                .responses
+                .push_fetch_response("https://a.example.com/0x010203", Err(GatewayError::Timeout));
+        }
+        // The metadata leads with its expiry as a `uint64`
+        let clock = Arc::new(MockClock::default());
+        let now = clock.unix_time().as_secs();
+        let fresh = [(now + 600).to_be_bytes().as_slice(), &[0xaa]].concat();
+        let conf = CcipReadConf {
+            metadata_stale_grace: Duration::from_secs(60),
+            ism_metadata_expiry: HashMap::from([(
+                H256::zero(),
+                MetadataExpiry {
+                    offset: 0,
+                    length: 8,
+                },
+            )]),
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let context = CcipReadContext::with_gateway_client_and_clock(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+            clock.clone(),
+        );
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(context.clone());
+        let builder = into_ccip_read_builder(base_builder);
+        let message = HyperlaneMessage::default();
+        let key = LookupKey::new(H256::zero(), "getOffchainVerifyInfo", &message);
+        let insert_stale = || {
+            context.metadata_cache.insert_with_ttl(
+                key.clone(),
+                (fresh.clone(), "a.example.com".to_owned()),
+                Duration::ZERO,
+            )
+        };
+
+        insert_stale().await;
+        let (metadata, source) = builder
+            .build_with_source(H256::zero(), &message)
+            .await
+            .expect("Expected the stale metadata");
+        assert_eq!(metadata.to_vec(), fresh);
+        assert!(source.cached);
+
+        // Once the metadata itself expired, it isn't used anymore
+        clock.advance(Duration::from_secs(600));
+        insert_stale().await;
+        assert!(builder
+            .build_with_source(H256::zero(), &message)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stale_metadata_is_not_used_when_gateways_answer() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Err(GatewayError::Status(StatusCode::NOT_FOUND)),
+        );
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Err(GatewayError::InvalidResponse(
+                "missing data field".to_owned(),
+            )),
+        );
+        let conf = CcipReadConf {
+            metadata_stale_grace: Duration::from_secs(60),
+            negative_cache_ttl: Duration::ZERO,
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        );
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(context.clone());
+        let builder = into_ccip_read_builder(base_builder);
+        let message = HyperlaneMessage::default();
+        let key = LookupKey::new(H256::zero(), "getOffchainVerifyInfo", &message);
+
+        context
+            .metadata_cache
+            .insert_with_ttl(
+                key,
+                (vec![0xaa], "a.example.com".to_owned()),
+                Duration::ZERO,
+            )
+            .await;
+        // The gateway rejects the message, then answers with an invalid body
+        for _ in 0..2 {
+            assert_eq!(
+                builder
+                    .build_with_source(H256::zero(), &message)
+                    .await
+                    .map(|(metadata, _)| metadata.to_vec()),
+                Err(MetadataBuildError::AwaitingOffchainData)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_is_encoded_as_callback_calldata() {
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/",
+            Ok(br#"{"data":"0x0102"}"#.to_vec()),
+        );
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &CcipReadConf::default(),
+            CcipReadMetrics::new(&core_metrics),
+        );
+        let lookup = OffchainLookup {
+            sender: Address::zero(),
+            urls: vec!["https://a.example.com/".to_owned()],
+            call_data: vec![1, 2, 3].into(),
+            callback_function: [0xca, 0x11, 0xba, 0xcc],
+            extra_data: vec![0xee].into(),
+        };
+        let request = GatewayRequest {
+            callback: Some(LookupCallback::new(&lookup)),
+            ..gateway_request("https://a.example.com/".to_owned())
+        };
+
+        let metadata = context.fetch(&request).await.unwrap();
+        assert_eq!(metadata[..4], [0xca, 0x11, 0xba, 0xcc]);
+        let args = abi::decode(&[ParamType::Bytes, ParamType::Bytes], &metadata[4..]).unwrap();
+        assert_eq!(
+            args,
+            vec![Token::Bytes(vec![1, 2]), Token::Bytes(vec![0xee])]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalidated_message_is_built_with_fresh_metadata() {
+        test_utils::run_test_db(|db| async move {
+            let urls = vec!["https://a.example.com/{data}".to_owned()];
+            let gateway_client = MockGatewayClient::default();
+            for data in ["0x0d", "0x0e"] {
+                gateway_client.responses.push_fetch_response(
+                    "https://a.example.com/0x010203",
+                    Ok(format!(r#"{{"data":"{data}"}}"#).into_bytes()),
+                );
+            }
+            let conf = CcipReadConf::default();
+            let store = OffchainLookupStore::new(db, conf.offchain_lookup_cache_ttl);
+            let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+            let context = CcipReadContext::with_gateway_client(
+                Arc::new(gateway_client),
+                &conf,
+                CcipReadMetrics::new(&core_metrics),
+            )
+            .with_offchain_lookup_store(store.clone());
+            let mut base_builder = ccip_read_base_builder(&urls, &conf);
+            base_builder.responses.ccip_read_context = Some(context.clone());
+            // The ISM is called again once its lookup is dropped
+            let ism = MockCcipReadIsm::default();
+            ism.responses
+                .get_offchain_verify_info
+                .lock()
+                .unwrap()
+                .push_back(Err(offchain_lookup_revert(&urls)));
+            base_builder
+                .responses
+                .build_ccip_read_ism
+                .lock()
+                .unwrap()
+                .push_back(Ok(Box::new(ism)));
+            let builder = into_ccip_read_builder(base_builder);
+            let message = HyperlaneMessage::default();
+            let key = LookupKey::new(H256::zero(), "getOffchainVerifyInfo", &message);
+
+            let (metadata, _) = builder
+                .build_with_source(H256::zero(), &message)
+                .await
+                .unwrap();
+            assert_eq!(metadata.to_vec(), vec![13]);
+            let (metadata, source) = builder
+                .build_with_source(H256::zero(), &message)
+                .await
+                .unwrap();
+            assert_eq!(metadata.to_vec(), vec![13]);
+            assert!(source.cached);
+            store.flush().await;
+            assert!(store.get(&key).await.is_some());
+
+            // The lookup and the metadata
+            assert_eq!(context.invalidate_message(message.id()).await, 2);
+            store.flush().await;
+            assert!(store.get(&key).await.is_none());
+            let (metadata, source) = builder
+                .build_with_source(H256::zero(), &message)
+                .await
+                .unwrap();
+            assert_eq!(metadata.to_vec(), vec![14]);
+            assert!(!source.cached);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_caches_offchain_lookups_of_every_message() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let conf = CcipReadConf {
+            batch_build_concurrency: 2,
+            ..Default::default()
+        };
+        let messages: Vec<_> = (0..5)
+            .map(|nonce| HyperlaneMessage {
+                nonce,
+                ..Default::default()
+            })
+            .collect();
+
+        let mut base_builder = MockBaseMetadataBuilder::new();
+        base_builder.responses.ccip_read_context = Some(test_context(&conf));
+        // Concurrent calls may each check the module type before it's cached
+        for _ in &messages {
+            push_module_type(&base_builder, ModuleType::CcipRead);
+            let ism = MockCcipReadIsm::default();
+            ism.responses
+                .get_offchain_verify_info
+                .lock()
+                .unwrap()
+                .push_back(Err(offchain_lookup_revert(&urls)));
+            base_builder
+                .responses
+                .build_ccip_read_ism
+                .lock()
+                .unwrap()
+                .push_back(Ok(Box::new(ism)));
+        }
+        let builder = into_ccip_read_builder(base_builder);
+
+        let prefetched = builder
+            .prefetch_offchain_info(H256::zero(), &messages.iter().collect::<Vec<_>>())
+            .await;
+        assert_eq!(prefetched, messages.len());
+
+        let context = builder.base_builder().ccip_read_context();
+        for message in &messages {
+            let lookup_key = context.offchain_lookup_key(&LookupKey::new(
+                H256::zero(),
+                "getOffchainVerifyInfo",
+                message,
+            ));
+            let info = context.offchain_lookups.get(&lookup_key).await.unwrap();
+            assert_eq!(info.urls, urls);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rebuilt_message_skips_recent_failure() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Err(GatewayError::Status(StatusCode::NOT_FOUND)),
+        );
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Ok(br#"{"data":"0x0d"}"#.to_vec()),
+        );
+        let conf = CcipReadConf::default();
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let context = CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        );
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(context);
+        // The ISM is called again for the rebuild
+        let ism = MockCcipReadIsm::default();
+        ism.responses
+            .get_offchain_verify_info
+            .lock()
+            .unwrap()
+            .push_back(Err(offchain_lookup_revert(&urls)));
+        base_builder
+            .responses
+            .build_ccip_read_ism
+            .lock()
+            .unwrap()
+            .push_back(Ok(Box::new(ism)));
+        let builder = into_ccip_read_builder(base_builder);
+        let message = HyperlaneMessage::default();
+
+        // The failure is cached, so building again doesn't query the gateway
+        for _ in 0..2 {
+            assert!(builder
+                .build_with_source(H256::zero(), &message)
+                .await
+                .is_err());
+        }
+        let (metadata, source) = builder
+            .rebuild_with_source(H256::zero(), &message)
+            .await
+            .unwrap();
+        assert_eq!(metadata.to_vec(), vec![13]);
+        assert!(!source.cached);
+    }
+
+    #[tokio::test]
+    async fn test_cache_dump_summarizes_entries() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Ok(br#"{"data":"0x0d0e"}"#.to_vec()),
+        );
+        let conf = CcipReadConf::default();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(
+                &CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap(),
+            ),
+        ));
+        let builder = into_ccip_read_builder(base_builder);
+        let message = HyperlaneMessage::default();
+        builder
+            .build(
+                H256::zero(),
+                &message,
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .unwrap();
+
+        let mut dump = builder
+            .base_builder()
+            .ccip_read_context()
+            .dump_cache()
+            .await;
+        dump.sort_by(|a, b| a.cache.cmp(&b.cache));
+        let [metadata, lookup] = dump.as_slice() else {
+            panic!("Expected a cached lookup and metadata, got {dump:?}");
+        };
+        assert_eq!(metadata.cache, "metadata");
+        assert_eq!(metadata.message_id, message.id());
+        assert_eq!(metadata.metadata_len, Some(2));
+        assert_eq!(metadata.gateway_host.as_deref(), Some("a.example.com"));
+        assert_eq!(lookup.cache, "offchain_lookups");
+        assert_eq!(lookup.message_id, message.id());
+        assert_eq!(lookup.fn_name, "getOffchainVerifyInfo");
+        assert_eq!(lookup.gateway_urls, Some(1));
+        assert!(lookup.expires_in_ms > 0);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_must_match_configured_hash() {
+        let urls = vec![
+            "https://tampered.example.com/{data}".to_owned(),
+            "https://intact.example.com/{data}".to_owned(),
+        ];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://tampered.example.com/0x010203",
+            Ok(br#"{"data":"0x0bad"}"#.to_vec()),
+        );
+        gateway_client.responses.push_fetch_response(
+            "https://intact.example.com/0x010203",
+            Ok(br#"{"data":"0x0102"}"#.to_vec()),
+        );
+        let requests = gateway_client.requests.clone();
+        let conf = CcipReadConf {
+            ism_metadata_hashes: HashMap::from([(H256::zero(), H256::from(keccak256([1, 2])))]),
+            ..Default::default()
+        };
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(&core_metrics),
+        ));
+
+        let metadata = into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .expect("Expected the metadata matching the hash");
+        assert_eq!(metadata.to_vec(), vec![1, 2]);
+        // The tampered response was fetched, and skipped
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_build_span_records_timing_breakdown() {
+        let urls = vec!["https://a.example.com/{data}".to_owned()];
+        let gateway_client = MockGatewayClient::default();
+        gateway_client.responses.push_fetch_response(
+            "https://a.example.com/0x010203",
+            Ok(br#"{"data":"0x0d"}"#.to_vec()),
+        );
+        let conf = CcipReadConf::default();
+        let mut base_builder = ccip_read_base_builder(&urls, &conf);
+        base_builder.responses.ccip_read_context = Some(CcipReadContext::with_gateway_client(
+            Arc::new(gateway_client),
+            &conf,
+            CcipReadMetrics::new(
+                &CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap(),
+            ),
+        ));
+        into_ccip_read_builder(base_builder)
+            .build(
+                H256::zero(),
+                &HyperlaneMessage::default(),
+                MessageMetadataBuildParams::default(),
+            )
+            .await
+            .unwrap();
+
+        logs_assert(|lines: &[&str]| {
+            let fetched = lines
+                .iter()
+                .find(|line| line.contains("Fetched metadata from a CCIP-read gateway"))
+                .ok_or("No log of the fetched metadata")?;
+            for field in [
+                "cache_lookup_ms=",
+                "offchain_lookup_ms=",
+                "gateway_fetch_ms=",
+            ] {
+                if !fetched.contains(field) {
+                    return Err(format!("{field} missing from {fetched}"));
+                }
+            }
+            lines
+                .iter()
+                .find(|line| {
+                    line.contains("CCIP-read gateway request attempt finished")
+                        && line.contains("decode_ms=")
+                })
+                .map(|_| ())
+                .ok_or_else(|| "No decode timing of the gateway attempt".to_owned())
+        });
+    }
+}
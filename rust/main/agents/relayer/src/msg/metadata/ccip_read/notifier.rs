@@ -0,0 +1,147 @@
+use std::{sync::Arc, time::Duration};
+
+use reqwest::{Client, Url};
+use serde_json::json;
+use tracing::{error, warn};
+
+use hyperlane_core::H256;
+
+use super::{cache::TtlCache, clock::Clock};
+
+/// How long the failures of a message are remembered after its last failed
+/// lookup. Several times the longest backoff between metadata builds, so
+/// messages that are still retried keep their count, while those that were
+/// dropped or skipped are forgotten.
+const FAILURES_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Reports messages whose lookups keep failing on every gateway to a
+/// webhook, so operators can look into the ISM's configuration. A reported
+/// message is only reported again if it fails another `threshold` times.
+///
+/// Reports are sent with a client of their own, so they don't carry the
+/// gateways' headers or client identity and aren't replayed from fixtures.
+#[derive(Debug)]
+pub struct StuckMessageNotifier {
+    webhook: Url,
+    /// Failed lookups after which a message is reported
+    threshold: u32,
+    client: Client,
+    timeout: Duration,
+    /// Messages that failed since they last succeeded or were reported, at
+    /// most `max_entries` of them
+    messages: TtlCache<H256, FailedLookups>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct FailedLookups {
+    count: u32,
+    /// Distinct reasons the lookups failed, in the order they first occurred
+    reasons: Vec<String>,
+}
+
+impl StuckMessageNotifier {
+    pub fn new(
+        webhook: Url,
+        threshold: u32,
+        timeout: Duration,
+        max_entries: usize,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            webhook,
+            threshold,
+            client: Client::new(),
+            timeout,
+            messages: TtlCache::new(FAILURES_TTL, max_entries).with_clock(clock),
+        }
+    }
+
+    /// Forgets the failures of a message whose metadata was found
+    pub async fn record_success(&self, message_id: H256) {
+        self.messages.remove(&message_id).await;
+    }
+
+    /// Records that a lookup for `message_id` failed, notifying the webhook
+    /// in the background if this failure crosses the threshold
+    pub async fn record_failure(&self, message_id: H256, ism_address: H256, reason: String) {
+        let mut failed = self.messages.get(&message_id).await.unwrap_or_default();
+        failed.count = failed.count.saturating_add(1);
+        if !failed.reasons.contains(&reason) {
+            failed.reasons.push(reason);
+        }
+        if failed.count < self.threshold {
+            self.messages.insert(message_id, failed).await;
+            return;
+        }
+        self.messages.remove(&message_id).await;
+        let payload = json!({
+            "messageId": format!("{message_id:?}"),
+            "ismAddress": format!("{ism_address:?}"),
+            "failures": failed.count,
+            "reasons": failed.reasons,
+        });
+        error!(
+            ?message_id,
+            ?ism_address,
+            failures = self.threshold,
+            "CCIP-read lookups for message keep failing on every gateway, reporting it as stuck"
+        );
+        let request = self
+            .client
+            .post(self.webhook.clone())
+            .timeout(self.timeout)
+            .json(&payload);
+        tokio::spawn(async move {
+            let result = request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(err) = result {
+                warn!(%err, "Failed to report stuck message to CCIP-read webhook");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::mock_clock::MockClock;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_failures_of_idle_messages_are_forgotten() {
+        let clock = Arc::new(MockClock::default());
+        let notifier = StuckMessageNotifier::new(
+            "http://127.0.0.1:1/stuck".parse().unwrap(),
+            3,
+            Duration::from_secs(1),
+            2,
+            clock.clone(),
+        );
+        let message_id = H256::repeat_byte(1);
+        for _ in 0..2 {
+            notifier
+                .record_failure(message_id, H256::zero(), "no gateway".to_owned())
+                .await;
+        }
+        assert_eq!(notifier.messages.get(&message_id).await.unwrap().count, 2);
+
+        // A message that isn't retried anymore is forgotten after the TTL
+        clock.advance(FAILURES_TTL);
+        assert!(notifier.messages.get(&message_id).await.is_none());
+
+        // Only `max_entries` messages are remembered at once
+        for byte in 2..5 {
+            notifier
+                .record_failure(
+                    H256::repeat_byte(byte),
+                    H256::zero(),
+                    "no gateway".to_owned(),
+                )
+                .await;
+        }
+        assert!(notifier.messages.get(&H256::repeat_byte(2)).await.is_none());
+        assert!(notifier.messages.get(&H256::repeat_byte(4)).await.is_some());
+    }
+}
@@ -0,0 +1,164 @@
+use std::{collections::HashMap, io::BufReader, sync::Arc, time::SystemTime};
+
+use ethers::utils::hex;
+use eyre::{eyre, Context};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName,
+};
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+use crate::settings::{ccip_read::ClientIdentity, host_filter::normalize_host};
+
+/// TLS configuration verifying gateway certificates against the system's
+/// roots as usual, and additionally requiring pinned hosts to present a
+/// certificate matching one of their pins. A connection to a pinned host
+/// presenting any other certificate is aborted during the handshake, before
+/// a request is sent. Presents `identity` to gateways asking for a client
+/// certificate.
+pub fn pinning_tls_config(
+    pins: HashMap<String, Vec<[u8; 32]>>,
+    identity: Option<&ClientIdentity>,
+) -> eyre::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    let native_certs: Vec<Vec<u8>> = rustls_native_certs::load_native_certs()
+        .context("Failed to load the system's root certificates")?
+        .into_iter()
+        .map(|cert| cert.0)
+        .collect();
+    roots.add_parsable_certificates(&native_certs);
+    let verifier = PinningCertVerifier {
+        inner: WebPkiVerifier::new(roots, None),
+        pins,
+    };
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+    let Some(identity) = identity else {
+        return Ok(builder.with_no_client_auth());
+    };
+    let certs = rustls_pemfile::certs(&mut BufReader::new(identity.cert_pem.as_slice()))
+        .context("Invalid CCIP-read client certificate")?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let key = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(identity.key_pem.as_slice()))
+        .context("Invalid CCIP-read client key")?
+        .into_iter()
+        .next()
+        .ok_or_else(|| eyre!("CCIP-read client key has no PKCS#8 private key"))?;
+    builder
+        .with_client_auth_cert(certs, PrivateKey(key))
+        .context("Invalid CCIP-read client certificate or key")
+}
+
+struct PinningCertVerifier {
+    inner: WebPkiVerifier,
+    /// SHA-256 fingerprints of the certificates pinned hosts must present
+    pins: HashMap<String, Vec<[u8; 32]>>,
+}
+
+impl PinningCertVerifier {
+    /// Whether `host` is unpinned, or `certificate` matches one of its pins
+    fn matches_pin(&self, host: &str, certificate: &Certificate) -> bool {
+        let Some(pins) = self.pins.get(host) else {
+            return true;
+        };
+        let fingerprint: [u8; 32] = Sha256::digest(&certificate.0).into();
+        if pins.contains(&fingerprint) {
+            return true;
+        }
+        error!(
+            host,
+            fingerprint = hex::encode(fingerprint),
+            "CCIP-read gateway presented a certificate that doesn't match its pin, possible \
+             man-in-the-middle, aborting the connection"
+        );
+        false
+    }
+}
+
+impl ServerCertVerifier for PinningCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        let host = match server_name {
+            ServerName::DnsName(name) => normalize_host(name.as_ref()),
+            ServerName::IpAddress(address) => address.to_string(),
+            _ => return Ok(verified),
+        };
+        if !self.matches_pin(&host, end_entity) {
+            return Err(rustls::Error::General(
+                "Gateway certificate doesn't match the pinned certificate of its host".to_owned(),
+            ));
+        }
+        Ok(verified)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base64::Engine;
+
+    use crate::test_utils::{
+        client_identity::{CLIENT_CERT_PEM, CLIENT_KEY_PEM},
+        gateway_certificate::SHORT_LIVED_CERT_PEM,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_only_pinned_hosts_must_match_a_pin() {
+        let encoded: String = SHORT_LIVED_CERT_PEM
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let certificate = Certificate(
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .unwrap(),
+        );
+        let fingerprint: [u8; 32] = Sha256::digest(&certificate.0).into();
+        let verifier = PinningCertVerifier {
+            inner: WebPkiVerifier::new(RootCertStore::empty(), None),
+            pins: HashMap::from([
+                ("gateway.test".to_owned(), vec![[0xab; 32], fingerprint]),
+                ("other.test".to_owned(), vec![[0xab; 32]]),
+            ]),
+        };
+        assert!(verifier.matches_pin("gateway.test", &certificate));
+        assert!(!verifier.matches_pin("other.test", &certificate));
+        assert!(verifier.matches_pin("unpinned.test", &certificate));
+    }
+
+    #[test]
+    fn test_pinning_config_presents_client_identity() {
+        let identity = ClientIdentity {
+            cert_pem: CLIENT_CERT_PEM.as_bytes().to_vec(),
+            key_pem: CLIENT_KEY_PEM.as_bytes().to_vec(),
+        };
+        let config = pinning_tls_config(HashMap::new(), Some(&identity)).unwrap();
+        assert!(config.client_auth_cert_resolver.has_certs());
+
+        let identity = ClientIdentity {
+            key_pem: b"not a key".to_vec(),
+            ..identity
+        };
+        assert!(pinning_tls_config(HashMap::new(), Some(&identity)).is_err());
+    }
+}
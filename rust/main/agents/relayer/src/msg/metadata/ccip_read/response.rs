@@ -0,0 +1,414 @@
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ethers::{
+    abi::{self, ParamType, Token},
+    core::utils::hex::decode as hex_decode,
+};
+use serde_json::Value;
+
+use hyperlane_core::H256;
+
+use crate::settings::ccip_read::{
+    GatewayResponseFormat, ResponseDataEncoding, ResponseDecoderScope,
+};
+
+use super::GatewayError;
+
+/// Turns the data a gateway responded with, i.e. the JSON field or the raw
+/// body, into ISM metadata
+pub trait DataDecoder: Send + Sync + Debug {
+    fn decode(&self, data: &str) -> Result<Vec<u8>, GatewayError>;
+
+    /// Whether the whole response body is the metadata as is, in which
+    /// case `decode` is never called
+    fn passthrough(&self) -> bool {
+        false
+    }
+}
+
+/// Hex, with or without `0x`, as per EIP-3668
+#[derive(Debug)]
+pub struct HexDecoder;
+
+impl DataDecoder for HexDecoder {
+    fn decode(&self, data: &str) -> Result<Vec<u8>, GatewayError> {
+        decode_hex(data)
+    }
+}
+
+/// Standard base64
+#[derive(Debug)]
+pub struct Base64Decoder;
+
+impl DataDecoder for Base64Decoder {
+    fn decode(&self, data: &str) -> Result<Vec<u8>, GatewayError> {
+        STANDARD
+            .decode(data.trim())
+            .map_err(|err| GatewayError::InvalidResponse(format!("Invalid base64: {err}")))
+    }
+}
+
+/// Hex of the ABI encoding of a single `bytes` value, which is the metadata
+#[derive(Debug)]
+pub struct AbiBytesDecoder;
+
+impl DataDecoder for AbiBytesDecoder {
+    fn decode(&self, data: &str) -> Result<Vec<u8>, GatewayError> {
+        let encoded = decode_hex(data)?;
+        match abi::decode(&[ParamType::Bytes], &encoded).as_deref() {
+            Ok([Token::Bytes(metadata)]) => Ok(metadata.clone()),
+            _ => Err(GatewayError::InvalidResponse(
+                "Data is not ABI encoded bytes".to_owned(),
+            )),
+        }
+    }
+}
+
+/// The metadata is the raw response body, e.g. of gateways serving
+/// `application/octet-stream`
+#[derive(Debug)]
+pub struct PassthroughDecoder;
+
+impl DataDecoder for PassthroughDecoder {
+    fn decode(&self, data: &str) -> Result<Vec<u8>, GatewayError> {
+        Ok(data.as_bytes().to_vec())
+    }
+
+    fn passthrough(&self) -> bool {
+        true
+    }
+}
+
+impl From<ResponseDataEncoding> for Arc<dyn DataDecoder> {
+    fn from(encoding: ResponseDataEncoding) -> Self {
+        match encoding {
+            ResponseDataEncoding::Hex => Arc::new(HexDecoder),
+            ResponseDataEncoding::Base64 => Arc::new(Base64Decoder),
+            ResponseDataEncoding::AbiBytes => Arc::new(AbiBytesDecoder),
+            ResponseDataEncoding::Raw => Arc::new(PassthroughDecoder),
+        }
+    }
+}
+
+/// Decodes the metadata out of gateway response bodies
+#[derive(Clone, Debug)]
+pub struct ResponseDecoder {
+    format: GatewayResponseFormat,
+    /// JSON pointer to the encoded metadata in JSON responses
+    data_pointer: String,
+    /// Decoders of the data in responses to particular ISMs or from
+    /// particular hosts. The data is hex otherwise.
+    data_decoders: HashMap<ResponseDecoderScope, Arc<dyn DataDecoder>>,
+}
+
+impl ResponseDecoder {
+    pub fn new(format: GatewayResponseFormat, data_pointer: String) -> Self {
+        Self {
+            format,
+            data_pointer,
+            data_decoders: HashMap::new(),
+        }
+    }
+
+    /// Decodes the data of responses in `scope` with `decoder`
+    pub fn with_data_decoder(
+        mut self,
+        scope: ResponseDecoderScope,
+        decoder: Arc<dyn DataDecoder>,
+    ) -> Self {
+        self.data_decoders.insert(scope, decoder);
+        self
+    }
+
+    /// The decoder for responses to a lookup of `ism` from `host`
+    fn data_decoder(&self, ism: Option<H256>, host: &str) -> &dyn DataDecoder {
+        ism.and_then(|ism| self.data_decoders.get(&ResponseDecoderScope::Ism(ism)))
+            .or_else(|| {
+                self.data_decoders
+                    .get(&ResponseDecoderScope::Host(host.to_owned()))
+            })
+            .map_or(&HexDecoder as &dyn DataDecoder, |decoder| decoder.as_ref())
+    }
+
+    /// Decodes the metadata out of the body of a response from `host` to a
+    /// lookup of `ism`
+    pub fn decode(
+        &self,
+        body: &[u8],
+        ism: Option<H256>,
+        host: &str,
+    ) -> Result<Vec<u8>, GatewayError> {
+        let decoder = self.data_decoder(ism, host);
+        if decoder.passthrough() {
+            if body.is_empty() {
+                return Err(GatewayError::InvalidResponse(
+                    "Empty response body".to_owned(),
+                ));
+            }
+            return Ok(body.to_vec());
+        }
+        match self.format {
+            GatewayResponseFormat::Json => self.decode_json(body, decoder),
+            GatewayResponseFormat::RawHex => decode_raw(body, decoder),
+            // Report the JSON error if neither works since that's the standard format
+            GatewayResponseFormat::Auto => self
+                .decode_json(body, decoder)
+                .or_else(|err| decode_raw(body, decoder).map_err(|_| err)),
+        }
+    }
+
+    fn decode_json(&self, body: &[u8], decoder: &dyn DataDecoder) -> Result<Vec<u8>, GatewayError> {
+        let response: Value = serde_json::from_slice(body)
+            .map_err(|err| GatewayError::InvalidResponse(err.to_string()))?;
+        // A missing field is a malformed response, while an empty one means
+        // the gateway doesn't have the metadata yet
+        let data = response.pointer(&self.data_pointer).ok_or_else(|| {
+            GatewayError::InvalidResponse(format!(
+                "No `{}` field in the response",
+                self.data_pointer
+            ))
+        })?;
+        let data = data.as_str().ok_or_else(|| {
+            GatewayError::InvalidResponse(format!(
+                "`{}` in the response is not a string",
+                self.data_pointer
+            ))
+        })?;
+        // Including a bare `0x`, the empty hex string
+        let data_trimmed = data.trim();
+        let unprefixed = data_trimmed
+            .strip_prefix("0x")
+            .or_else(|| data_trimmed.strip_prefix("0X"))
+            .unwrap_or(data_trimmed);
+        if unprefixed.is_empty() {
+            return Err(GatewayError::DataNotAvailable);
+        }
+        decoder.decode(data)
+    }
+
+    /// The URL of the next page of a paginated JSON response, if any
+    pub fn next_page(&self, body: &[u8]) -> Option<String> {
+        if self.format == GatewayResponseFormat::RawHex {
+            return None;
+        }
+        let response: Value = serde_json::from_slice(body).ok()?;
+        Some(response.get("next")?.as_str()?.to_owned())
+    }
+}
+
+fn decode_raw(body: &[u8], decoder: &dyn DataDecoder) -> Result<Vec<u8>, GatewayError> {
+    let body = std::str::from_utf8(body)
+        .map_err(|err| GatewayError::InvalidResponse(err.to_string()))?
+        .trim();
+    // Some gateways send the bare data string JSON-encoded
+    let body = body
+        .strip_prefix('"')
+        .and_then(|b| b.strip_suffix('"'))
+        .unwrap_or(body);
+    if body.is_empty() {
+        return Err(GatewayError::InvalidResponse(
+            "Empty response body".to_owned(),
+        ));
+    }
+    decoder.decode(body)
+}
+
+/// Decodes a hex string, with or without a leading `0x`
+fn decode_hex(hex: &str) -> Result<Vec<u8>, GatewayError> {
+    let hex = hex.trim();
+    let digits = hex
+        .strip_prefix("0x")
+        .or_else(|| hex.strip_prefix("0X"))
+        .unwrap_or(hex);
+    hex_decode(digits).map_err(|err| GatewayError::InvalidResponse(format!("Invalid hex: {err}")))
+}
+
+#[cfg(test)]
+mod test {
+    use hyperlane_core::utils::bytes_to_hex;
+
+    use crate::settings::ccip_read::DEFAULT_RESPONSE_DATA_POINTER;
+
+    use super::*;
+
+    fn decode_response(
+        body: &[u8],
+        format: GatewayResponseFormat,
+    ) -> Result<Vec<u8>, GatewayError> {
+        ResponseDecoder::new(format, DEFAULT_RESPONSE_DATA_POINTER.to_owned())
+            .decode(body, None, "")
+    }
+
+    #[test]
+    fn test_decodes_json_envelope() {
+        let res = decode_response(br#"{"data":"0x0102"}"#, GatewayResponseFormat::Auto);
+        assert_eq!(res.unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_decodes_raw_hex() {
+        for body in ["0x0102", "0102", " 0x0102\n", r#""0x0102""#] {
+            let res = decode_response(body.as_bytes(), GatewayResponseFormat::Auto);
+            assert_eq!(res.unwrap(), vec![1, 2], "body: {body:?}");
+        }
+    }
+
+    #[test]
+    fn test_respects_explicit_format() {
+        let res = decode_response(b"0x0102", GatewayResponseFormat::Json);
+        assert!(matches!(res, Err(GatewayError::InvalidResponse(_))));
+
+        let res = decode_response(br#"{"data":"0x0102"}"#, GatewayResponseFormat::RawHex);
+        assert!(matches!(res, Err(GatewayError::InvalidResponse(_))));
+    }
+
+    #[test]
+    fn test_rejects_invalid_hex() {
+        for body in [
+            r#"{"data":"0xzz"}"#,
+            r#"{"data":"x"}"#,
+            "0xzz",
+            "<html>Hello</html>",
+            "",
+        ] {
+            let res = decode_response(body.as_bytes(), GatewayResponseFormat::Auto);
+            assert!(
+                matches!(res, Err(GatewayError::InvalidResponse(_))),
+                "body: {body:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_hex_handles_each_prefix() {
+        for hex in ["0x0102", "0X0102", "0102"] {
+            assert_eq!(decode_hex(hex).unwrap(), vec![1, 2], "hex: {hex:?}");
+        }
+        assert_eq!(decode_hex("0x").unwrap(), Vec::<u8>::new());
+        // Too short to have a prefix, which must not be sliced off blindly
+        for hex in ["0", "x", "0X1"] {
+            assert!(
+                matches!(decode_hex(hex), Err(GatewayError::InvalidResponse(_))),
+                "hex: {hex:?}"
+            );
+        }
+        let res = decode_response(br#"{"data":"0X0102"}"#, GatewayResponseFormat::Json);
+        assert_eq!(res.unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_decodes_nested_field() {
+        let decoder = ResponseDecoder::new(GatewayResponseFormat::Json, "/result/data".to_owned());
+        let res = decoder.decode(br#"{"result":{"data":"0x0102"}}"#, None, "");
+        assert_eq!(res.unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_reads_next_page_url() {
+        let decoder = ResponseDecoder::new(
+            GatewayResponseFormat::Auto,
+            DEFAULT_RESPONSE_DATA_POINTER.to_owned(),
+        );
+        let next = decoder.next_page(br#"{"data":"0x01","next":"/page/2"}"#);
+        assert_eq!(next.as_deref(), Some("/page/2"));
+        for body in [
+            r#"{"data":"0x01"}"#,
+            r#"{"data":"0x01","next":null}"#,
+            "0x01",
+        ] {
+            assert!(
+                decoder.next_page(body.as_bytes()).is_none(),
+                "body: {body:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rejects_missing_or_non_string_field() {
+        let decoder = ResponseDecoder::new(GatewayResponseFormat::Json, "/result/data".to_owned());
+        for body in [
+            r#"{"data":"0x0102"}"#,
+            r#"{"result":{"data":1}}"#,
+            r#"{"result":"0x0102"}"#,
+        ] {
+            let res = decoder.decode(body.as_bytes(), None, "");
+            assert!(
+                matches!(res, Err(GatewayError::InvalidResponse(_))),
+                "body: {body:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_scoped_data_decoders() {
+        let ism = H256::from_low_u64_be(1);
+        let decoder = ResponseDecoder::new(
+            GatewayResponseFormat::Auto,
+            DEFAULT_RESPONSE_DATA_POINTER.to_owned(),
+        )
+        .with_data_decoder(
+            ResponseDecoderScope::Host("gateway.example.com".to_owned()),
+            ResponseDataEncoding::Base64.into(),
+        )
+        .with_data_decoder(
+            ResponseDecoderScope::Ism(ism),
+            ResponseDataEncoding::AbiBytes.into(),
+        );
+
+        let base64 = br#"{"data":"AQI="}"#;
+        let res = decoder.decode(base64, None, "gateway.example.com");
+        assert_eq!(res.unwrap(), vec![1, 2]);
+        // Hex elsewhere
+        let res = decoder.decode(base64, None, "other.example.com");
+        assert!(matches!(res, Err(GatewayError::InvalidResponse(_))));
+
+        // The ISM's decoder takes precedence over the host's
+        let abi_encoded = bytes_to_hex(&abi::encode(&[Token::Bytes(vec![1, 2])]));
+        let res = decoder.decode(abi_encoded.as_bytes(), Some(ism), "gateway.example.com");
+        assert_eq!(res.unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_passthrough_returns_body_as_is() {
+        let ism = H256::from_low_u64_be(1);
+        let decoder = ResponseDecoder::new(
+            GatewayResponseFormat::Json,
+            DEFAULT_RESPONSE_DATA_POINTER.to_owned(),
+        )
+        .with_data_decoder(
+            ResponseDecoderScope::Ism(ism),
+            ResponseDataEncoding::Raw.into(),
+        );
+
+        // Neither JSON nor hex, nor even UTF-8
+        let body = [0x00, 0xff, 0x30, 0x78, 0x0a];
+        assert_eq!(decoder.decode(&body, Some(ism), "").unwrap(), body);
+        assert!(decoder.decode(&body, None, "").is_err());
+        assert!(matches!(
+            decoder.decode(&[], Some(ism), ""),
+            Err(GatewayError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_empty_data_is_distinguished_from_missing_data() {
+        for format in [GatewayResponseFormat::Json, GatewayResponseFormat::Auto] {
+            for body in [r#"{"data":""}"#, r#"{"data":" "}"#, r#"{"data":"0x"}"#] {
+                let res = decode_response(body.as_bytes(), format);
+                assert!(
+                    matches!(res, Err(GatewayError::DataNotAvailable)),
+                    "body: {body:?}, format: {format:?}"
+                );
+            }
+            let res = decode_response(b"{}", format);
+            assert!(
+                matches!(res, Err(GatewayError::InvalidResponse(_))),
+                "format: {format:?}"
+            );
+        }
+        // Only the former is worth asking the gateway again for
+        assert!(GatewayError::DataNotAvailable.is_transient());
+        assert!(!GatewayError::InvalidResponse(String::new()).is_transient());
+    }
+}
@@ -0,0 +1,116 @@
+use std::{future::Future, time::Duration};
+
+use derive_new::new;
+use rand::Rng;
+use tokio::time::sleep;
+use tracing::debug;
+
+use super::GatewayError;
+
+/// How a gateway request is retried before moving on to the next URL
+#[derive(Clone, Debug, new)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    max_attempts: u32,
+    /// Delay before the first retry. Doubled for every subsequent retry.
+    base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (starting at 1), with up to 50%
+    /// random jitter added so concurrent lookups don't retry in lockstep.
+    fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(16);
+        let delay = self.base_delay.saturating_mul(1u32 << exponent);
+        let jitter = delay.mul_f64(rand::thread_rng().gen_range(0.0..0.5));
+        delay.saturating_add(jitter)
+    }
+}
+
+/// Runs `op` until it succeeds, fails with an error that is not transient,
+/// or the policy runs out of attempts. The last error is returned on failure.
+pub async fn retry_with_backoff<T, F, Fut>(
+    policy: &RetryPolicy,
+    mut op: F,
+) -> Result<T, GatewayError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, GatewayError>>,
+{
+    let mut attempt: u32 = 1;
+    loop {
+        match op().await {
+            Err(err) if err.is_transient() && attempt < policy.max_attempts => {
+                let delay = policy.backoff(attempt);
+                debug!(?err, attempt, ?delay, "Transient gateway error, retrying");
+                sleep(delay).await;
+                attempt = attempt.saturating_add(1);
+            }
+            res => return res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new(3, Duration::from_millis(1))
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors_until_success() {
+        let calls = &AtomicU32::new(0);
+        let res = retry_with_backoff(&policy(), || async move {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(GatewayError::Status(StatusCode::BAD_GATEWAY))
+            } else {
+                Ok(())
+            }
+        })
+        .await;
+        assert!(res.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let calls = &AtomicU32::new(0);
+        let res: Result<(), _> = retry_with_backoff(&policy(), || async move {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(GatewayError::Status(StatusCode::TOO_MANY_REQUESTS))
+        })
+        .await;
+        assert!(matches!(
+            res,
+            Err(GatewayError::Status(StatusCode::TOO_MANY_REQUESTS))
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let calls = &AtomicU32::new(0);
+        let res: Result<(), _> = retry_with_backoff(&policy(), || async move {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(GatewayError::Status(StatusCode::NOT_FOUND))
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(200));
+        let first = policy.backoff(1);
+        let third = policy.backoff(3);
+        assert!(first >= Duration::from_millis(200) && first < Duration::from_millis(300));
+        assert!(third >= Duration::from_millis(800) && third < Duration::from_millis(1200));
+    }
+}
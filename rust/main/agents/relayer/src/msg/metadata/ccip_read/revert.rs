@@ -0,0 +1,289 @@
+use std::fmt::Display;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ethers::{abi::AbiDecode, core::utils::hex::decode as hex_decode};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{Deserializer, Value};
+use tracing::debug;
+
+use hyperlane_core::utils::bytes_to_hex;
+use hyperlane_ethereum::OffchainLookup;
+
+use super::MetadataBuildError;
+
+/// Selector of the EIP-3668 `OffchainLookup(address,string[],bytes,bytes4,bytes)` error
+pub const OFFCHAIN_LOOKUP_SELECTOR: [u8; 4] = [0x55, 0x6f, 0x18, 0x30];
+
+/// Number of leading bytes of undecodable revert data included in errors
+const DECODE_FAILURE_PREFIX_LEN: usize = 36;
+
+/// `0x` prefixed hex anywhere in a revert
+static HEX_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"0x[[:xdigit:]]+").unwrap());
+/// Runs of base64 long enough to hold revert data
+static BASE64_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"[A-Za-z0-9+/]{8,}={0,2}").unwrap());
+
+/// Where providers are known to put the revert data in JSON-RPC errors,
+/// either the whole response or only its `error` object. Tried before any
+/// other `data` field, which may hold something else, like the call data.
+const KNOWN_DATA_PATHS: &[&[&str]] = &[
+    // geth, Infura
+    &["error", "data"],
+    &["data"],
+    // Alchemy
+    &["error", "data", "originalError", "data"],
+    &["data", "originalError", "data"],
+    // Hardhat, Anvil
+    &["error", "data", "data"],
+    &["data", "data"],
+];
+
+/// Extracts the `OffchainLookup` error from the text of a reverted call.
+/// Revert data that doesn't start with the `OffchainLookup` selector is
+/// skipped, so a revert with any other custom error returns `Ok(None)`.
+pub fn parse_offchain_lookup(revert: &str) -> Result<Option<OffchainLookup>, MetadataBuildError> {
+    for data in revert_data_candidates(revert) {
+        if !data.starts_with(&OFFCHAIN_LOOKUP_SELECTOR) {
+            debug!(
+                selector = %bytes_to_hex(&data[..data.len().min(4)]),
+                "Skipping revert data that is not an OffchainLookup error"
+            );
+            continue;
+        }
+        return OffchainLookup::decode(&data)
+            .map(Some)
+            .map_err(|err| MetadataBuildError::FailedToBuild(decode_failure(&data, err)));
+    }
+    Ok(None)
+}
+
+/// Describes `OffchainLookup` revert data that failed to decode with `err`,
+/// with enough of the data to tell an RPC provider truncating it from a
+/// contract reverting with mismatched arguments
+fn decode_failure(data: &[u8], err: impl Display) -> String {
+    // The selector and the heads of the five arguments
+    let kind = if data.len() < 4 + 5 * 32 {
+        "truncated"
+    } else {
+        "ABI mismatch"
+    };
+    format!(
+        "Failed to decode OffchainLookup revert data ({kind}, {} bytes starting with {}): {err}",
+        data.len(),
+        bytes_to_hex(&data[..data.len().min(DECODE_FAILURE_PREFIX_LEN)])
+    )
+}
+
+/// Everything in `revert` that may be the revert data, in the order it
+/// should be tried. RPC providers wrap revert data differently: as a `data`
+/// field of a JSON error, possibly nested and possibly base64 encoded, as
+/// `0x` prefixed hex anywhere in the message, or as bare base64 in it. In
+/// JSON errors, the fields at `KNOWN_DATA_PATHS` come first.
+fn revert_data_candidates(revert: &str) -> Vec<Vec<u8>> {
+    let mut candidates = Vec::new();
+
+    let mut rest = revert;
+    while let Some(start) = rest.find('{') {
+        let mut values = Deserializer::from_str(&rest[start..]).into_iter::<Value>();
+        match values.next() {
+            Some(Ok(value)) => {
+                collect_known_data_fields(&value, &mut candidates);
+                collect_data_fields(&value, &mut candidates);
+                rest = &rest[start + values.byte_offset()..];
+            }
+            _ => rest = &rest[start + 1..],
+        }
+    }
+
+    // remove leading 0x which hex_decode doesn't like
+    candidates.extend(
+        HEX_REGEX
+            .find_iter(revert)
+            .filter_map(|matching| hex_decode(&matching.as_str()[2..]).ok()),
+    );
+
+    // A run of base64 characters is far too common to try them all, so only
+    // those decoding to an `OffchainLookup` are kept
+    candidates.extend(
+        BASE64_REGEX
+            .find_iter(revert)
+            .filter_map(|matching| STANDARD.decode(matching.as_str()).ok())
+            .filter(|data| data.starts_with(&OFFCHAIN_LOOKUP_SELECTOR)),
+    );
+    candidates
+}
+
+/// Decodes the string fields of `value` at `KNOWN_DATA_PATHS`
+fn collect_known_data_fields(value: &Value, candidates: &mut Vec<Vec<u8>>) {
+    candidates.extend(KNOWN_DATA_PATHS.iter().filter_map(|path| {
+        let data = path.iter().try_fold(value, |value, key| value.get(*key))?;
+        decode_data_field(data.as_str()?)
+    }));
+}
+
+/// Decodes the string `data` fields found anywhere in `value`
+fn collect_data_fields(value: &Value, candidates: &mut Vec<Vec<u8>>) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                match field {
+                    Value::String(data) if key == "data" => {
+                        candidates.extend(decode_data_field(data));
+                    }
+                    _ => collect_data_fields(field, candidates),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                collect_data_fields(value, candidates);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Hex with or without `0x`, falling back to base64
+fn decode_data_field(data: &str) -> Option<Vec<u8>> {
+    let digits = data.strip_prefix("0x").unwrap_or(data);
+    hex_decode(digits)
+        .ok()
+        .or_else(|| STANDARD.decode(data).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::{abi::AbiEncode, types::Address};
+
+    use super::*;
+
+    fn lookup() -> OffchainLookup {
+        OffchainLookup {
+            sender: Address::zero(),
+            urls: vec!["https://example.com/{data}".to_owned()],
+            call_data: vec![1, 2, 3].into(),
+            callback_function: [0; 4],
+            extra_data: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_parses_offchain_lookup() {
+        let revert = format!("execution reverted: {}", bytes_to_hex(&lookup().encode()));
+        let parsed = parse_offchain_lookup(&revert).unwrap().unwrap();
+        assert_eq!(parsed.urls, lookup().urls);
+        assert_eq!(parsed.call_data, lookup().call_data);
+    }
+
+    #[test]
+    fn test_skips_unrelated_hex_before_offchain_lookup() {
+        let revert = format!(
+            "contract 0xdeadbeef execution reverted: {}",
+            bytes_to_hex(&lookup().encode())
+        );
+        assert!(parse_offchain_lookup(&revert).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_ignores_other_custom_errors() {
+        // `Error(string)` with the message "nope"
+        let revert = "execution reverted: 0x08c379a0\
+            0000000000000000000000000000000000000000000000000000000000000020\
+            0000000000000000000000000000000000000000000000000000000000000004\
+            6e6f706500000000000000000000000000000000000000000000000000000000";
+        assert!(parse_offchain_lookup(revert).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_ignores_revert_without_data() {
+        assert!(parse_offchain_lookup("execution reverted")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_parses_geth_style_revert() {
+        // How ethers formats the JSON-RPC error geth returns for `eth_call`
+        let revert = format!(
+            "(code: 3, message: execution reverted, data: Some(String(\"{}\")))",
+            bytes_to_hex(&lookup().encode())
+        );
+        assert!(parse_offchain_lookup(&revert).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_parses_infura_style_json_error() {
+        let revert = format!(
+            r#"JsonRpcError {{"jsonrpc":"2.0","id":1,"error":{{"code":3,"message":"execution reverted","data":"{}"}}}}"#,
+            &bytes_to_hex(&lookup().encode())[2..]
+        );
+        // The data isn't `0x` prefixed, so it's only found in the JSON
+        assert!(!revert.contains("0x"));
+        assert!(parse_offchain_lookup(&revert).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_parses_bare_base64_revert_data() {
+        let revert = format!(
+            "execution reverted, data: Some(String(\"{}\"))",
+            STANDARD.encode(lookup().encode())
+        );
+        let parsed = parse_offchain_lookup(&revert).unwrap().unwrap();
+        assert_eq!(parsed.urls, lookup().urls);
+        assert_eq!(parsed.call_data, lookup().call_data);
+    }
+
+    #[test]
+    fn test_parses_alchemy_style_nested_base64_data() {
+        let revert = format!(
+            r#"execution reverted: {{"code":-32000,"message":"execution reverted","data":{{"originalError":{{"code":3,"data":"{}"}}}}}}"#,
+            STANDARD.encode(lookup().encode())
+        );
+        let parsed = parse_offchain_lookup(&revert).unwrap().unwrap();
+        assert_eq!(parsed.urls, lookup().urls);
+    }
+
+    #[test]
+    fn test_prefers_revert_data_at_known_paths() {
+        // The echoed request's call data comes first in the JSON, but isn't
+        // where the revert data is known to be
+        let revert = format!(
+            r#"{{"body":{{"data":"0xdeadbeef"}},"jsonrpc":"2.0","id":1,"error":{{"code":-32000,"message":"execution reverted","data":{{"originalError":{{"code":3,"message":"execution reverted","data":"{}"}}}}}}}}"#,
+            &bytes_to_hex(&lookup().encode())[2..]
+        );
+        assert_eq!(revert_data_candidates(&revert)[0], lookup().encode());
+        assert!(parse_offchain_lookup(&revert).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_parses_hardhat_style_nested_data() {
+        let revert = format!(
+            r#"(code: -32603, message: Error: VM Exception while processing transaction, data: {{"message":"reverted with an unrecognized custom error","data":"{}"}})"#,
+            bytes_to_hex(&lookup().encode())
+        );
+        let parsed = parse_offchain_lookup(&revert).unwrap().unwrap();
+        assert_eq!(parsed.urls, lookup().urls);
+    }
+
+    #[test]
+    fn test_hex_candidates_are_unchanged_across_calls() {
+        let revert = format!(
+            "contract 0xdeadbeef execution reverted: {}",
+            bytes_to_hex(&lookup().encode())
+        );
+        let first = revert_data_candidates(&revert);
+        assert_eq!(first[0], vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(first[1], lookup().encode());
+        assert_eq!(revert_data_candidates(&revert), first);
+    }
+
+    #[test]
+    fn test_truncated_offchain_lookup_error_is_described() {
+        let encoded = lookup().encode();
+        let revert = format!("execution reverted: {}", bytes_to_hex(&encoded[..100]));
+        let Err(MetadataBuildError::FailedToBuild(err)) = parse_offchain_lookup(&revert) else {
+            panic!("Expected the truncated OffchainLookup to fail to decode");
+        };
+        assert!(err.contains("truncated, 100 bytes starting with 0x556f1830"));
+    }
+}
@@ -0,0 +1,360 @@
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use ethers::abi::{AbiDecode, AbiEncode};
+use prometheus::IntCounterVec;
+use tokio::{
+    sync::RwLock,
+    task::{spawn_blocking, JoinHandle},
+};
+use tracing::{debug, warn};
+
+use hyperlane_base::db::{DbError, DB};
+use hyperlane_core::H256;
+use hyperlane_ethereum::OffchainLookup;
+
+use super::{
+    cache::LookupKey,
+    clock::{Clock, SystemClock},
+};
+
+// these keys MUST not be given multiple uses in case multiple agents are
+// started with the same database.
+const OFFCHAIN_LOOKUP: &[u8] = b"ccip_read_offchain_lookup_";
+const INVALIDATED_BEFORE: &[u8] = b"ccip_read_offchain_lookups_invalidated_before_";
+
+/// Version of the serialized form of `SerializedOffchainLookup`. Must be
+/// bumped whenever that form changes, so entries written by older relayers
+/// are treated as missing rather than misread.
+const SCHEMA_VERSION: u8 = 1;
+
+/// An `OffchainLookup` as persisted, along with when it was fetched so its
+/// TTL carries over restarts
+#[derive(Clone, Debug)]
+pub struct SerializedOffchainLookup {
+    /// Milliseconds since the Unix epoch
+    pub fetched_at: u64,
+    pub lookup: OffchainLookup,
+}
+
+impl SerializedOffchainLookup {
+    /// Layout: `version (1 byte) | fetched_at (8 bytes, big endian) | ABI encoded lookup`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![SCHEMA_VERSION];
+        bytes.extend_from_slice(&self.fetched_at.to_be_bytes());
+        bytes.extend(self.lookup.clone().encode());
+        bytes
+    }
+
+    /// `None` if `bytes` were written with another schema version or can't
+    /// be decoded
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&version, rest) = bytes.split_first()?;
+        if version != SCHEMA_VERSION {
+            debug!(
+                version,
+                "Ignoring persisted OffchainLookup with another schema version"
+            );
+            return None;
+        }
+        if rest.len() < 8 {
+            return None;
+        }
+        let (fetched_at, lookup) = rest.split_at(8);
+        Some(Self {
+            fetched_at: u64::from_be_bytes(fetched_at.try_into().ok()?),
+            lookup: OffchainLookup::decode(lookup).ok()?,
+        })
+    }
+}
+
+/// Key-value storage an `OffchainLookupStore` persists lookups in
+pub trait LookupBackend: Send + Sync + Debug {
+    fn retrieve(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DbError>;
+    fn store(&self, key: &[u8], value: &[u8]) -> Result<(), DbError>;
+}
+
+impl LookupBackend for DB {
+    fn retrieve(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
+        DB::retrieve(self, key)
+    }
+
+    fn store(&self, key: &[u8], value: &[u8]) -> Result<(), DbError> {
+        DB::store(self, key, value)
+    }
+}
+
+/// Persists `OffchainLookup`s in the relayer's database, so the cache in
+/// front of `getOffchainVerifyInfo` is still warm after a restart. The store
+/// is only an optimization: failing reads are misses, so lookups fall back
+/// to calling the ISM, and failing writes are dropped.
+#[derive(Clone, Debug)]
+pub struct OffchainLookupStore {
+    backend: Arc<dyn LookupBackend>,
+    ttl: Duration,
+    /// Read by every background write until it is done, so taking the
+    /// write lock waits for all of them
+    pending_writes: Arc<RwLock<()>>,
+    /// Failed reads and writes, by `operation`
+    failures: Option<IntCounterVec>,
+    clock: Arc<dyn Clock>,
+}
+
+impl OffchainLookupStore {
+    pub fn new(db: DB, ttl: Duration) -> Self {
+        Self::with_backend(Arc::new(db), ttl)
+    }
+
+    pub fn with_backend(backend: Arc<dyn LookupBackend>, ttl: Duration) -> Self {
+        Self {
+            backend,
+            ttl,
+            pending_writes: Default::default(),
+            failures: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Timestamps and expires lookups by `clock` instead of the system clock
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Counts failed reads and writes in `failures`, labelled by `operation`
+    pub fn with_failure_metric(self, failures: IntCounterVec) -> Self {
+        Self {
+            failures: Some(failures),
+            ..self
+        }
+    }
+
+    /// Waits for the writes started until now to finish, e.g. before the
+    /// relayer shuts down
+    pub async fn flush(&self) {
+        let _flushed = self.pending_writes.write().await;
+    }
+
+    /// The lookup stored for `key` if it was fetched less than a TTL ago and
+    /// hasn't been invalidated since, for its ISM, its message or all of them
+    pub async fn get(&self, key: &LookupKey) -> Option<OffchainLookup> {
+        let db = self.backend.clone();
+        let db_key = lookup_db_key(key);
+        let invalidation_keys = [
+            invalidated_before_db_key(None),
+            invalidated_before_db_key(Some(key.ism_address)),
+            message_invalidated_before_db_key(key.message_id),
+        ];
+        let res = spawn_blocking(move || -> Result<_, DbError> {
+            let stored = db.retrieve(&db_key)?;
+            let invalidated_before = invalidation_keys
+                .iter()
+                .map(|key| db.retrieve(key))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .flatten()
+                .filter_map(|bytes| Some(u64::from_be_bytes(bytes.try_into().ok()?)))
+                .max()
+                .unwrap_or_default();
+            Ok((stored, invalidated_before))
+        })
+        .await;
+        let (stored, invalidated_before) = match res {
+            Ok(Ok(res)) => res,
+            err => {
+                warn!(
+                    ?err,
+                    "Failed to read persisted OffchainLookup, calling the ISM instead"
+                );
+                record_failure(&self.failures, "read");
+                return None;
+            }
+        };
+        let serialized = SerializedOffchainLookup::from_bytes(&stored?)?;
+        let expires_at = serialized
+            .fetched_at
+            .saturating_add(self.ttl.as_millis() as u64);
+        (serialized.fetched_at > invalidated_before && self.now_millis() < expires_at)
+            .then_some(serialized.lookup)
+    }
+
+    /// Persists `lookup` in the background, so the caller isn't held up by
+    /// the write
+    pub fn insert(&self, key: &LookupKey, lookup: OffchainLookup) -> JoinHandle<()> {
+        let serialized = SerializedOffchainLookup {
+            fetched_at: self.now_millis(),
+            lookup,
+        };
+        self.store_in_background(lookup_db_key(key), serialized.to_bytes())
+    }
+
+    /// Makes lookups fetched until now count as missing, for a single ISM or
+    /// all of them
+    pub fn invalidate(&self, ism_address: Option<H256>) -> JoinHandle<()> {
+        self.store_in_background(
+            invalidated_before_db_key(ism_address),
+            self.now_millis().to_be_bytes().to_vec(),
+        )
+    }
+
+    /// Makes lookups fetched until now for the message with `message_id`
+    /// count as missing
+    pub fn invalidate_message(&self, message_id: H256) -> JoinHandle<()> {
+        self.store_in_background(
+            message_invalidated_before_db_key(message_id),
+            self.now_millis().to_be_bytes().to_vec(),
+        )
+    }
+
+    fn now_millis(&self) -> u64 {
+        self.clock.unix_time().as_millis() as u64
+    }
+
+    fn store_in_background(&self, db_key: Vec<u8>, value: Vec<u8>) -> JoinHandle<()> {
+        let db = self.backend.clone();
+        let failures = self.failures.clone();
+        // Only missing while a flush is waiting, which then doesn't wait for
+        // this write
+        let pending = self.pending_writes.clone().try_read_owned().ok();
+        spawn_blocking(move || {
+            if let Err(err) = db.store(&db_key, &value) {
+                warn!(?err, "Failed to persist OffchainLookup");
+                record_failure(&failures, "write");
+            }
+            drop(pending);
+        })
+    }
+}
+
+fn record_failure(failures: &Option<IntCounterVec>, operation: &str) {
+    if let Some(failures) = failures {
+        failures.with_label_values(&[operation]).inc();
+    }
+}
+
+fn lookup_db_key(key: &LookupKey) -> Vec<u8> {
+    [
+        OFFCHAIN_LOOKUP,
+        key.fn_name.as_bytes(),
+        b"_".as_slice(),
+        &key.origin_domain.to_be_bytes(),
+        &key.destination_domain.to_be_bytes(),
+        key.ism_address.as_bytes(),
+        key.message_id.as_bytes(),
+    ]
+    .concat()
+}
+
+fn invalidated_before_db_key(ism_address: Option<H256>) -> Vec<u8> {
+    match ism_address {
+        Some(ism_address) => [INVALIDATED_BEFORE, ism_address.as_bytes()].concat(),
+        None => [INVALIDATED_BEFORE, b"all".as_slice()].concat(),
+    }
+}
+
+fn message_invalidated_before_db_key(message_id: H256) -> Vec<u8> {
+    [
+        INVALIDATED_BEFORE,
+        b"message_".as_slice(),
+        message_id.as_bytes(),
+    ]
+    .concat()
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::types::Address;
+    use hyperlane_base::db::test_utils;
+
+    use crate::test_utils::mock_clock::MockClock;
+
+    use super::*;
+
+    fn key() -> LookupKey {
+        LookupKey {
+            origin_domain: 1,
+            destination_domain: 2,
+            ism_address: H256::repeat_byte(1),
+            fn_name: "getOffchainVerifyInfo",
+            message_id: H256::repeat_byte(2),
+        }
+    }
+
+    fn lookup() -> OffchainLookup {
+        OffchainLookup {
+            sender: Address::zero(),
+            urls: vec!["https://example.com/{data}".to_owned()],
+            call_data: vec![1, 2, 3].into(),
+            callback_function: [0; 4],
+            extra_data: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_unknown_schema_version_is_a_miss() {
+        let serialized = SerializedOffchainLookup {
+            fetched_at: 1,
+            lookup: lookup(),
+        };
+        let mut bytes = serialized.to_bytes();
+        let decoded = SerializedOffchainLookup::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.fetched_at, 1);
+        assert_eq!(decoded.lookup.urls, lookup().urls);
+
+        bytes[0] = SCHEMA_VERSION + 1;
+        assert!(SerializedOffchainLookup::from_bytes(&bytes).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_invalidated_lookups_are_a_miss() {
+        test_utils::run_test_db(|db| async move {
+            let clock = Arc::new(MockClock::default());
+            let store =
+                OffchainLookupStore::new(db, Duration::from_secs(60)).with_clock(clock.clone());
+            store.insert(&key(), lookup()).await.unwrap();
+            assert!(store.get(&key()).await.is_some());
+
+            // Invalidation has millisecond resolution
+            clock.advance(Duration::from_millis(1));
+            store.invalidate(Some(key().ism_address)).await.unwrap();
+            assert!(store.get(&key()).await.is_none());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_invalidated_message_lookups_are_a_miss() {
+        test_utils::run_test_db(|db| async move {
+            let clock = Arc::new(MockClock::default());
+            let store =
+                OffchainLookupStore::new(db, Duration::from_secs(60)).with_clock(clock.clone());
+            let other_message = LookupKey {
+                message_id: H256::repeat_byte(3),
+                ..key()
+            };
+            store.insert(&key(), lookup()).await.unwrap();
+            store.insert(&other_message, lookup()).await.unwrap();
+
+            clock.advance(Duration::from_millis(1));
+            store.invalidate_message(key().message_id).await.unwrap();
+            assert!(store.get(&key()).await.is_none());
+            assert!(store.get(&other_message).await.is_some());
+
+            // Lookups fetched after the invalidation are served again
+            clock.advance(Duration::from_millis(1));
+            store.insert(&key(), lookup()).await.unwrap();
+            assert!(store.get(&key()).await.is_some());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_flush_waits_for_background_writes() {
+        test_utils::run_test_db(|db| async move {
+            let store = OffchainLookupStore::new(db, Duration::from_secs(60));
+            // The write isn't awaited, only flushed
+            drop(store.insert(&key(), lookup()));
+            store.flush().await;
+            assert!(store.get(&key()).await.is_some());
+        })
+        .await;
+    }
+}
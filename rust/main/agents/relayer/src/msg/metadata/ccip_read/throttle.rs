@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::sleep,
+};
+
+use super::clock::{Clock, SystemClock};
+
+/// Limits the requests sent to each gateway host, so bursts of lookups
+/// across many messages don't get the relayer rate limited or banned, and
+/// optionally the requests in flight across all hosts, so they don't exhaust
+/// file descriptors and memory
+#[derive(Debug)]
+pub struct HostThrottle {
+    max_in_flight: Option<usize>,
+    requests_per_second: Option<f64>,
+    total_in_flight: Option<Arc<Semaphore>>,
+    hosts: Mutex<Hosts>,
+    /// Hosts that asked to not be queried until the given time
+    backed_off_until: Mutex<HashMap<String, Instant>>,
+    /// Back-offs and rate limits are timed by this clock
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug, Default)]
+struct Hosts {
+    limits: HashMap<String, HostLimits>,
+    /// When idle hosts were last dropped
+    pruned_at: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct HostLimits {
+    in_flight: Option<Arc<Semaphore>>,
+    bucket: Option<TokenBucket>,
+}
+
+impl HostLimits {
+    /// Whether no request to the host is in flight or waiting, and its
+    /// bucket is full, so dropping the limits loses nothing
+    fn is_idle(&self, now: Instant) -> bool {
+        let in_flight = self
+            .in_flight
+            .as_ref()
+            .map_or(false, |semaphore| Arc::strong_count(semaphore) > 1);
+        let drained = self
+            .bucket
+            .as_ref()
+            .map_or(false, |bucket| !bucket.is_full(now));
+        !in_flight && !drained
+    }
+}
+
+impl Default for HostThrottle {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+/// Counts a request as in flight until dropped
+#[derive(Debug, Default)]
+pub struct HostPermit {
+    _host: Option<OwnedSemaphorePermit>,
+    _total: Option<OwnedSemaphorePermit>,
+}
+
+impl HostThrottle {
+    /// How often the limits of idle hosts are dropped, so hosts that are no
+    /// longer queried don't accumulate
+    const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn new(max_in_flight: Option<usize>, requests_per_second: Option<f64>) -> Self {
+        Self {
+            max_in_flight,
+            requests_per_second,
+            total_in_flight: None,
+            hosts: Default::default(),
+            backed_off_until: Default::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Times back-offs and rate limits by `clock` instead of the system clock
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Also limits the requests in flight across all hosts to `max`
+    pub fn with_max_in_flight_total(self, max: Option<usize>) -> Self {
+        Self {
+            total_in_flight: max.map(|max| Arc::new(Semaphore::new(max))),
+            ..self
+        }
+    }
+
+    /// Avoids `host` for `duration`, e.g. as asked by a `Retry-After` header
+    pub fn back_off(&self, host: &str, duration: Duration) {
+        let until = self.clock.now() + duration;
+        let mut backed_off_until = self
+            .backed_off_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let entry = backed_off_until.entry(host.to_owned()).or_insert(until);
+        *entry = (*entry).max(until);
+    }
+
+    /// How much longer `host` is avoided for, if at all
+    pub fn backing_off(&self, host: &str) -> Option<Duration> {
+        let mut backed_off_until = self
+            .backed_off_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let remaining = backed_off_until
+            .get(host)?
+            .checked_duration_since(self.clock.now())
+            .filter(|remaining| !remaining.is_zero());
+        if remaining.is_none() {
+            backed_off_until.remove(host);
+        }
+        remaining
+    }
+
+    /// Waits until a request may be sent to `host`, for as long as it takes,
+    /// so callers should bound this with their own deadline. The limit across
+    /// hosts is taken last, so it isn't held while waiting on `host`.
+    pub async fn acquire(&self, host: &str) -> HostPermit {
+        let host = if self.max_in_flight.is_none() && self.requests_per_second.is_none() {
+            None
+        } else {
+            self.acquire_host(host).await
+        };
+        let total = match &self.total_in_flight {
+            Some(semaphore) => Some(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("the total semaphore is never closed"),
+            ),
+            None => None,
+        };
+        HostPermit {
+            _host: host,
+            _total: total,
+        }
+    }
+
+    async fn acquire_host(&self, host: &str) -> Option<OwnedSemaphorePermit> {
+        while let Some(wait) = self.with_limits(host, |limits, now| {
+            limits.bucket.as_mut().and_then(|bucket| bucket.take(now))
+        }) {
+            sleep(wait).await;
+        }
+        let semaphore = self.with_limits(host, |limits, _| limits.in_flight.clone())?;
+        let permit = semaphore
+            .acquire_owned()
+            .await
+            .expect("host semaphores are never closed");
+        Some(permit)
+    }
+
+    fn with_limits<T>(&self, host: &str, f: impl FnOnce(&mut HostLimits, Instant) -> T) -> T {
+        let now = self.clock.now();
+        let mut hosts = self.hosts.lock().unwrap_or_else(PoisonError::into_inner);
+        let prune = hosts.pruned_at.map_or(true, |pruned_at| {
+            now.saturating_duration_since(pruned_at) >= Self::PRUNE_INTERVAL
+        });
+        if prune {
+            hosts.limits.retain(|_, limits| !limits.is_idle(now));
+            hosts.pruned_at = Some(now);
+        }
+        let limits = hosts
+            .limits
+            .entry(host.to_owned())
+            .or_insert_with(|| HostLimits {
+                in_flight: self.max_in_flight.map(|max| Arc::new(Semaphore::new(max))),
+                bucket: self
+                    .requests_per_second
+                    .map(|rate| TokenBucket::new(rate, now)),
+            });
+        f(limits, now)
+    }
+}
+
+/// Allows `rate` requests per second on average, in bursts of up to `rate`
+/// requests (at least one)
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, now: Instant) -> Self {
+        let capacity = rate.max(1.0);
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            refilled_at: now,
+        }
+    }
+
+    /// The tokens available at `now`
+    fn tokens(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity)
+    }
+
+    fn is_full(&self, now: Instant) -> bool {
+        self.tokens(now) >= self.capacity
+    }
+
+    /// Takes a token if one is available, or returns how long until one is
+    fn take(&mut self, now: Instant) -> Option<Duration> {
+        self.tokens = self.tokens(now);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::mock_clock::MockClock;
+
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_refills_at_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, start);
+        assert!(bucket.take(start).is_none());
+        assert!(bucket.take(start).is_none());
+        assert_eq!(bucket.take(start), Some(Duration::from_millis(500)));
+        assert!(bucket.take(start + Duration::from_millis(500)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_idle_hosts_are_pruned() {
+        let clock = Arc::new(MockClock::default());
+        let throttle = HostThrottle::new(Some(1), Some(1.0)).with_clock(clock.clone());
+        let permit = throttle.acquire("busy.example.com").await;
+        drop(throttle.acquire("idle.example.com").await);
+
+        clock.advance(HostThrottle::PRUNE_INTERVAL);
+        drop(throttle.acquire("other.example.com").await);
+        let hosts = throttle.hosts.lock().unwrap();
+        assert!(hosts.limits.contains_key("busy.example.com"));
+        assert!(!hosts.limits.contains_key("idle.example.com"));
+        drop(permit);
+    }
+
+    #[test]
+    fn test_backed_off_hosts_are_avoided_until_expiry() {
+        let throttle = HostThrottle::default();
+        throttle.back_off("gateway.example.com", Duration::from_secs(60));
+        throttle.back_off("other.example.com", Duration::ZERO);
+        assert!(throttle.backing_off("gateway.example.com").unwrap() > Duration::from_secs(59));
+        assert!(throttle.backing_off("other.example.com").is_none());
+        assert!(throttle.backing_off("unknown.example.com").is_none());
+    }
+
+    #[test]
+    fn test_back_off_ends_as_clock_advances() {
+        let clock = Arc::new(MockClock::default());
+        let throttle = HostThrottle::default().with_clock(clock.clone());
+        throttle.back_off("gateway.example.com", Duration::from_secs(60));
+
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(
+            throttle.backing_off("gateway.example.com"),
+            Some(Duration::from_secs(15))
+        );
+        clock.advance(Duration::from_secs(15));
+        assert!(throttle.backing_off("gateway.example.com").is_none());
+    }
+}
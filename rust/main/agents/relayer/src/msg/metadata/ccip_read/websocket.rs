@@ -0,0 +1,273 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use reqwest::{StatusCode, Url};
+use serde_json::Value;
+use tokio::{net::TcpStream, time::timeout};
+use tokio_tungstenite::{
+    connect_async_with_config,
+    tungstenite::{
+        self, client::IntoClientRequest, error::CapacityError, protocol::WebSocketConfig, Message,
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::settings::ccip_read::CcipReadConf;
+
+use super::{client::GatewayHeaders, clock::Clock, GatewayClient, GatewayError};
+
+/// Whether `url` points at a gateway reached over a WebSocket
+pub fn is_websocket_url(url: &str) -> bool {
+    Url::parse(url).map_or(false, |url| matches!(url.scheme(), "ws" | "wss"))
+}
+
+/// Looks up metadata from gateways that push it over a WebSocket. A
+/// connection is opened per lookup, the JSON `{"sender": ..., "data": ...}`
+/// request is sent as a text frame, and the first text or binary frame the
+/// gateway sends back is its response, decoded like an HTTP response body.
+/// The handshake carries the gateway's configured headers.
+#[derive(Clone, Debug)]
+pub struct WebSocketGatewayClient {
+    /// Bounds the whole exchange, from connecting to the response frame
+    timeout: Duration,
+    /// Limit on the size of frames and messages the gateway sends
+    max_response_bytes: usize,
+    headers: GatewayHeaders,
+}
+
+impl WebSocketGatewayClient {
+    /// The gateway headers file is checked for changes by `clock`
+    pub fn new(conf: &CcipReadConf, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            timeout: conf.gateway_timeout,
+            max_response_bytes: conf.max_response_bytes,
+            headers: GatewayHeaders::new(conf, clock),
+        }
+    }
+
+    async fn connect(
+        &self,
+        url: &str,
+    ) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, GatewayError> {
+        let mut request = url.into_client_request().map_err(transport_error)?;
+        if let Some(headers) = self.headers.for_url(url) {
+            request.headers_mut().extend(headers);
+        }
+        let config = WebSocketConfig {
+            max_message_size: Some(self.max_response_bytes),
+            max_frame_size: Some(self.max_response_bytes),
+            ..Default::default()
+        };
+        let (socket, _) = connect_async_with_config(request, Some(config))
+            .await
+            .map_err(transport_error)?;
+        Ok(socket)
+    }
+
+    async fn exchange(&self, url: &str, request: String) -> Result<Vec<u8>, GatewayError> {
+        let mut socket = self.connect(url).await?;
+        socket
+            .send(Message::Text(request))
+            .await
+            .map_err(transport_error)?;
+        while let Some(message) = socket.next().await {
+            let message = match message {
+                Err(tungstenite::Error::Capacity(CapacityError::MessageTooLong { .. })) => {
+                    return Err(GatewayError::ResponseTooLarge(self.max_response_bytes))
+                }
+                message => message.map_err(transport_error)?,
+            };
+            let response = match message {
+                Message::Text(text) => text.into_bytes(),
+                Message::Binary(data) => data,
+                Message::Close(_) => break,
+                // Pings are answered by the stream itself
+                _ => continue,
+            };
+            // Nothing else is expected from the gateway, and it may keep
+            // the connection open, so failing to close it is irrelevant
+            drop(socket.close(None).await);
+            return Ok(response);
+        }
+        Err(GatewayError::Transport(
+            "Gateway closed the WebSocket without responding".to_owned(),
+        ))
+    }
+}
+
+fn transport_error(err: tungstenite::Error) -> GatewayError {
+    GatewayError::Transport(err.to_string())
+}
+
+#[async_trait]
+impl GatewayClient for WebSocketGatewayClient {
+    /// WebSocket gateways are always sent the lookup's JSON body, since
+    /// there is no GET request to put it in the URL of
+    async fn fetch(
+        &self,
+        url: &str,
+        body: Option<&Value>,
+        _request_id: Option<&str>,
+    ) -> Result<Vec<u8>, GatewayError> {
+        let Some(body) = body else {
+            return Err(GatewayError::Transport(
+                "WebSocket gateways can only be sent lookups".to_owned(),
+            ));
+        };
+        timeout(self.timeout, self.exchange(url, body.to_string()))
+            .await
+            .map_err(|_| GatewayError::Timeout)?
+    }
+
+    /// Only opens a connection, since gateways may not respond until
+    /// they're sent a lookup
+    async fn probe(&self, url: &str) -> Result<StatusCode, GatewayError> {
+        let mut socket = timeout(self.timeout, self.connect(url))
+            .await
+            .map_err(|_| GatewayError::Timeout)??;
+        drop(socket.close(None).await);
+        Ok(StatusCode::SWITCHING_PROTOCOLS)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, net::SocketAddr};
+
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use serde_json::json;
+    use tokio::{net::TcpListener, sync::oneshot};
+    use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request};
+
+    use crate::msg::metadata::SystemClock;
+
+    use super::*;
+
+    /// Accepts WebSocket connections, answering each request frame with
+    /// what `respond` returns for it, or not at all for `None`
+    async fn run_websocket_gateway(
+        respond: impl Fn(Value) -> Option<String> + Send + Sync + Copy + 'static,
+    ) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    while let Some(Ok(Message::Text(request))) = socket.next().await {
+                        if let Some(response) = respond(serde_json::from_str(&request).unwrap()) {
+                            socket.send(Message::Text(response)).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    fn client(timeout: Duration) -> WebSocketGatewayClient {
+        WebSocketGatewayClient::new(
+            &CcipReadConf {
+                gateway_timeout: timeout,
+                ..Default::default()
+            },
+            Arc::new(SystemClock),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_lookup_is_answered_over_websocket() {
+        let addr = run_websocket_gateway(|request| {
+            // Echoes the call data back as the metadata
+            Some(json!({ "data": request["data"] }).to_string())
+        })
+        .await;
+
+        let body = json!({ "sender": "0x01", "data": "0x010203" });
+        let response = client(Duration::from_secs(5))
+            .fetch(&format!("ws://{addr}/"), Some(&body), None)
+            .await
+            .unwrap();
+        let response: Value = serde_json::from_slice(&response).unwrap();
+        assert_eq!(response, json!({ "data": "0x010203" }));
+    }
+
+    #[tokio::test]
+    async fn test_silent_websocket_gateway_times_out() {
+        let addr = run_websocket_gateway(|_| None).await;
+
+        let body = json!({ "sender": "0x01", "data": "0x010203" });
+        let res = client(Duration::from_millis(200))
+            .fetch(&format!("ws://{addr}/"), Some(&body), None)
+            .await;
+        assert!(matches!(res, Err(GatewayError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_websocket_response_is_rejected() {
+        let addr = run_websocket_gateway(|_| Some(format!("0x{}", "ab".repeat(1024)))).await;
+
+        let client = WebSocketGatewayClient::new(
+            &CcipReadConf {
+                gateway_timeout: Duration::from_secs(5),
+                max_response_bytes: 1024,
+                ..Default::default()
+            },
+            Arc::new(SystemClock),
+        );
+        let body = json!({ "sender": "0x01", "data": "0x010203" });
+        let res = client
+            .fetch(&format!("ws://{addr}/"), Some(&body), None)
+            .await;
+        assert!(matches!(res, Err(GatewayError::ResponseTooLarge(1024))));
+    }
+
+    #[tokio::test]
+    async fn test_gateway_headers_are_sent_with_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (authorization_tx, authorization_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket =
+                tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response| {
+                    let authorization = request.headers().get(AUTHORIZATION).cloned();
+                    authorization_tx.send(authorization).unwrap();
+                    Ok::<_, ErrorResponse>(response)
+                })
+                .await
+                .unwrap();
+            socket.send(Message::Text("0x01".to_owned())).await.unwrap();
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        let client = WebSocketGatewayClient::new(
+            &CcipReadConf {
+                gateway_timeout: Duration::from_secs(5),
+                gateway_headers: HashMap::from([("127.0.0.1".to_owned(), headers)]),
+                ..Default::default()
+            },
+            Arc::new(SystemClock),
+        );
+        let body = json!({ "sender": "0x01", "data": "0x010203" });
+        let response = client
+            .fetch(&format!("ws://{addr}/"), Some(&body), None)
+            .await
+            .unwrap();
+        assert_eq!(response, b"0x01");
+        assert_eq!(
+            authorization_rx.await.unwrap(),
+            Some(HeaderValue::from_static("Bearer secret"))
+        );
+    }
+
+    #[test]
+    fn test_websocket_urls_are_recognized() {
+        assert!(is_websocket_url("ws://gateway.example.com/"));
+        assert!(is_websocket_url("WSS://gateway.example.com/lookup"));
+        assert!(!is_websocket_url("https://gateway.example.com/"));
+        assert!(!is_websocket_url("not a url"));
+    }
+}
@@ -1,12 +1,13 @@
 #![allow(clippy::blocks_in_conditions)] // TODO: `rustc` 1.80.1 clippy issue
 #![allow(clippy::unnecessary_get_then_check)] // TODO: `rustc` 1.80.1 clippy issue
 
-use std::{fmt::Debug, sync::Arc};
+use std::{fmt::Debug, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use eyre::Result;
 use hyperlane_core::{HyperlaneMessage, InterchainSecurityModule, ModuleType, H256};
 
+use tokio::time::timeout;
 use tracing::instrument;
 
 use crate::msg::{
@@ -146,15 +147,35 @@ pub async fn build_message_metadata(
         ModuleType::CcipRead => Box::new(CcipReadIsmMetadataBuilder::new(message_builder)),
         _ => return Err(MetadataBuildError::UnsupportedModuleType(module_type)),
     };
+    if !metadata_builder.supports(module_type) {
+        return Err(MetadataBuildError::UnsupportedModuleType(module_type));
+    }
     let metadata = metadata_builder.build(ism_address, message, params).await?;
 
     Ok(IsmWithMetadataAndType { ism, metadata })
 }
 
+/// Builds metadata with `builder`, giving up once `deadline` has passed so a
+/// single message can't hold up its worker indefinitely. Work still in
+/// flight, such as requests to offchain gateways, is cancelled by dropping
+/// its future.
+pub async fn build_with_deadline(
+    builder: &dyn MetadataBuilder,
+    deadline: Duration,
+    ism_address: H256,
+    message: &HyperlaneMessage,
+    params: MessageMetadataBuildParams,
+) -> Result<Metadata, MetadataBuildError> {
+    timeout(deadline, builder.build(ism_address, message, params))
+        .await
+        .map_err(|_| MetadataBuildError::TimedOut(deadline))?
+}
+
 #[cfg(test)]
 mod test {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
 
+    use async_trait::async_trait;
     use hyperlane_core::{
         HyperlaneDomain, HyperlaneMessage, KnownHyperlaneDomain, Mailbox, ModuleType, H256, U256,
     };
@@ -162,8 +183,9 @@ mod test {
 
     use crate::{
         msg::metadata::{
-            base::MetadataBuildError, message_builder::build_message_metadata,
-            IsmAwareAppContextClassifier, MessageMetadataBuildParams,
+            base::MetadataBuildError,
+            message_builder::{build_message_metadata, build_with_deadline},
+            IsmAwareAppContextClassifier, MessageMetadataBuildParams, Metadata, MetadataBuilder,
         },
         settings::matching_list::{Filter, ListElement, MatchingList},
         test_utils::{
@@ -480,4 +502,64 @@ mod test {
         assert_eq!(*(params.ism_count.lock().await), 5);
         assert!(logs_contain("Max ISM count reached ism_count=5"));
     }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn self_referential_routing_ism_hits_max_depth() {
+        let base_builder = build_mock_base_builder();
+        let ism_address = H256::from_low_u64_be(0x1);
+        // Every level of the recursion looks the same ISM up again, and the
+        // last lookup happens before the depth check fails
+        insert_mock_routing_isms(&base_builder, &[(ism_address, ism_address); 4]);
+        insert_null_isms(&base_builder, &[ism_address]);
+
+        let message = HyperlaneMessage::default();
+        let message_builder = {
+            let mut builder =
+                MessageMetadataBuilder::new(Arc::new(base_builder), ism_address, &message)
+                    .await
+                    .expect("Failed to build MessageMetadataBuilder");
+            builder.max_ism_depth = 4;
+            builder
+        };
+
+        let params = MessageMetadataBuildParams::default();
+        let err = build_message_metadata(message_builder, ism_address, &message, params.clone())
+            .await
+            .expect_err("Metadata found when it should have failed");
+        assert_eq!(err, MetadataBuildError::MaxIsmDepthExceeded(4));
+        assert_eq!(*(params.ism_count.lock().await), 4);
+        assert!(logs_contain("Max ISM depth reached ism_depth=4"));
+    }
+
+    /// Never finishes building in time for a test
+    struct SlowMetadataBuilder;
+
+    #[async_trait]
+    impl MetadataBuilder for SlowMetadataBuilder {
+        async fn build(
+            &self,
+            _ism_address: H256,
+            _message: &HyperlaneMessage,
+            _params: MessageMetadataBuildParams,
+        ) -> Result<Metadata, MetadataBuildError> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(Metadata::new(vec![]))
+        }
+    }
+
+    #[tokio::test]
+    async fn build_with_deadline_times_out() {
+        let deadline = Duration::from_millis(10);
+        let err = build_with_deadline(
+            &SlowMetadataBuilder,
+            deadline,
+            H256::zero(),
+            &HyperlaneMessage::default(),
+            MessageMetadataBuildParams::default(),
+        )
+        .await
+        .expect_err("Metadata found when it should have timed out");
+        assert_eq!(err, MetadataBuildError::TimedOut(deadline));
+    }
 }
@@ -12,4 +12,10 @@ pub(crate) use base::{
     MetadataBuildError, MetadataBuilder,
 };
 pub(crate) use base_builder::{BaseMetadataBuilder, BuildsBaseMetadata};
-pub(crate) use message_builder::MessageMetadataBuilder;
+pub(crate) use ccip_read::{
+    CacheEntryInfo, CcipReadContext, CcipReadIsmMetadataBuilder, CcipReadMetrics, GatewayHealth,
+    OffchainLookupStore,
+};
+#[cfg(test)]
+pub(crate) use ccip_read::{Clock, GatewayClient, GatewayError, SystemClock};
+pub(crate) use message_builder::{build_with_deadline, MessageMetadataBuilder};
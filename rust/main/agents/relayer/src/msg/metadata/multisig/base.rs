@@ -9,7 +9,7 @@ use eyre::{Context, Result};
 use hyperlane_base::settings::CheckpointSyncerBuildError;
 use hyperlane_base::MultisigCheckpointSyncer;
 use hyperlane_core::accumulator::merkle::Proof;
-use hyperlane_core::{HyperlaneMessage, MultisigSignedCheckpoint, H256};
+use hyperlane_core::{HyperlaneMessage, ModuleType, MultisigSignedCheckpoint, H256};
 use strum::Display;
 use tracing::{debug, info};
 
@@ -39,6 +39,9 @@ pub enum MetadataToken {
 
 #[async_trait]
 pub trait MultisigIsmMetadataBuilder: AsRef<MessageMetadataBuilder> + Send + Sync {
+    /// Module type of the ISMs this builds metadata for
+    const MODULE_TYPE: ModuleType;
+
     async fn fetch_metadata(
         &self,
         validators: &[H256],
@@ -159,4 +162,8 @@ impl<T: MultisigIsmMetadataBuilder> MetadataBuilder for T {
             Err(MetadataBuildError::CouldNotFetch)
         }
     }
+
+    fn supports(&self, module_type: ModuleType) -> bool {
+        module_type == T::MODULE_TYPE
+    }
 }
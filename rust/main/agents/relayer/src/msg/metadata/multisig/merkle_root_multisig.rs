@@ -6,7 +6,7 @@ use derive_new::new;
 
 use eyre::{Context, Result};
 use hyperlane_base::MultisigCheckpointSyncer;
-use hyperlane_core::{unwrap_or_none_result, HyperlaneMessage, H256};
+use hyperlane_core::{unwrap_or_none_result, HyperlaneMessage, ModuleType, H256};
 use tracing::debug;
 
 use crate::msg::metadata::MessageMetadataBuilder;
@@ -17,6 +17,8 @@ use super::base::{MetadataToken, MultisigIsmMetadataBuilder, MultisigMetadata};
 pub struct MerkleRootMultisigMetadataBuilder(MessageMetadataBuilder);
 #[async_trait]
 impl MultisigIsmMetadataBuilder for MerkleRootMultisigMetadataBuilder {
+    const MODULE_TYPE: ModuleType = ModuleType::MerkleRootMultisig;
+
     fn token_layout(&self) -> Vec<MetadataToken> {
         vec![
             MetadataToken::CheckpointMerkleTreeHook,
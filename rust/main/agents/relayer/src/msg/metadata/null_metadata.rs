@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use derive_new::new;
 use tracing::instrument;
 
-use hyperlane_core::{HyperlaneMessage, H256};
+use hyperlane_core::{HyperlaneMessage, ModuleType, H256};
 
 use super::{MessageMetadataBuildParams, Metadata, MetadataBuildError, MetadataBuilder};
 
@@ -21,4 +21,8 @@ impl MetadataBuilder for NullMetadataBuilder {
     ) -> Result<Metadata, MetadataBuildError> {
         Ok(Metadata::new(vec![]))
     }
+
+    fn supports(&self, module_type: ModuleType) -> bool {
+        module_type == ModuleType::Null
+    }
 }
@@ -3,7 +3,7 @@ use derive_more::Deref;
 use derive_new::new;
 use tracing::instrument;
 
-use hyperlane_core::{HyperlaneMessage, H256};
+use hyperlane_core::{HyperlaneMessage, ModuleType, H256};
 
 use super::{
     base::MessageMetadataBuildParams, MessageMetadataBuilder, Metadata, MetadataBuildError,
@@ -36,4 +36,8 @@ impl MetadataBuilder for RoutingIsmMetadataBuilder {
             .map_err(|err| MetadataBuildError::FailedToBuild(err.to_string()))?;
         self.base.build(module, message, params).await
     }
+
+    fn supports(&self, module_type: ModuleType) -> bool {
+        module_type == ModuleType::Routing
+    }
 }
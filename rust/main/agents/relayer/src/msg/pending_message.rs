@@ -22,7 +22,7 @@ use hyperlane_core::{
 };
 use hyperlane_operation_verifier::ApplicationOperationVerifier;
 
-use crate::msg::metadata::{MessageMetadataBuildParams, MetadataBuildError};
+use crate::msg::metadata::{build_with_deadline, MessageMetadataBuildParams, MetadataBuildError};
 
 use super::{
     gas_payment::{GasPaymentEnforcer, GasPolicyStatus},
@@ -46,6 +46,14 @@ pub const USE_CACHE_METADATA_LOG: &str = "Reusing cached metadata";
 pub const INVALIDATE_CACHE_METADATA_LOG: &str = "Invalidating cached metadata";
 pub const ISM_MAX_DEPTH: u32 = 13;
 pub const ISM_MAX_COUNT: u32 = 100;
+pub const DEFAULT_METADATA_BUILD_TIMEOUT: Duration = Duration::from_secs(120);
+/// Backoff after the first failure to build metadata that isn't available yet
+pub const METADATA_BUILD_BACKOFF_BASE: Duration = Duration::from_secs(5);
+/// Most the backoff between metadata builds grows to, before jitter
+pub const METADATA_BUILD_BACKOFF_MAX: Duration = Duration::from_secs(60 * 60);
+/// Backoff after building metadata failed in a way that won't resolve by
+/// itself, e.g. because the ISM refused the message
+pub const TERMINAL_METADATA_BUILD_BACKOFF: Duration = Duration::from_secs(60 * 60 * 6);
 
 /// The outcome of a gas payment requirement check.
 enum GasPaymentRequirementOutcome {
@@ -72,6 +80,10 @@ pub struct MessageContext {
     pub metrics: MessageSubmissionMetrics,
     /// Application operation verifier
     pub application_operation_verifier: Option<Arc<dyn ApplicationOperationVerifier>>,
+    /// Maximum depth of nested ISMs to build metadata for
+    pub max_ism_depth: u32,
+    /// Deadline for building a message's metadata, including all nested ISMs
+    pub metadata_build_timeout: Duration,
 }
 
 /// A message that the submitter can and should try to submit.
@@ -106,6 +118,10 @@ pub struct PendingMessage {
     #[new(default)]
     #[serde(skip_serializing)]
     metric: Option<Arc<IntGauge>>,
+    /// Consecutive attempts that failed because metadata wasn't available yet
+    #[new(default)]
+    #[serde(skip_serializing)]
+    metadata_build_failures: u32,
 }
 
 impl Debug for PendingMessage {
@@ -786,6 +802,44 @@ impl PendingMessage {
         }
     }
 
+    /// How long to wait after `failures` consecutive attempts failed to build
+    /// metadata that wasn't available yet. Doubles from
+    /// `METADATA_BUILD_BACKOFF_BASE` up to `METADATA_BUILD_BACKOFF_MAX`, plus up
+    /// to half as much again at random, so messages that failed together are
+    /// retried apart.
+    /// `pub(crate)` for testing purposes
+    pub(crate) fn calculate_metadata_build_backoff(failures: u32) -> Duration {
+        let doublings = failures.saturating_sub(1).min(31);
+        let backoff = METADATA_BUILD_BACKOFF_BASE
+            .saturating_mul(1 << doublings)
+            .min(METADATA_BUILD_BACKOFF_MAX);
+        backoff.mul_f64(1.0 + rand::random::<f64>() / 2.0)
+    }
+
+    /// How long to back off after the `failures`th consecutive failure to
+    /// build metadata with `err`. Terminal failures wait
+    /// `TERMINAL_METADATA_BUILD_BACKOFF`, metadata that isn't available yet
+    /// backs off exponentially, and other failures follow the usual retry
+    /// schedule only.
+    /// `pub(crate)` for testing purposes
+    pub(crate) fn metadata_build_backoff(
+        err: &MetadataBuildError,
+        failures: u32,
+    ) -> Option<Duration> {
+        if err.is_terminal() {
+            return Some(TERMINAL_METADATA_BUILD_BACKOFF);
+        }
+        match err {
+            MetadataBuildError::CouldNotFetch
+            | MetadataBuildError::AwaitingOffchainData
+            | MetadataBuildError::AggregationThresholdNotMet(_)
+            | MetadataBuildError::TimedOut(_) => {
+                Some(PendingMessage::calculate_metadata_build_backoff(failures))
+            }
+            _ => None,
+        }
+    }
+
     /// Get duration we should wait before re-attempting to deliver a message
     /// given the number of retries.
     /// `pub(crate)` for testing purposes
@@ -871,7 +925,10 @@ impl PendingMessage {
         )
         .await
         {
-            Ok(message_metadata_builder) => message_metadata_builder,
+            Ok(message_metadata_builder) => MessageMetadataBuilder {
+                max_ism_depth: self.ctx.max_ism_depth,
+                ..message_metadata_builder
+            },
             Err(err) => {
                 return Err(
                     self.on_reprepare(Some(err), ReprepareReason::ErrorGettingMetadataBuilder)
@@ -881,40 +938,81 @@ impl PendingMessage {
 
         let params = MessageMetadataBuildParams::default();
 
-        let metadata = message_metadata_builder
-            .build(ism_address, &self.message, params)
-            .await
-            .map_err(|err| match &err {
-                MetadataBuildError::FailedToBuild(_) => {
-                    self.on_reprepare(Some(err), ReprepareReason::ErrorBuildingMetadata)
-                }
-                MetadataBuildError::CouldNotFetch => {
-                    self.on_reprepare::<String>(None, ReprepareReason::CouldNotFetchMetadata)
-                }
-                // If the metadata building is refused, we still allow it to be retried later.
-                MetadataBuildError::Refused(reason) => {
-                    warn!(?reason, "Metadata building refused");
-                    self.on_reprepare::<String>(None, ReprepareReason::MessageMetadataRefused)
-                }
-                // These errors cannot be recovered from, so we drop them
-                MetadataBuildError::UnsupportedModuleType(reason) => {
-                    warn!(?reason, "Unsupported module type");
-                    self.on_reprepare(Some(err), ReprepareReason::ErrorBuildingMetadata)
-                }
-                MetadataBuildError::MaxIsmDepthExceeded(depth) => {
-                    warn!(depth, "Max ISM depth reached");
-                    self.on_reprepare(Some(err), ReprepareReason::ErrorBuildingMetadata)
-                }
-                MetadataBuildError::MaxIsmCountReached(count) => {
-                    warn!(count, "Max ISM count reached");
-                    self.on_reprepare(Some(err), ReprepareReason::ErrorBuildingMetadata)
-                }
-                MetadataBuildError::AggregationThresholdNotMet(threshold) => {
-                    warn!(threshold, "Aggregation threshold not met");
-                    self.on_reprepare(Some(err), ReprepareReason::CouldNotFetchMetadata)
-                }
-            })?;
-        Ok(metadata)
+        let res = build_with_deadline(
+            &message_metadata_builder,
+            self.ctx.metadata_build_timeout,
+            ism_address,
+            &self.message,
+            params,
+        )
+        .await;
+        let backoff = res.as_ref().err().and_then(|err| {
+            PendingMessage::metadata_build_backoff(
+                err,
+                self.metadata_build_failures.saturating_add(1),
+            )
+        });
+        let metadata = res.map_err(|err| match &err {
+            MetadataBuildError::FailedToBuild(_) => {
+                self.on_reprepare(Some(err), ReprepareReason::ErrorBuildingMetadata)
+            }
+            MetadataBuildError::CouldNotFetch => {
+                self.on_reprepare::<String>(None, ReprepareReason::CouldNotFetchMetadata)
+            }
+            MetadataBuildError::AwaitingOffchainData => {
+                self.on_reprepare::<String>(None, ReprepareReason::AwaitingOffchainGatewayData)
+            }
+            // If the metadata building is refused, we still allow it to be retried later.
+            MetadataBuildError::Refused(reason) => {
+                warn!(?reason, "Metadata building refused");
+                self.on_reprepare::<String>(None, ReprepareReason::MessageMetadataRefused)
+            }
+            // These errors cannot be recovered from, so we drop them
+            MetadataBuildError::UnsupportedModuleType(reason) => {
+                warn!(?reason, "Unsupported module type");
+                self.on_reprepare(Some(err), ReprepareReason::ErrorBuildingMetadata)
+            }
+            MetadataBuildError::NotCcipReadIsm(module_type) => {
+                warn!(?module_type, "ISM is not a CCIP-read ISM");
+                self.on_reprepare(Some(err), ReprepareReason::ErrorBuildingMetadata)
+            }
+            MetadataBuildError::MaxIsmDepthExceeded(depth) => {
+                warn!(depth, "Max ISM depth reached");
+                self.on_reprepare(Some(err), ReprepareReason::ErrorBuildingMetadata)
+            }
+            MetadataBuildError::MaxIsmCountReached(count) => {
+                warn!(count, "Max ISM count reached");
+                self.on_reprepare(Some(err), ReprepareReason::ErrorBuildingMetadata)
+            }
+            MetadataBuildError::AggregationThresholdNotMet(threshold) => {
+                warn!(threshold, "Aggregation threshold not met");
+                self.on_reprepare(Some(err), ReprepareReason::CouldNotFetchMetadata)
+            }
+            MetadataBuildError::TimedOut(deadline) => {
+                warn!(?deadline, "Timed out building metadata");
+                self.on_reprepare(Some(err), ReprepareReason::CouldNotFetchMetadata)
+            }
+        });
+        match (&metadata, backoff) {
+            (Ok(_), _) => self.metadata_build_failures = 0,
+            (Err(_), Some(backoff)) => self.back_off_metadata_build(backoff),
+            (Err(_), None) => {}
+        }
+        metadata
+    }
+
+    /// Pushes the next attempt back by `backoff`, on top of the usual retry
+    /// schedule, so messages whose metadata isn't available yet or can't be
+    /// built at all don't crowd out the rest of the queue
+    fn back_off_metadata_build(&mut self, backoff: Duration) {
+        self.metadata_build_failures = self.metadata_build_failures.saturating_add(1);
+        let next_attempt_after = self.last_attempted_at + backoff;
+        self.next_attempt_after = self.next_attempt_after.max(Some(next_attempt_after));
+        debug!(
+            failures = self.metadata_build_failures,
+            ?backoff,
+            "Backing off building metadata"
+        );
     }
 
     /// clear metadata cache
@@ -972,7 +1070,7 @@ mod test {
     use hyperlane_base::db::*;
     use hyperlane_core::{identifiers::UniqueIdentifier, *};
 
-    use crate::msg::pending_message::DEFAULT_MAX_MESSAGE_RETRIES;
+    use crate::msg::{metadata::MetadataBuildError, pending_message::DEFAULT_MAX_MESSAGE_RETRIES};
 
     use super::PendingMessage;
 
@@ -1255,4 +1353,44 @@ mod test {
 
         assert_eq!(num_retries_in_range, 2);
     }
+
+    #[test]
+    fn test_metadata_build_backoff_increases_with_failures() {
+        let mut last_backoff = Duration::ZERO;
+        for failures in 1..=8 {
+            let backoff = PendingMessage::calculate_metadata_build_backoff(failures);
+            let unjittered = super::METADATA_BUILD_BACKOFF_BASE * 2u32.pow(failures - 1);
+            assert!(backoff >= unjittered && backoff < unjittered.mul_f64(1.5));
+            assert!(backoff > last_backoff);
+            last_backoff = backoff;
+        }
+
+        let capped = PendingMessage::calculate_metadata_build_backoff(u32::MAX);
+        assert!(capped >= super::METADATA_BUILD_BACKOFF_MAX);
+        assert!(capped < super::METADATA_BUILD_BACKOFF_MAX.mul_f64(1.5));
+    }
+
+    #[test]
+    fn test_terminal_metadata_build_failures_back_off_long() {
+        for err in [
+            MetadataBuildError::Refused("gateway rejects the message".to_owned()),
+            MetadataBuildError::NotCcipReadIsm(ModuleType::Routing),
+            MetadataBuildError::MaxIsmDepthExceeded(13),
+        ] {
+            assert!(err.is_terminal());
+            assert_eq!(
+                PendingMessage::metadata_build_backoff(&err, 1),
+                Some(super::TERMINAL_METADATA_BUILD_BACKOFF)
+            );
+        }
+
+        let err = MetadataBuildError::AwaitingOffchainData;
+        assert!(!err.is_terminal());
+        let backoff = PendingMessage::metadata_build_backoff(&err, 1).unwrap();
+        assert!(backoff < super::METADATA_BUILD_BACKOFF_BASE.mul_f64(1.5));
+
+        let err = MetadataBuildError::FailedToBuild("RPC error".to_owned());
+        assert!(!err.is_terminal());
+        assert_eq!(PendingMessage::metadata_build_backoff(&err, 1), None);
+    }
 }
@@ -428,9 +428,12 @@ mod test {
         merkle_tree::builder::MerkleTreeBuilder,
         msg::{
             gas_payment::GasPaymentEnforcer,
-            metadata::{BaseMetadataBuilder, IsmAwareAppContextClassifier},
+            metadata::{
+                BaseMetadataBuilder, CcipReadContext, CcipReadMetrics, IsmAwareAppContextClassifier,
+            },
         },
         processor::Processor,
+        settings::ccip_read::CcipReadConf,
     };
 
     use super::*;
@@ -505,6 +508,11 @@ mod test {
         );
         let destination_chain_conf = settings.chain_setup(destination_domain).unwrap();
         let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let ccip_read_context = CcipReadContext::new(
+            &CcipReadConf::default(),
+            CcipReadMetrics::new(&core_metrics),
+        )
+        .unwrap();
         BaseMetadataBuilder::new(
             origin_domain.clone(),
             destination_chain_conf.clone(),
@@ -514,6 +522,7 @@ mod test {
             Arc::new(core_metrics),
             db.clone(),
             IsmAwareAppContextClassifier::new(Arc::new(MockMailboxContract::default()), vec![]),
+            Arc::new(ccip_read_context),
         )
     }
 
@@ -531,6 +540,8 @@ mod test {
             transaction_gas_limit: Default::default(),
             metrics: dummy_submission_metrics(),
             application_operation_verifier: Some(Arc::new(DummyApplicationOperationVerifier {})),
+            max_ism_depth: ISM_MAX_DEPTH,
+            metadata_build_timeout: DEFAULT_METADATA_BUILD_TIMEOUT,
         });
 
         let (send_channel, receive_channel) = mpsc::unbounded_channel::<QueueOperation>();
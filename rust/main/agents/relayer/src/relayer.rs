@@ -41,7 +41,10 @@ use crate::{
     msg::{
         blacklist::AddressBlacklist,
         gas_payment::GasPaymentEnforcer,
-        metadata::{BaseMetadataBuilder, IsmAwareAppContextClassifier},
+        metadata::{
+            BaseMetadataBuilder, CcipReadContext, CcipReadMetrics, IsmAwareAppContextClassifier,
+            OffchainLookupStore,
+        },
         op_submitter::{SerialSubmitter, SerialSubmitterMetrics},
         pending_message::{MessageContext, MessageSubmissionMetrics},
         processor::{MessageProcessor, MessageProcessorMetrics},
@@ -88,6 +91,8 @@ pub struct Relayer {
     allow_local_checkpoint_syncers: bool,
     metric_app_contexts: Vec<(MatchingList, String)>,
     max_retries: u32,
+    /// Shared by the metadata builders of every message context
+    ccip_read_context: Arc<CcipReadContext>,
     core_metrics: Arc<CoreMetrics>,
     // TODO: decide whether to consolidate `agent_metrics` and `chain_metrics` into a single struct
     // or move them in `core_metrics`, like the validator metrics
@@ -271,6 +276,16 @@ impl BaseAgent for Relayer {
             .collect();
         debug!(elapsed = ?start_entity_init.elapsed(), event = "initialized gas payment enforcers", "Relayer startup duration measurement");
 
+        // shared across all message contexts so gateway connections are pooled
+        let mut ccip_read_context =
+            CcipReadContext::new(&settings.ccip_read, CcipReadMetrics::new(&core_metrics))?;
+        if settings.ccip_read.persist_offchain_lookups {
+            ccip_read_context = ccip_read_context.with_offchain_lookup_store(
+                OffchainLookupStore::new(db.clone(), settings.ccip_read.offchain_lookup_cache_ttl),
+            );
+        }
+        let ccip_read_context = Arc::new(ccip_read_context);
+
         let mut msg_ctxs = HashMap::new();
         let mut destination_chains = HashMap::new();
 
@@ -303,6 +318,7 @@ impl BaseAgent for Relayer {
                         dest_mailbox.clone(),
                         settings.metric_app_contexts.clone(),
                     ),
+                    ccip_read_context.clone(),
                 );
 
                 msg_ctxs.insert(
@@ -318,6 +334,8 @@ impl BaseAgent for Relayer {
                         transaction_gas_limit,
                         metrics: MessageSubmissionMetrics::new(&core_metrics, origin, destination),
                         application_operation_verifier: application_operation_verifier.cloned(),
+                        max_ism_depth: settings.max_ism_depth,
+                        metadata_build_timeout: settings.metadata_build_timeout,
                     }),
                 );
             }
@@ -344,6 +362,7 @@ impl BaseAgent for Relayer {
             allow_local_checkpoint_syncers: settings.allow_local_checkpoint_syncers,
             metric_app_contexts: settings.metric_app_contexts,
             max_retries: settings.max_retries,
+            ccip_read_context,
             core_metrics,
             agent_metrics,
             chain_metrics,
@@ -468,6 +487,13 @@ impl BaseAgent for Relayer {
         let custom_routes = relayer_server::Server::new(self.destination_chains.len())
             .with_op_retry(sender.clone())
             .with_message_queue(prep_queues)
+            .with_ccip_read_context(self.ccip_read_context.clone())
+            .with_message_contexts(
+                self.msg_ctxs
+                    .iter()
+                    .map(|(key, ctx)| ((key.origin, key.destination), ctx.clone()))
+                    .collect(),
+            )
             .routes();
         let server = self
             .core
@@ -505,6 +531,7 @@ impl BaseAgent for Relayer {
                 "Relayer task panicked"
             );
         }
+        self.ccip_read_context.shutdown().await;
     }
 }
 
@@ -0,0 +1,536 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing, Json, Router,
+};
+use derive_new::new;
+use ethers::{
+    types::{Address, Bytes},
+    utils::hex,
+};
+use hyperlane_core::{HyperlaneMessage, H256};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    msg::{
+        metadata::{
+            CacheEntryInfo, CcipReadContext, CcipReadIsmMetadataBuilder, MessageMetadataBuilder,
+            Metadata,
+        },
+        pending_message::{MessageContext, ISM_MAX_COUNT},
+    },
+    settings::matching_list::MatchingList,
+};
+
+use super::{MessageRetryApi, MessageRetryResponse};
+
+const CCIP_READ_CACHE_API_BASE: &str = "/ccip_read_cache";
+
+/// Lets operators drop cached CCIP-read state, e.g. after an ISM's gateway
+/// URLs changed
+#[derive(new, Clone)]
+pub struct CcipReadCacheApi {
+    context: Arc<CcipReadContext>,
+    /// Used to retry messages whose metadata is rebuilt, if retries are served
+    #[new(default)]
+    retry: Option<MessageRetryApi>,
+    /// Contexts of the messages whose `OffchainLookup` can be inspected, by
+    /// origin and destination domain
+    #[new(default)]
+    message_contexts: HashMap<(u32, u32), Arc<MessageContext>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct InvalidateOffchainLookupsRequest {
+    /// Only drop lookups for this ISM, or all of them if unset
+    ism_address: Option<H256>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct InvalidateOffchainLookupsResponse {
+    /// how many cached lookups were dropped
+    pub invalidated: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct OffchainLookupRequest {
+    /// ISM to call, or the ISM of the message's recipient if unset
+    ism_address: Option<H256>,
+}
+
+/// The `OffchainLookup` an ISM reverts with for a message
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct OffchainLookupResponse {
+    pub ism_address: H256,
+    pub sender: Address,
+    pub urls: Vec<String>,
+    pub call_data: Bytes,
+    /// the 4-byte selector of the callback, hex encoded
+    pub callback_function: String,
+    pub extra_data: Bytes,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BuildMessagesRequest {
+    message_ids: Vec<H256>,
+}
+
+/// The outcome of building the metadata of a message
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct BuildMessageResponse {
+    pub message_id: H256,
+    /// the metadata built, if the build succeeded
+    pub metadata: Option<Bytes>,
+    /// why no metadata was built, if the build failed
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PrefetchOffchainLookupsRequest {
+    message_ids: Vec<H256>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PrefetchOffchainLookupsResponse {
+    /// how many of the messages' lookups are cached
+    pub prefetched: usize,
+    /// messages that weren't found, or whose ISM couldn't be determined
+    pub skipped: Vec<H256>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct RebuildMessageResponse {
+    /// length of the metadata built
+    pub metadata_len: usize,
+    /// lowercase host of the gateway the metadata came from, empty for
+    /// `data:` URIs and the fallback source
+    pub gateway_host: String,
+    /// whether the metadata came from the fallback source
+    pub fallback: bool,
+    /// the retry of the message's pending operation, if retries are served
+    pub retry: Option<MessageRetryResponse>,
+}
+
+async fn invalidate_offchain_lookups(
+    State(api): State<CcipReadCacheApi>,
+    Query(request): Query<InvalidateOffchainLookupsRequest>,
+) -> Json<InvalidateOffchainLookupsResponse> {
+    let invalidated = api
+        .context
+        .invalidate_offchain_lookups(request.ism_address)
+        .await;
+    tracing::info!(
+        ism_address = ?request.ism_address,
+        invalidated,
+        "Invalidated cached CCIP-read offchain lookups"
+    );
+    Json(InvalidateOffchainLookupsResponse { invalidated })
+}
+
+/// Lists what's cached for CCIP-read lookups, without URLs or metadata
+async fn list_cache_entries(State(api): State<CcipReadCacheApi>) -> Json<Vec<CacheEntryInfo>> {
+    Json(api.context.dump_cache().await)
+}
+
+/// Builds a message's metadata again from its ISM and the gateways right
+/// away, ignoring what's cached for it, including a recent failure, and
+/// regardless of its pending operation's backoff. Then retries the
+/// operation, which picks up the fresh metadata, if retries are served.
+async fn rebuild_message(
+    State(api): State<CcipReadCacheApi>,
+    Path(message_id): Path<H256>,
+) -> Result<Json<RebuildMessageResponse>, (StatusCode, String)> {
+    let (message, ctx) = api.find_message(message_id)?;
+    let ism_address = recipient_ism(&ctx, &message).await?;
+    let (metadata, source) = ccip_read_builder(&ctx)
+        .rebuild_with_source(ism_address, &message)
+        .await
+        .map_err(|err| (StatusCode::BAD_GATEWAY, format!("{err:?}")))?;
+    let retry = match &api.retry {
+        Some(retry) => Some(
+            retry
+                .retry(MatchingList::with_message_id(message_id))
+                .await
+                .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?,
+        ),
+        None => None,
+    };
+    tracing::info!(
+        ?message_id,
+        gateway = source.host,
+        retried = retry.is_some(),
+        "Rebuilt CCIP-read metadata of message"
+    );
+    Ok(Json(RebuildMessageResponse {
+        metadata_len: metadata.to_vec().len(),
+        gateway_host: source.host,
+        fallback: source.fallback,
+        retry,
+    }))
+}
+
+/// The `OffchainLookup` the ISM of a message reverts with, as decoded and
+/// before any configured URL overrides, to debug which gateways it's sent to.
+/// No gateway is queried.
+async fn get_offchain_lookup(
+    State(api): State<CcipReadCacheApi>,
+    Path(message_id): Path<H256>,
+    Query(request): Query<OffchainLookupRequest>,
+) -> Result<Json<OffchainLookupResponse>, (StatusCode, String)> {
+    let (message, ctx) = api.find_message(message_id)?;
+    let ism_address = match request.ism_address {
+        Some(ism_address) => ism_address,
+        None => recipient_ism(&ctx, &message).await?,
+    };
+    let lookup = ccip_read_builder(&ctx)
+        .offchain_lookup_for(ism_address, &message)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "ISM doesn't revert with an OffchainLookup".to_owned(),
+            )
+        })?;
+    Ok(Json(OffchainLookupResponse {
+        ism_address,
+        sender: lookup.sender,
+        urls: lookup.urls,
+        call_data: lookup.call_data,
+        callback_function: format!("0x{}", hex::encode(lookup.callback_function)),
+        extra_data: lookup.extra_data,
+    }))
+}
+
+/// Builds the metadata of several messages at once, like their pending
+/// operations would, to check what the gateways return for a burst of
+/// messages. Nothing is submitted. Outcomes are in the order of the request.
+async fn build_messages(
+    State(api): State<CcipReadCacheApi>,
+    Json(request): Json<BuildMessagesRequest>,
+) -> Json<Vec<BuildMessageResponse>> {
+    let mut outcomes: Vec<Result<Metadata, String>> =
+        vec![Err("Not built".to_owned()); request.message_ids.len()];
+    // Messages are built in batches sharing the builder of their context
+    let mut batches: HashMap<(u32, u32), (Arc<MessageContext>, Vec<_>)> = HashMap::new();
+    for (index, message_id) in request.message_ids.iter().enumerate() {
+        let found = match api.find_message(*message_id) {
+            Ok((message, ctx)) => recipient_ism(&ctx, &message)
+                .await
+                .map(|ism_address| (message, ctx, ism_address)),
+            Err(err) => Err(err),
+        };
+        match found {
+            Ok((message, ctx, ism_address)) => batches
+                .entry((message.origin, message.destination))
+                .or_insert_with(|| (ctx, Vec::new()))
+                .1
+                .push((index, ism_address, message)),
+            Err((_, reason)) => outcomes[index] = Err(reason),
+        }
+    }
+    for (ctx, batch) in batches.into_values() {
+        let items: Vec<_> = batch
+            .iter()
+            .map(|(_, ism_address, message)| (*ism_address, message))
+            .collect();
+        let results = ccip_read_builder(&ctx).build_batch(&items).await;
+        for ((index, _, _), res) in batch.iter().zip(results) {
+            outcomes[*index] = res.map_err(|err| format!("{err:?}"));
+        }
+    }
+    Json(
+        request
+            .message_ids
+            .into_iter()
+            .zip(outcomes)
+            .map(|(message_id, outcome)| match outcome {
+                Ok(metadata) => BuildMessageResponse {
+                    message_id,
+                    metadata: Some(metadata.to_vec().into()),
+                    error: None,
+                },
+                Err(error) => BuildMessageResponse {
+                    message_id,
+                    metadata: None,
+                    error: Some(error),
+                },
+            })
+            .collect(),
+    )
+}
+
+/// Caches the `OffchainLookup`s of several messages ahead of building their
+/// metadata, e.g. before a burst of messages to the same ISM is processed.
+/// No gateway is queried.
+async fn prefetch_offchain_lookups(
+    State(api): State<CcipReadCacheApi>,
+    Json(request): Json<PrefetchOffchainLookupsRequest>,
+) -> Json<PrefetchOffchainLookupsResponse> {
+    let mut skipped = vec![];
+    // Lookups are prefetched in batches sharing their context and ISM
+    let mut batches: HashMap<(u32, u32, H256), (Arc<MessageContext>, Vec<_>)> = HashMap::new();
+    for message_id in request.message_ids {
+        let found = match api.find_message(message_id) {
+            Ok((message, ctx)) => recipient_ism(&ctx, &message)
+                .await
+                .map(|ism_address| (message, ctx, ism_address)),
+            Err(err) => Err(err),
+        };
+        match found {
+            Ok((message, ctx, ism_address)) => batches
+                .entry((message.origin, message.destination, ism_address))
+                .or_insert_with(|| (ctx, Vec::new()))
+                .1
+                .push(message),
+            Err(_) => skipped.push(message_id),
+        }
+    }
+    let mut prefetched = 0;
+    for ((_, _, ism_address), (ctx, messages)) in batches {
+        let messages: Vec<_> = messages.iter().collect();
+        prefetched += ccip_read_builder(&ctx)
+            .prefetch_offchain_info(ism_address, &messages)
+            .await;
+    }
+    tracing::info!(
+        prefetched,
+        skipped = skipped.len(),
+        "Prefetched CCIP-read offchain lookups"
+    );
+    Json(PrefetchOffchainLookupsResponse {
+        prefetched,
+        skipped,
+    })
+}
+
+/// The ISM the recipient of `message` has its messages verified by
+async fn recipient_ism(
+    ctx: &MessageContext,
+    message: &HyperlaneMessage,
+) -> Result<H256, (StatusCode, String)> {
+    ctx.destination_mailbox
+        .recipient_ism(message.recipient)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+fn ccip_read_builder(ctx: &MessageContext) -> CcipReadIsmMetadataBuilder {
+    CcipReadIsmMetadataBuilder::new(MessageMetadataBuilder {
+        base: ctx.metadata_builder.clone(),
+        app_context: None,
+        max_ism_depth: ctx.max_ism_depth,
+        max_ism_count: ISM_MAX_COUNT,
+    })
+}
+
+impl CcipReadCacheApi {
+    /// A message from the database of any origin, and the context it's
+    /// relayed in
+    fn find_message(
+        &self,
+        message_id: H256,
+    ) -> Result<(HyperlaneMessage, Arc<MessageContext>), (StatusCode, String)> {
+        let not_found = |reason: &str| (StatusCode::NOT_FOUND, reason.to_owned());
+        let message = self
+            .message_contexts
+            .values()
+            .find_map(|ctx| {
+                ctx.origin_db
+                    .retrieve_message_by_id(&message_id)
+                    .ok()
+                    .flatten()
+            })
+            .ok_or_else(|| not_found("Message not found"))?;
+        let ctx = self
+            .message_contexts
+            .get(&(message.origin, message.destination))
+            .ok_or_else(|| not_found("Message is not relayed"))?
+            .clone();
+        Ok((message, ctx))
+    }
+
+    pub fn with_retry(mut self, retry: MessageRetryApi) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    pub fn with_message_contexts(
+        mut self,
+        message_contexts: HashMap<(u32, u32), Arc<MessageContext>>,
+    ) -> Self {
+        self.message_contexts = message_contexts;
+        self
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route(
+                "/offchain_lookups",
+                routing::delete(invalidate_offchain_lookups),
+            )
+            .route(
+                "/offchain_lookups/prefetch",
+                routing::post(prefetch_offchain_lookups),
+            )
+            .route("/entries", routing::get(list_cache_entries))
+            .route("/messages/build", routing::post(build_messages))
+            .route(
+                "/messages/:message_id/rebuild",
+                routing::post(rebuild_message),
+            )
+            .route(
+                "/messages/:message_id/offchain_lookup",
+                routing::get(get_offchain_lookup),
+            )
+            .with_state(self.clone())
+    }
+
+    pub fn get_route(&self) -> (&'static str, Router) {
+        (CCIP_READ_CACHE_API_BASE, self.router())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use hyperlane_base::CoreMetrics;
+    use prometheus::Registry;
+
+    use crate::{msg::metadata::CcipReadMetrics, settings::ccip_read::CcipReadConf};
+
+    use super::*;
+
+    fn setup_test_server() -> SocketAddr {
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let context = CcipReadContext::new(
+            &CcipReadConf::default(),
+            CcipReadMetrics::new(&core_metrics),
+        )
+        .unwrap();
+        let (path, router) = CcipReadCacheApi::new(Arc::new(context)).get_route();
+        let app = Router::new().nest(path, router);
+
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_offchain_lookups() {
+        let addr = setup_test_server();
+        let client = reqwest::Client::new();
+
+        for query in [
+            "",
+            "?ism_address=0x0000000000000000000000000000000000000000000000000000000000000001",
+        ] {
+            let response = client
+                .delete(format!(
+                    "http://{addr}{CCIP_READ_CACHE_API_BASE}/offchain_lookups{query}"
+                ))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body: InvalidateOffchainLookupsResponse = response.json().await.unwrap();
+            assert_eq!(body, InvalidateOffchainLookupsResponse { invalidated: 0 });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_unknown_message() {
+        let addr = setup_test_server();
+        let client = reqwest::Client::new();
+
+        let response = client
+            .post(format!(
+                "http://{addr}{CCIP_READ_CACHE_API_BASE}/messages/0x0000000000000000000000000000000000000000000000000000000000000001/rebuild"
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.text().await.unwrap(), "Message not found");
+    }
+
+    #[tokio::test]
+    async fn test_offchain_lookup_of_unknown_message() {
+        let addr = setup_test_server();
+
+        let response = reqwest::get(format!(
+            "http://{addr}{CCIP_READ_CACHE_API_BASE}/messages/0x0000000000000000000000000000000000000000000000000000000000000001/offchain_lookup"
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_build_unknown_messages() {
+        let addr = setup_test_server();
+        let client = reqwest::Client::new();
+
+        let message_id = H256::from_low_u64_be(1);
+        let response = client
+            .post(format!(
+                "http://{addr}{CCIP_READ_CACHE_API_BASE}/messages/build"
+            ))
+            .json(&serde_json::json!({ "message_ids": [message_id] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Vec<BuildMessageResponse> = response.json().await.unwrap();
+        assert_eq!(
+            body,
+            vec![BuildMessageResponse {
+                message_id,
+                metadata: None,
+                error: Some("Message not found".to_owned()),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_skips_unknown_messages() {
+        let addr = setup_test_server();
+        let client = reqwest::Client::new();
+
+        let message_id = H256::from_low_u64_be(1);
+        let response = client
+            .post(format!(
+                "http://{addr}{CCIP_READ_CACHE_API_BASE}/offchain_lookups/prefetch"
+            ))
+            .json(&serde_json::json!({ "message_ids": [message_id] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: PrefetchOffchainLookupsResponse = response.json().await.unwrap();
+        assert_eq!(
+            body,
+            PrefetchOffchainLookupsResponse {
+                prefetched: 0,
+                skipped: vec![message_id],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_cache_entries() {
+        let addr = setup_test_server();
+
+        let response = reqwest::get(format!("http://{addr}{CCIP_READ_CACHE_API_BASE}/entries"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Vec<CacheEntryInfo> = response.json().await.unwrap();
+        assert!(body.is_empty());
+    }
+}
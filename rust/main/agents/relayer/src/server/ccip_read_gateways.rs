@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use axum::{extract::State, routing, Json, Router};
+use derive_new::new;
+use serde::Deserialize;
+
+use crate::msg::metadata::{CcipReadContext, GatewayHealth};
+
+const CCIP_READ_GATEWAYS_API_BASE: &str = "/ccip_read_gateways";
+
+/// Lets operators check that the CCIP-read gateways their ISMs rely on are
+/// reachable before messages need them
+#[derive(new, Clone)]
+pub struct CcipReadGatewaysApi {
+    context: Arc<CcipReadContext>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProbeGatewaysRequest {
+    /// gateway URL templates, as listed by an ISM's `OffchainLookup`
+    urls: Vec<String>,
+}
+
+async fn probe_gateways(
+    State(context): State<Arc<CcipReadContext>>,
+    Json(request): Json<ProbeGatewaysRequest>,
+) -> Json<GatewayHealth> {
+    let health = context.probe_gateways(&request.urls).await;
+    if !health.all_reachable() {
+        tracing::warn!(?health, "Some CCIP-read gateways are unreachable");
+    }
+    Json(health)
+}
+
+impl CcipReadGatewaysApi {
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/probe", routing::post(probe_gateways))
+            .with_state(self.context.clone())
+    }
+
+    pub fn get_route(&self) -> (&'static str, Router) {
+        (CCIP_READ_GATEWAYS_API_BASE, self.router())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::http::StatusCode;
+    use hyperlane_base::CoreMetrics;
+    use prometheus::Registry;
+    use serde_json::json;
+
+    use crate::{msg::metadata::CcipReadMetrics, settings::ccip_read::CcipReadConf};
+
+    use super::*;
+
+    fn setup_test_server() -> SocketAddr {
+        let core_metrics = CoreMetrics::new("dummy_relayer", 37582, Registry::new()).unwrap();
+        let context = CcipReadContext::new(
+            &CcipReadConf::default(),
+            CcipReadMetrics::new(&core_metrics),
+        )
+        .unwrap();
+        let (path, router) = CcipReadGatewaysApi::new(Arc::new(context)).get_route();
+        let app = Router::new().nest(path, router);
+
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_probe_gateways() {
+        let addr = setup_test_server();
+        let response = reqwest::Client::new()
+            .post(format!("http://{addr}{CCIP_READ_GATEWAYS_API_BASE}/probe"))
+            .json(&json!({ "urls": ["data:,0x01", "ipfs://"] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let health: GatewayHealth = response.json().await.unwrap();
+        let reachable: Vec<_> = health
+            .gateways
+            .iter()
+            .map(|probe| (probe.url.as_str(), probe.reachable))
+            .collect();
+        assert_eq!(reachable, vec![("data:,0x01", true), ("ipfs://", false)]);
+    }
+}
@@ -41,52 +41,60 @@ async fn retry_message(
     State(state): State<MessageRetryApi>,
     Json(retry_req_payload): Json<MatchingList>,
 ) -> Result<Json<MessageRetryResponse>, String> {
-    let uuid = uuid::Uuid::new_v4();
-    let uuid_string = uuid.to_string();
-
-    tracing::debug!(?retry_req_payload);
-    tracing::debug!(uuid = uuid_string, "Sending message retry request");
-
-    // Create a channel that can hold each chain's SerialSubmitter
-    // message retry responses.
-    // 3 queues for each chain (prepare, submit, confirm)
-    let (transmitter, mut receiver) =
-        mpsc::channel(SUBMITTER_QUEUE_COUNT * state.destination_chains);
-    state
-        .retry_request_transmitter
-        .send(MessageRetryRequest {
-            uuid: uuid_string.clone(),
-            pattern: retry_req_payload,
-            transmitter,
-        })
-        .map_err(|err| {
-            // Technically it's bad practice to print the error message to the user, but
-            // this endpoint is for debugging purposes only.
-            format!("Failed to send retry request to the queue: {}", err)
-        })?;
-
-    let mut resp = MessageRetryResponse {
-        uuid: uuid_string,
-        evaluated: 0,
-        matched: 0,
-    };
-
-    // Wait for responses from relayer
-    tracing::debug!(uuid = resp.uuid, "Waiting for response from relayer");
-    while let Some(relayer_resp) = receiver.recv().await {
-        tracing::debug!(
-            evaluated = relayer_resp.evaluated,
-            matched = relayer_resp.matched,
-            "Received relayer response"
-        );
-        resp.evaluated += relayer_resp.evaluated;
-        resp.matched += relayer_resp.matched;
-    }
-
-    Ok(Json(resp))
+    state.retry(retry_req_payload).await.map(Json)
 }
 
 impl MessageRetryApi {
+    /// Retries the pending operations matching `retry_req_payload` and waits
+    /// for every queue to report how many it matched
+    pub async fn retry(
+        &self,
+        retry_req_payload: MatchingList,
+    ) -> Result<MessageRetryResponse, String> {
+        let uuid = uuid::Uuid::new_v4();
+        let uuid_string = uuid.to_string();
+
+        tracing::debug!(?retry_req_payload);
+        tracing::debug!(uuid = uuid_string, "Sending message retry request");
+
+        // Create a channel that can hold each chain's SerialSubmitter
+        // message retry responses.
+        // 3 queues for each chain (prepare, submit, confirm)
+        let (transmitter, mut receiver) =
+            mpsc::channel(SUBMITTER_QUEUE_COUNT * self.destination_chains);
+        self.retry_request_transmitter
+            .send(MessageRetryRequest {
+                uuid: uuid_string.clone(),
+                pattern: retry_req_payload,
+                transmitter,
+            })
+            .map_err(|err| {
+                // Technically it's bad practice to print the error message to the user, but
+                // this endpoint is for debugging purposes only.
+                format!("Failed to send retry request to the queue: {}", err)
+            })?;
+
+        let mut resp = MessageRetryResponse {
+            uuid: uuid_string,
+            evaluated: 0,
+            matched: 0,
+        };
+
+        // Wait for responses from relayer
+        tracing::debug!(uuid = resp.uuid, "Waiting for response from relayer");
+        while let Some(relayer_resp) = receiver.recv().await {
+            tracing::debug!(
+                evaluated = relayer_resp.evaluated,
+                matched = relayer_resp.matched,
+                "Received relayer response"
+            );
+            resp.evaluated += relayer_resp.evaluated;
+            resp.matched += relayer_resp.matched;
+        }
+
+        Ok(resp)
+    }
+
     pub fn router(&self) -> Router {
         Router::new()
             .route("/", routing::post(retry_message))
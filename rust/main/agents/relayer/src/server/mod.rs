@@ -1,15 +1,21 @@
 use axum::Router;
 use derive_new::new;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 use tokio::sync::broadcast::Sender;
 
-use crate::msg::op_queue::OperationPriorityQueue;
+use crate::msg::{
+    metadata::CcipReadContext, op_queue::OperationPriorityQueue, pending_message::MessageContext,
+};
 
 pub const ENDPOINT_MESSAGES_QUEUE_SIZE: usize = 100;
 
+pub use ccip_read_cache::*;
+pub use ccip_read_gateways::*;
 pub use list_messages::*;
 pub use message_retry::*;
 
+mod ccip_read_cache;
+mod ccip_read_gateways;
 mod list_messages;
 mod message_retry;
 
@@ -20,6 +26,10 @@ pub struct Server {
     retry_transmitter: Option<Sender<MessageRetryRequest>>,
     #[new(default)]
     op_queues: Option<HashMap<u32, OperationPriorityQueue>>,
+    #[new(default)]
+    ccip_read_context: Option<Arc<CcipReadContext>>,
+    #[new(default)]
+    message_contexts: HashMap<(u32, u32), Arc<MessageContext>>,
 }
 
 impl Server {
@@ -33,16 +43,42 @@ impl Server {
         self
     }
 
+    pub fn with_ccip_read_context(mut self, ccip_read_context: Arc<CcipReadContext>) -> Self {
+        self.ccip_read_context = Some(ccip_read_context);
+        self
+    }
+
+    /// Contexts of the messages relayed, by origin and destination domain
+    pub fn with_message_contexts(
+        mut self,
+        message_contexts: HashMap<(u32, u32), Arc<MessageContext>>,
+    ) -> Self {
+        self.message_contexts = message_contexts;
+        self
+    }
+
     /// Returns a vector of agent-specific endpoint routes to be served.
     /// Can be extended with additional routes and feature flags to enable/disable individually.
     pub fn routes(self) -> Vec<(&'static str, Router)> {
         let mut routes = vec![];
-        if let Some(tx) = self.retry_transmitter {
-            routes.push(MessageRetryApi::new(tx, self.destination_chains).get_route());
+        let retry = self
+            .retry_transmitter
+            .map(|tx| MessageRetryApi::new(tx, self.destination_chains));
+        if let Some(retry) = retry.clone() {
+            routes.push(retry.get_route());
         }
         if let Some(op_queues) = self.op_queues {
             routes.push(ListOperationsApi::new(op_queues).get_route());
         }
+        if let Some(ccip_read_context) = self.ccip_read_context {
+            let mut cache_api = CcipReadCacheApi::new(ccip_read_context.clone())
+                .with_message_contexts(self.message_contexts);
+            if let Some(retry) = retry {
+                cache_api = cache_api.with_retry(retry);
+            }
+            routes.push(cache_api.get_route());
+            routes.push(CcipReadGatewaysApi::new(ccip_read_context).get_route());
+        }
 
         routes
     }
@@ -0,0 +1,1925 @@
+//! Configuration for building CCIP-read ISM metadata.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Debug},
+    fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use convert_case::Case;
+use ethers::utils::hex;
+use eyre::{eyre, Context};
+use hyperlane_base::settings::parser::{recase_json_value, ValueParser};
+use hyperlane_core::{
+    config::{ConfigErrResultExt, ConfigParsingError, ConfigPath, ConfigResultOptionExt},
+    H256, U256,
+};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    Identity, Url,
+};
+
+use super::{
+    host_filter::{normalize_host, private_network_patterns, HostFilter, HostPattern},
+    parse_json_array,
+};
+
+/// Default timeout for a single request to an offchain gateway.
+pub const DEFAULT_GATEWAY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Default number of attempts made against a gateway before trying the next one.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+/// Default delay before the first retry of a gateway request.
+pub const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// Default limit on the size of a gateway response body.
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+/// Default number of response pages fetched per gateway, i.e. `next` links
+/// are not followed.
+pub const DEFAULT_MAX_RESPONSE_PAGES: usize = 1;
+/// Default number of redirects followed for a single gateway request.
+pub const DEFAULT_MAX_REDIRECTS: usize = 3;
+/// Default time for which a lookup that failed on every gateway isn't retried.
+pub const DEFAULT_NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(30);
+/// Default time for which the `OffchainLookup` returned by an ISM is reused.
+pub const DEFAULT_OFFCHAIN_LOOKUP_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+/// Default time for which metadata returned by a gateway is reused.
+pub const DEFAULT_METADATA_CACHE_TTL: Duration = Duration::from_secs(15);
+/// Default lower bound on the time metadata is cached for based on when its
+/// attestation expires.
+pub const DEFAULT_METADATA_CACHE_MIN_TTL: Duration = Duration::from_secs(1);
+/// Default upper bound on the time metadata is cached for based on when its
+/// attestation expires.
+pub const DEFAULT_METADATA_CACHE_MAX_TTL: Duration = Duration::from_secs(5 * 60);
+/// Default fraction of a CCIP-read cache TTL randomly taken off each entry.
+pub const DEFAULT_CACHE_TTL_JITTER: f64 = 0.1;
+/// Default maximum number of entries held by each CCIP-read cache.
+pub const DEFAULT_MAX_CACHE_ENTRIES: usize = 10_000;
+/// Default maximum number of distinct gateway URLs tried for a lookup.
+pub const DEFAULT_MAX_GATEWAY_URLS: usize = 10;
+/// Default HTTP gateway `ipfs://` URLs are resolved through.
+pub const DEFAULT_IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
+/// Default number of consecutive failures after which a gateway host is
+/// short-circuited.
+pub const DEFAULT_CIRCUIT_BREAKER_FAILURES: u32 = 5;
+/// Default window within which failures count as consecutive.
+pub const DEFAULT_CIRCUIT_BREAKER_WINDOW: Duration = Duration::from_secs(60);
+/// Default time for which a failing gateway host is short-circuited.
+pub const DEFAULT_CIRCUIT_BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+/// Default number of messages whose metadata is built, or whose lookups are
+/// prefetched, at once in a batch.
+pub const DEFAULT_BATCH_BUILD_CONCURRENCY: usize = 8;
+/// Default number of times every gateway must have failed for a message
+/// before it is reported as stuck.
+pub const DEFAULT_STUCK_MESSAGE_FAILURES: u32 = 20;
+/// Default time before a gateway's certificate expires from which a warning
+/// is logged.
+pub const DEFAULT_CERT_EXPIRY_WARNING: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+/// Default interval at which the gateway headers file is checked for changes.
+pub const DEFAULT_GATEWAY_HEADERS_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+/// Default `User-Agent` gateway requests are sent with.
+pub const DEFAULT_USER_AGENT: &str = concat!("hyperlane-relayer/", env!("CARGO_PKG_VERSION"));
+/// Default JSON pointer to the metadata in a gateway response, as per EIP-3668.
+pub const DEFAULT_RESPONSE_DATA_POINTER: &str = "/data";
+
+/// How the metadata is read out of a gateway response body
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GatewayResponseFormat {
+    /// Try the JSON `data` field first and fall back to a raw hex body
+    #[default]
+    Auto,
+    /// The EIP-3668 `{"data": "0x..."}` envelope
+    Json,
+    /// The body is the hex encoded metadata, with or without `0x`
+    RawHex,
+}
+
+/// How the data in a gateway response is encoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseDataEncoding {
+    /// Hex, with or without `0x`, as per EIP-3668
+    #[default]
+    Hex,
+    /// Standard base64
+    Base64,
+    /// Hex of the ABI encoding of a single `bytes` value
+    AbiBytes,
+    /// No encoding: the whole response body is the metadata, whatever the
+    /// response format
+    Raw,
+}
+
+/// Responses a non-default [`ResponseDataEncoding`] is configured for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ResponseDecoderScope {
+    /// Responses to lookups of the ISM at this address
+    Ism(H256),
+    /// Responses from this gateway host
+    Host(String),
+}
+
+/// HTTP method forced for a gateway, instead of the EIP-3668 convention of
+/// GET for URLs containing `{data}` and POST otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GatewayMethod {
+    /// Always GET the interpolated URL, e.g. for gateways that reject POST
+    Get,
+    /// Always POST the `sender` and `data` to the interpolated URL
+    Post,
+}
+
+/// Gateway URLs an operator configured for an ISM, e.g. while the URLs it
+/// lists onchain are broken
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GatewayUrlOverride {
+    /// URL templates, interpolated like the ones listed onchain
+    pub urls: Vec<String>,
+    /// If true, the URLs are tried after the onchain ones instead of
+    /// replacing them
+    pub append: bool,
+}
+
+/// Length metadata for an ISM must have, e.g. because the ISM decodes it
+/// with a fixed layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataLength {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl MetadataLength {
+    pub fn contains(&self, len: usize) -> bool {
+        (self.min..=self.max).contains(&len)
+    }
+}
+
+impl fmt::Display for MetadataLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{} bytes", self.min)
+        } else if self.max == usize::MAX {
+            write!(f, "at least {} bytes", self.min)
+        } else {
+            write!(f, "{} to {} bytes", self.min, self.max)
+        }
+    }
+}
+
+/// Where in the metadata of an ISM the attestation it carries says it
+/// expires, as a big-endian unix timestamp in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataExpiry {
+    /// Offset of the timestamp in bytes
+    pub offset: usize,
+    /// Length of the timestamp in bytes, 32 for an ABI encoded `uint256`
+    pub length: usize,
+}
+
+impl MetadataExpiry {
+    /// The timestamp `metadata` expires at, if it's long enough to hold one.
+    /// Timestamps beyond `u64::MAX` are read as never expiring.
+    pub fn expires_at(&self, metadata: &[u8]) -> Option<u64> {
+        let end = self.offset.checked_add(self.length)?;
+        let expiry = U256::from_big_endian(metadata.get(self.offset..end)?);
+        Some(if expiry > U256::from(u64::MAX) {
+            u64::MAX
+        } else {
+            expiry.as_u64()
+        })
+    }
+}
+
+/// A client certificate presented to gateways that require mutual TLS
+#[derive(Clone)]
+pub struct ClientIdentity {
+    /// PEM encoded certificate chain
+    pub cert_pem: Vec<u8>,
+    /// PEM encoded PKCS#8 private key
+    pub key_pem: Vec<u8>,
+}
+
+/// The private key is never printed
+impl Debug for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientIdentity").finish_non_exhaustive()
+    }
+}
+
+/// Settings for how the relayer queries CCIP-read offchain gateways
+#[derive(Debug, Clone)]
+pub struct CcipReadConf {
+    /// Timeout applied to each individual gateway request
+    pub gateway_timeout: Duration,
+    /// Number of attempts made against a gateway, including the first one,
+    /// before moving on to the next URL. Only transient failures are retried.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every subsequent one
+    pub retry_base_delay: Duration,
+    /// If true, all gateways are queried at once and the first valid response
+    /// is used. Off by default since some gateways bill per request.
+    pub concurrent_gateways: bool,
+    /// Number of gateways that must return the same metadata before it is
+    /// used, so a single compromised gateway can't forge it. Above one,
+    /// gateways are queried in order until enough of them agree, whether
+    /// or not `concurrent_gateways` is set.
+    pub gateway_quorum: usize,
+    /// Extra headers sent to gateways, keyed by lowercase URL host. Header
+    /// values are marked sensitive so they are redacted from `Debug` output.
+    pub gateway_headers: HashMap<String, HeaderMap>,
+    /// File with more gateway headers, listed like `gatewayHeaders`, e.g.
+    /// mounted from a secret store. A host's headers in it replace those in
+    /// `gateway_headers`. It's re-read once it changes, so credentials can be
+    /// rotated without a restart.
+    pub gateway_headers_file: Option<PathBuf>,
+    /// How often the gateway headers file is checked for changes
+    pub gateway_headers_reload_interval: Duration,
+    /// HTTP method used for gateways that only accept one, keyed by lowercase
+    /// URL host. Other gateways follow the `{data}` convention.
+    pub gateway_methods: HashMap<String, GatewayMethod>,
+    /// Base URL relative gateway URL templates, e.g. `/ccip/{sender}/{data}`,
+    /// are resolved against as RFC 3986 references, so operators can keep
+    /// gateway endpoints in relayer config. Absolute URLs are unaffected.
+    pub gateway_base_url: Option<Url>,
+    /// If true, `{destRoot}` and `{destBlock}` in gateway URL templates are
+    /// substituted with the root of the latest checkpoint of the destination
+    /// chain's merkle tree hook and its latest block number, for gateways
+    /// whose attestations depend on the destination's state. Each is only
+    /// queried if a template uses it. Metadata is still cached for
+    /// `metadata_cache_ttl`, so that should be short for such gateways.
+    pub include_destination_state: bool,
+    /// If true, `ws://` and `wss://` gateway URLs are looked up over a
+    /// WebSocket for gateways that push metadata instead of serving it over
+    /// HTTP. See `WebSocketGatewayClient` for the exchange. Gateway headers
+    /// are sent with the handshake, but WebSocket gateways can't be used
+    /// with a proxy, a client certificate, pinned certificates, host
+    /// overrides, a gateway mirror or a response buffer budget.
+    pub websocket_gateways: bool,
+    /// `User-Agent` sent to gateways, so their operators can identify and
+    /// allowlist relayer traffic
+    pub user_agent: String,
+    /// How gateway responses are decoded
+    pub response_format: GatewayResponseFormat,
+    /// JSON pointer (RFC 6901) to the encoded metadata in JSON responses,
+    /// e.g. `/result/data`
+    pub response_data_pointer: String,
+    /// Encoding of the data in responses to particular ISMs or from
+    /// particular hosts, an ISM's taking precedence. The data is hex
+    /// otherwise.
+    pub response_decoders: HashMap<ResponseDecoderScope, ResponseDataEncoding>,
+    /// Responses with a larger body are rejected without being fully read
+    pub max_response_bytes: usize,
+    /// Maximum number of bytes buffered across all HTTP response bodies
+    /// being read at once, so a burst of large responses can't exhaust
+    /// memory. Each read reserves its `Content-Length`, or
+    /// `max_response_bytes` without one, and waits for the reservation, for
+    /// at most the gateway timeout. Unlimited if unset.
+    pub max_buffered_response_bytes: Option<usize>,
+    /// Maximum number of pages fetched for a single gateway response. Pages
+    /// are linked by a `next` URL in the JSON response and their data is
+    /// concatenated, up to `max_response_bytes` in total. One disables
+    /// following `next`.
+    pub max_response_pages: usize,
+    /// Hosts gateway requests may be sent to. Gateway URLs come from onchain
+    /// ISM configuration, so this guards against requests to internal services.
+    pub gateway_hosts: HostFilter,
+    /// Maximum number of redirects followed for a single gateway request.
+    /// Each redirect target must also be permitted by `gateway_hosts`. Zero
+    /// disables following redirects.
+    pub max_redirects: usize,
+    /// How long a lookup that failed on every gateway is not attempted again.
+    /// Zero disables caching of failures.
+    pub negative_cache_ttl: Duration,
+    /// If true, metadata is only returned if it passes a dry run of the ISM's
+    /// `verify`, trying the next gateway otherwise. Costs an extra RPC call
+    /// per candidate but avoids submitting transactions that will revert.
+    pub verify_metadata: bool,
+    /// Length of the metadata of ISMs with a known layout, keyed by ISM
+    /// address. Gateway responses of another length are skipped like ones
+    /// failing verification, catching truncated or corrupt responses early.
+    pub ism_metadata_lengths: HashMap<H256, MetadataLength>,
+    /// Where the metadata of ISMs whose gateways return time-limited
+    /// attestations holds its expiry, keyed by ISM address. Expired metadata
+    /// is skipped, and dropped from the metadata cache, so it isn't
+    /// submitted only to revert.
+    pub ism_metadata_expiry: HashMap<H256, MetadataExpiry>,
+    /// Keccak-256 hash of the metadata of ISMs whose gateways serve a static
+    /// attestation, keyed by ISM address. Responses hashing to anything else
+    /// are skipped as tampered with.
+    pub ism_metadata_hashes: HashMap<H256, H256>,
+    /// How long the `OffchainLookup` returned by an ISM's
+    /// `getOffchainVerifyInfo` is reused before calling the ISM again.
+    /// Zero disables caching.
+    pub offchain_lookup_cache_ttl: Duration,
+    /// ISMs whose `getOffchainVerifyInfo` reverts with the same
+    /// `OffchainLookup` for every message. Their lookups are cached by ISM
+    /// address alone, so only the first message within the TTL calls the ISM.
+    /// Other ISMs' lookups are cached per message.
+    pub message_independent_isms: HashSet<H256>,
+    /// ISMs verifying metadata as the calldata of the EIP-3668 callback of
+    /// their `OffchainLookup`, i.e. `callbackFunction` followed by the ABI
+    /// encoding of `(bytes response, bytes extraData)`, rather than the
+    /// gateway's response alone. Metadata from the fallback source is used
+    /// as is.
+    pub callback_encoded_isms: HashSet<H256>,
+    /// Gateway URLs used for ISMs instead of, or in addition to, the ones
+    /// in their `OffchainLookup`, keyed by ISM address. An escape hatch for
+    /// ISMs listing broken gateways onchain, so every use is logged.
+    pub ism_gateway_urls: HashMap<H256, GatewayUrlOverride>,
+    /// Gateway hosts tried first for ISMs, in order, keyed by ISM address.
+    /// The ISM's URLs at other hosts are still tried afterwards, in their
+    /// original order.
+    pub ism_gateway_priorities: HashMap<H256, Vec<String>>,
+    /// If true, the gateway tried first is picked from the ISM's URLs by the
+    /// message id, spreading messages across equivalent gateways while
+    /// keeping the order reproducible for each message. The other URLs are
+    /// still tried after it, in order. ISMs with gateway priorities are
+    /// left alone.
+    pub rotate_gateways: bool,
+    /// If true, cached `OffchainLookup`s are also persisted in the relayer's
+    /// database so they don't all have to be fetched again after a restart
+    pub persist_offchain_lookups: bool,
+    /// How long metadata returned by a gateway is reused for retries of the
+    /// same message. Kept short since gateway-served metadata, such as signed
+    /// attestations, may expire. Zero disables caching.
+    pub metadata_cache_ttl: Duration,
+    /// Metadata of ISMs with a configured expiry is instead cached until the
+    /// attestation it carries expires, clamped to between these bounds so a
+    /// gateway can't keep its metadata cached indefinitely. Expired metadata
+    /// is never reused either way.
+    pub metadata_cache_min_ttl: Duration,
+    /// Upper bound of `metadata_cache_min_ttl`'s clamp
+    pub metadata_cache_max_ttl: Duration,
+    /// How long metadata is kept in the cache after its TTL, to be used
+    /// while every gateway of its message is down, i.e. times out, can't be
+    /// reached or answers with a server error. Such metadata is still dropped once it expires, and checked like
+    /// gateway responses. Zero disables serving stale metadata.
+    pub metadata_stale_grace: Duration,
+    /// Up to this fraction of the TTL of the caches above is randomly taken
+    /// off each entry, so that entries cached at the same time, e.g. right
+    /// after startup, don't all expire and get fetched again at once. Must be
+    /// at least zero and below one.
+    pub cache_ttl_jitter: f64,
+    /// Maximum number of entries held by each of the caches above, beyond
+    /// which the least recently used entry is evicted
+    pub max_cache_entries: usize,
+    /// Maximum number of distinct gateway URLs tried for a single lookup.
+    /// Duplicate URLs are always skipped.
+    pub max_gateway_urls: usize,
+    /// HTTP gateway `ipfs://<cid>` URLs are fetched through, as a URL the
+    /// CID and path are appended to. Always ends with `/`.
+    pub ipfs_gateway: String,
+    /// Maximum number of requests in flight to a single gateway host. Further
+    /// requests wait, for at most the gateway timeout. Unlimited if unset.
+    pub max_in_flight_per_host: Option<usize>,
+    /// Maximum number of requests in flight across all gateway hosts, e.g.
+    /// to bound file descriptors and memory during a burst of lookups.
+    /// Further requests wait like for `max_in_flight_per_host`. Unlimited if
+    /// unset.
+    pub max_in_flight_total: Option<usize>,
+    /// Average number of requests per second sent to a single gateway host,
+    /// enforced with a token bucket that allows bursts of the same size.
+    /// Unlimited if unset.
+    pub max_requests_per_second_per_host: Option<f64>,
+    /// HTTP(S) proxy all gateway requests are sent through. If unset, the
+    /// `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables are
+    /// honored instead.
+    pub proxy: Option<Url>,
+    /// Comma separated hosts, domains and IP ranges that bypass `proxy`, in
+    /// the format of `NO_PROXY`. Defaults to the `NO_PROXY` environment
+    /// variable.
+    pub no_proxy: Option<String>,
+    /// Caching proxy run by the operator, e.g. shared by several relayers,
+    /// that gateway lookups are sent to instead of their gateway. The
+    /// gateway URL is sent along as `X-Gateway-Url`, and a hash of the
+    /// request's method, gateway URL and body as `X-Cache-Key`, which the
+    /// mirror can cache responses by. Requests that aren't for a message,
+    /// and requests to gateways with pinned certificates, are sent directly.
+    pub gateway_mirror: Option<Url>,
+    /// Client certificate presented to gateways, for those requiring mutual
+    /// TLS. Loaded and validated when the config is parsed.
+    pub client_identity: Option<ClientIdentity>,
+    /// SHA-256 fingerprints of the DER certificate each pinned gateway host
+    /// must present, keyed by host. Any of a host's pins may match, so
+    /// certificates can be rotated without downtime.
+    pub pinned_certificates: HashMap<String, Vec<[u8; 32]>>,
+    /// A warning is logged when a gateway's certificate expires within this
+    /// long, so operators can chase the gateway's provider before requests
+    /// start failing. Zero disables the check.
+    pub cert_expiry_warning: Duration,
+    /// Addresses gateway hosts resolve to, keyed by lowercase host, skipping
+    /// DNS for relayers talking to a small fixed set of gateways. The port
+    /// is still taken from the URL.
+    pub host_overrides: HashMap<String, Vec<IpAddr>>,
+    /// Number of consecutive failed requests to a gateway host, such as
+    /// timeouts or server errors, after which requests to it are
+    /// short-circuited. Zero disables the circuit breaker.
+    pub circuit_breaker_failures: u32,
+    /// Window within which failures must happen to count as consecutive
+    pub circuit_breaker_window: Duration,
+    /// How long requests to a failing host are short-circuited before a
+    /// single probe request is let through
+    pub circuit_breaker_cooldown: Duration,
+    /// Maximum number of messages whose metadata is built, or whose
+    /// `OffchainLookup` is prefetched, at once when handling a batch of
+    /// messages
+    pub batch_build_concurrency: usize,
+    /// Directory gateway responses are read from instead of the network,
+    /// to reproduce past metadata builds, e.g. in tests or after an incident.
+    /// See `FixtureGatewayClient` for how fixture files are named.
+    pub replay_fixture_dir: Option<PathBuf>,
+    /// If true, requests without a fixture are sent to the gateway when
+    /// replaying. Otherwise they fail as if the gateway had no response.
+    pub replay_live_fallback: bool,
+    /// Directory metadata is read from when no gateway returned usable
+    /// metadata, e.g. while gateways are under maintenance. Each file holds
+    /// the metadata of the message whose hex id it is named after.
+    pub fallback_metadata_dir: Option<PathBuf>,
+    /// URL a JSON report is POSTed to once a message's lookups failed on
+    /// every gateway `stuck_message_failures` times, so operators can look
+    /// into the ISM's configuration. Reported once per message.
+    pub stuck_message_webhook: Option<Url>,
+    /// Failed lookups after which a message is reported as stuck
+    pub stuck_message_failures: u32,
+}
+
+impl Default for CcipReadConf {
+    fn default() -> Self {
+        Self {
+            gateway_timeout: DEFAULT_GATEWAY_TIMEOUT,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retry_base_delay: DEFAULT_RETRY_BASE_DELAY,
+            concurrent_gateways: false,
+            gateway_quorum: 1,
+            gateway_headers: HashMap::new(),
+            gateway_headers_file: None,
+            gateway_headers_reload_interval: DEFAULT_GATEWAY_HEADERS_RELOAD_INTERVAL,
+            gateway_methods: HashMap::new(),
+            gateway_base_url: None,
+            include_destination_state: false,
+            websocket_gateways: false,
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+            response_format: GatewayResponseFormat::default(),
+            response_data_pointer: DEFAULT_RESPONSE_DATA_POINTER.to_owned(),
+            response_decoders: HashMap::new(),
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            max_buffered_response_bytes: None,
+            max_response_pages: DEFAULT_MAX_RESPONSE_PAGES,
+            gateway_hosts: HostFilter::default(),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            negative_cache_ttl: DEFAULT_NEGATIVE_CACHE_TTL,
+            verify_metadata: false,
+            ism_metadata_lengths: HashMap::new(),
+            ism_metadata_expiry: HashMap::new(),
+            ism_metadata_hashes: HashMap::new(),
+            offchain_lookup_cache_ttl: DEFAULT_OFFCHAIN_LOOKUP_CACHE_TTL,
+            message_independent_isms: HashSet::new(),
+            callback_encoded_isms: HashSet::new(),
+            ism_gateway_urls: HashMap::new(),
+            ism_gateway_priorities: HashMap::new(),
+            rotate_gateways: false,
+            persist_offchain_lookups: false,
+            metadata_cache_ttl: DEFAULT_METADATA_CACHE_TTL,
+            metadata_cache_min_ttl: DEFAULT_METADATA_CACHE_MIN_TTL,
+            metadata_cache_max_ttl: DEFAULT_METADATA_CACHE_MAX_TTL,
+            metadata_stale_grace: Duration::ZERO,
+            cache_ttl_jitter: DEFAULT_CACHE_TTL_JITTER,
+            max_cache_entries: DEFAULT_MAX_CACHE_ENTRIES,
+            max_gateway_urls: DEFAULT_MAX_GATEWAY_URLS,
+            ipfs_gateway: DEFAULT_IPFS_GATEWAY.to_owned(),
+            max_in_flight_per_host: None,
+            max_in_flight_total: None,
+            max_requests_per_second_per_host: None,
+            proxy: None,
+            no_proxy: None,
+            gateway_mirror: None,
+            client_identity: None,
+            host_overrides: HashMap::new(),
+            pinned_certificates: HashMap::new(),
+            cert_expiry_warning: DEFAULT_CERT_EXPIRY_WARNING,
+            circuit_breaker_failures: DEFAULT_CIRCUIT_BREAKER_FAILURES,
+            circuit_breaker_window: DEFAULT_CIRCUIT_BREAKER_WINDOW,
+            circuit_breaker_cooldown: DEFAULT_CIRCUIT_BREAKER_COOLDOWN,
+            batch_build_concurrency: DEFAULT_BATCH_BUILD_CONCURRENCY,
+            replay_fixture_dir: None,
+            replay_live_fallback: false,
+            fallback_metadata_dir: None,
+            stuck_message_webhook: None,
+            stuck_message_failures: DEFAULT_STUCK_MESSAGE_FAILURES,
+        }
+    }
+}
+
+/// Parses the `ccipRead` section of the relayer config
+pub(super) fn parse_ccip_read_conf(p: &ValueParser, err: &mut ConfigParsingError) -> CcipReadConf {
+    let Some(p) = p.get_opt_key("ccipRead").take_config_err_flat(err) else {
+        return CcipReadConf::default();
+    };
+
+    let gateway_timeout = p
+        .chain(err)
+        .get_opt_key("gatewayTimeout")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_GATEWAY_TIMEOUT);
+
+    let max_attempts = p
+        .chain(err)
+        .get_opt_key("maxAttempts")
+        .parse_u32()
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS)
+        .max(1);
+
+    let retry_base_delay = p
+        .chain(err)
+        .get_opt_key("retryBaseDelayMs")
+        .parse_u64()
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_RETRY_BASE_DELAY);
+
+    let concurrent_gateways = p
+        .chain(err)
+        .get_opt_key("concurrentGateways")
+        .parse_bool()
+        .unwrap_or(false);
+
+    let gateway_quorum = p
+        .chain(err)
+        .get_opt_key("gatewayQuorum")
+        .parse_u64()
+        .end()
+        .and_then(|quorum| {
+            if quorum > 0 {
+                Some(quorum as usize)
+            } else {
+                Err::<(), eyre::Report>(eyre!("CCIP-read gateway quorum must be at least 1"))
+                    .take_err(err, || &p.cwp + "gateway_quorum");
+                None
+            }
+        })
+        .unwrap_or(1);
+
+    let gateway_headers = p
+        .chain(err)
+        .get_opt_key("gatewayHeaders")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| parse_gateway_headers(ValueParser::new(cwp, &value), err))
+        .unwrap_or_default();
+
+    let gateway_headers_file = p
+        .chain(err)
+        .get_opt_key("gatewayHeadersFile")
+        .parse_string()
+        .end()
+        .map(PathBuf::from)
+        .and_then(|path| {
+            load_gateway_headers(&path)
+                .take_err(err, || &p.cwp + "gateway_headers_file")
+                .map(|_| path)
+        });
+
+    let gateway_headers_reload_interval = p
+        .chain(err)
+        .get_opt_key("gatewayHeadersReloadInterval")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_GATEWAY_HEADERS_RELOAD_INTERVAL);
+
+    let gateway_methods = p
+        .chain(err)
+        .get_opt_key("gatewayMethods")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| parse_gateway_methods(ValueParser::new(cwp, &value), err))
+        .unwrap_or_default();
+
+    let websocket_gateways = p
+        .chain(err)
+        .get_opt_key("websocketGateways")
+        .parse_bool()
+        .unwrap_or(false);
+
+    let include_destination_state = p
+        .chain(err)
+        .get_opt_key("includeDestinationState")
+        .parse_bool()
+        .unwrap_or(false);
+
+    let user_agent = p
+        .chain(err)
+        .get_opt_key("userAgent")
+        .parse_string()
+        .end()
+        .map(str::to_owned)
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_owned());
+
+    let response_format = match p
+        .chain(err)
+        .get_opt_key("responseFormat")
+        .parse_string()
+        .end()
+    {
+        Some("auto") | None => GatewayResponseFormat::Auto,
+        Some("json") => GatewayResponseFormat::Json,
+        Some("rawHex") => GatewayResponseFormat::RawHex,
+        Some(_) => {
+            Err::<(), eyre::Report>(eyre!(
+                "Unknown CCIP-read response format, expected `auto`, `json` or `rawHex`"
+            ))
+            .take_err(err, || &p.cwp + "response_format");
+            GatewayResponseFormat::Auto
+        }
+    };
+
+    let response_data_pointer = match p
+        .chain(err)
+        .get_opt_key("responseDataPointer")
+        .parse_string()
+        .end()
+    {
+        Some(pointer) if pointer.starts_with('/') => pointer.to_owned(),
+        Some(_) => {
+            Err::<(), eyre::Report>(eyre!("CCIP-read response data pointer must start with `/`"))
+                .take_err(err, || &p.cwp + "response_data_pointer");
+            DEFAULT_RESPONSE_DATA_POINTER.to_owned()
+        }
+        None => DEFAULT_RESPONSE_DATA_POINTER.to_owned(),
+    };
+
+    let response_decoders = p
+        .chain(err)
+        .get_opt_key("responseDecoders")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| parse_response_decoders(ValueParser::new(cwp, &value), err))
+        .unwrap_or_default();
+
+    let max_response_bytes = p
+        .chain(err)
+        .get_opt_key("maxResponseBytes")
+        .parse_u64()
+        .map(|bytes| bytes as usize)
+        .unwrap_or(DEFAULT_MAX_RESPONSE_BYTES);
+
+    let max_buffered_response_bytes = p
+        .chain(err)
+        .get_opt_key("maxBufferedResponseBytes")
+        .parse_u64()
+        .end()
+        .and_then(|max| match max {
+            0 => {
+                Err::<(), eyre::Report>(eyre!(
+                    "Max buffered CCIP-read response bytes must be positive"
+                ))
+                .take_err(err, || &p.cwp + "max_buffered_response_bytes");
+                None
+            }
+            max => Some(max as usize),
+        });
+
+    let max_response_pages = p
+        .chain(err)
+        .get_opt_key("maxResponsePages")
+        .parse_u64()
+        .map(|pages| pages as usize)
+        .unwrap_or(DEFAULT_MAX_RESPONSE_PAGES);
+
+    let mut denied_hosts = parse_host_patterns(&p, "deniedHosts", err);
+    if p.chain(err)
+        .get_opt_key("denyPrivateNetworks")
+        .parse_bool()
+        .unwrap_or(false)
+    {
+        denied_hosts.extend(private_network_patterns());
+    }
+    let gateway_hosts = HostFilter {
+        allowed: parse_host_patterns(&p, "allowedHosts", err),
+        denied: denied_hosts,
+    };
+
+    let max_redirects = p
+        .chain(err)
+        .get_opt_key("maxRedirects")
+        .parse_u64()
+        .map(|redirects| redirects as usize)
+        .unwrap_or(DEFAULT_MAX_REDIRECTS);
+
+    let negative_cache_ttl = p
+        .chain(err)
+        .get_opt_key("negativeCacheTtl")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_NEGATIVE_CACHE_TTL);
+
+    let verify_metadata = p
+        .chain(err)
+        .get_opt_key("verifyMetadata")
+        .parse_bool()
+        .unwrap_or(false);
+
+    let ism_metadata_lengths = p
+        .chain(err)
+        .get_opt_key("ismMetadataLengths")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| parse_ism_metadata_lengths(ValueParser::new(cwp, &value), err))
+        .unwrap_or_default();
+
+    let ism_metadata_expiry = p
+        .chain(err)
+        .get_opt_key("ismMetadataExpiry")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| parse_ism_metadata_expiry(ValueParser::new(cwp, &value), err))
+        .unwrap_or_default();
+
+    let ism_metadata_hashes = p
+        .chain(err)
+        .get_opt_key("ismMetadataHashes")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| parse_ism_metadata_hashes(ValueParser::new(cwp, &value), err))
+        .unwrap_or_default();
+
+    let offchain_lookup_cache_ttl = p
+        .chain(err)
+        .get_opt_key("offchainLookupCacheTtl")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_OFFCHAIN_LOOKUP_CACHE_TTL);
+
+    let message_independent_isms = p
+        .chain(err)
+        .get_opt_key("messageIndependentIsms")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| {
+            ValueParser::new(cwp, &value)
+                .into_array_iter()
+                .map(|itr| {
+                    itr.filter_map(|entry| entry.chain(err).parse_address_hash().end())
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+        .unwrap_or_default();
+
+    let callback_encoded_isms = p
+        .chain(err)
+        .get_opt_key("callbackEncodedIsms")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| {
+            ValueParser::new(cwp, &value)
+                .into_array_iter()
+                .map(|itr| {
+                    itr.filter_map(|entry| entry.chain(err).parse_address_hash().end())
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+        .unwrap_or_default();
+
+    let ism_gateway_urls = p
+        .chain(err)
+        .get_opt_key("ismGatewayUrls")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| parse_ism_gateway_urls(ValueParser::new(cwp, &value), err))
+        .unwrap_or_default();
+
+    let ism_gateway_priorities = p
+        .chain(err)
+        .get_opt_key("ismGatewayPriorities")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| parse_ism_gateway_priorities(ValueParser::new(cwp, &value), err))
+        .unwrap_or_default();
+
+    let rotate_gateways = p
+        .chain(err)
+        .get_opt_key("rotateGateways")
+        .parse_bool()
+        .unwrap_or(false);
+
+    let persist_offchain_lookups = p
+        .chain(err)
+        .get_opt_key("persistOffchainLookups")
+        .parse_bool()
+        .unwrap_or(false);
+
+    let metadata_cache_ttl = p
+        .chain(err)
+        .get_opt_key("metadataCacheTtl")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_METADATA_CACHE_TTL);
+
+    let metadata_cache_min_ttl = p
+        .chain(err)
+        .get_opt_key("metadataCacheMinTtl")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_METADATA_CACHE_MIN_TTL);
+
+    let mut metadata_cache_max_ttl = p
+        .chain(err)
+        .get_opt_key("metadataCacheMaxTtl")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_METADATA_CACHE_MAX_TTL);
+    if metadata_cache_min_ttl > metadata_cache_max_ttl {
+        Err::<(), eyre::Report>(eyre!(
+            "CCIP-read metadata cache min TTL must not exceed the max TTL"
+        ))
+        .take_err(err, || &p.cwp + "metadata_cache_max_ttl");
+        metadata_cache_max_ttl = metadata_cache_min_ttl;
+    }
+
+    let metadata_stale_grace = p
+        .chain(err)
+        .get_opt_key("metadataStaleGrace")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::ZERO);
+
+    let cache_ttl_jitter = p
+        .chain(err)
+        .get_opt_key("cacheTtlJitter")
+        .parse_f64()
+        .end()
+        .and_then(|jitter| {
+            if (0.0..1.0).contains(&jitter) {
+                Some(jitter)
+            } else {
+                Err::<(), eyre::Report>(eyre!(
+                    "CCIP-read cache TTL jitter must be at least 0 and below 1"
+                ))
+                .take_err(err, || &p.cwp + "cache_ttl_jitter");
+                None
+            }
+        })
+        .unwrap_or(DEFAULT_CACHE_TTL_JITTER);
+
+    let max_cache_entries = p
+        .chain(err)
+        .get_opt_key("maxCacheEntries")
+        .parse_u64()
+        .map(|max| max as usize)
+        .unwrap_or(DEFAULT_MAX_CACHE_ENTRIES);
+
+    let max_gateway_urls = p
+        .chain(err)
+        .get_opt_key("maxGatewayUrls")
+        .parse_u64()
+        .map(|max| max as usize)
+        .unwrap_or(DEFAULT_MAX_GATEWAY_URLS);
+
+    let ipfs_gateway = match p.chain(err).get_opt_key("ipfsGateway").parse_string().end() {
+        Some(gateway) => match Url::parse(gateway) {
+            Ok(_) if gateway.ends_with('/') => gateway.to_owned(),
+            Ok(_) => format!("{gateway}/"),
+            Err(parse_err) => {
+                Err::<(), _>(parse_err)
+                    .context("Invalid CCIP-read IPFS gateway URL")
+                    .take_err(err, || &p.cwp + "ipfs_gateway");
+                DEFAULT_IPFS_GATEWAY.to_owned()
+            }
+        },
+        None => DEFAULT_IPFS_GATEWAY.to_owned(),
+    };
+
+    let max_in_flight_per_host = p
+        .chain(err)
+        .get_opt_key("maxInFlightPerHost")
+        .parse_u64()
+        .end()
+        .and_then(|max| match max {
+            0 => {
+                Err::<(), eyre::Report>(eyre!(
+                    "Max in-flight CCIP-read requests per host must be positive"
+                ))
+                .take_err(err, || &p.cwp + "max_in_flight_per_host");
+                None
+            }
+            max => Some(max as usize),
+        });
+
+    let max_in_flight_total = p
+        .chain(err)
+        .get_opt_key("maxInFlightTotal")
+        .parse_u64()
+        .end()
+        .and_then(|max| match max {
+            0 => {
+                Err::<(), eyre::Report>(eyre!(
+                    "Max in-flight CCIP-read requests in total must be positive"
+                ))
+                .take_err(err, || &p.cwp + "max_in_flight_total");
+                None
+            }
+            max => Some(max as usize),
+        });
+
+    let max_requests_per_second_per_host = p
+        .chain(err)
+        .get_opt_key("maxRequestsPerSecondPerHost")
+        .parse_f64()
+        .end()
+        .and_then(|rate| {
+            if rate > 0.0 {
+                Some(rate)
+            } else {
+                Err::<(), eyre::Report>(eyre!(
+                    "Max CCIP-read requests per second per host must be positive"
+                ))
+                .take_err(err, || &p.cwp + "max_requests_per_second_per_host");
+                None
+            }
+        });
+
+    let gateway_base_url = p
+        .chain(err)
+        .get_opt_key("gatewayBaseUrl")
+        .parse_string()
+        .end()
+        .and_then(|base_url| {
+            Url::parse(base_url)
+                .context("Invalid CCIP-read gateway base URL")
+                .and_then(|base_url| {
+                    if base_url.cannot_be_a_base() {
+                        Err(eyre!("CCIP-read gateway base URL can't be a base"))
+                    } else {
+                        Ok(base_url)
+                    }
+                })
+                .take_err(err, || &p.cwp + "gateway_base_url")
+        });
+
+    let proxy = p
+        .chain(err)
+        .get_opt_key("proxy")
+        .parse_string()
+        .end()
+        .and_then(|proxy| {
+            Url::parse(proxy)
+                .context("Invalid CCIP-read gateway proxy URL")
+                .take_err(err, || &p.cwp + "proxy")
+        });
+
+    let no_proxy = p
+        .chain(err)
+        .get_opt_key("noProxy")
+        .parse_string()
+        .end()
+        .map(str::to_owned);
+
+    let gateway_mirror = p
+        .chain(err)
+        .get_opt_key("gatewayMirror")
+        .parse_string()
+        .end()
+        .and_then(|mirror| {
+            Url::parse(mirror)
+                .context("Invalid CCIP-read gateway mirror URL")
+                .take_err(err, || &p.cwp + "gateway_mirror")
+        });
+
+    let client_cert_path = p
+        .chain(err)
+        .get_opt_key("clientCertPath")
+        .parse_string()
+        .end();
+    let client_key_path = p
+        .chain(err)
+        .get_opt_key("clientKeyPath")
+        .parse_string()
+        .end();
+    let client_identity = match (client_cert_path, client_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            load_client_identity(cert_path, key_path).take_err(err, || &p.cwp + "client_cert_path")
+        }
+        (None, None) => None,
+        _ => {
+            Err::<(), eyre::Report>(eyre!(
+                "Both a CCIP-read client certificate and key must be given for mutual TLS"
+            ))
+            .take_err(err, || &p.cwp + "client_cert_path");
+            None
+        }
+    };
+
+    let pinned_certificates = p
+        .chain(err)
+        .get_opt_key("pinnedCertificates")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| parse_pinned_certificates(ValueParser::new(cwp, &value), err))
+        .unwrap_or_default();
+
+    let cert_expiry_warning = p
+        .chain(err)
+        .get_opt_key("certExpiryWarning")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_CERT_EXPIRY_WARNING);
+
+    let host_overrides = p
+        .chain(err)
+        .get_opt_key("hostOverrides")
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| parse_host_overrides(ValueParser::new(cwp, &value), err))
+        .unwrap_or_default();
+
+    let circuit_breaker_failures = p
+        .chain(err)
+        .get_opt_key("circuitBreakerFailures")
+        .parse_u32()
+        .unwrap_or(DEFAULT_CIRCUIT_BREAKER_FAILURES);
+
+    let circuit_breaker_window = p
+        .chain(err)
+        .get_opt_key("circuitBreakerWindow")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_CIRCUIT_BREAKER_WINDOW);
+
+    let circuit_breaker_cooldown = p
+        .chain(err)
+        .get_opt_key("circuitBreakerCooldown")
+        .parse_u64()
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_CIRCUIT_BREAKER_COOLDOWN);
+
+    let batch_build_concurrency = p
+        .chain(err)
+        .get_opt_key("batchBuildConcurrency")
+        .parse_u64()
+        .map(|concurrency| concurrency as usize)
+        .unwrap_or(DEFAULT_BATCH_BUILD_CONCURRENCY);
+
+    let replay_fixture_dir = p
+        .chain(err)
+        .get_opt_key("replayFixtureDir")
+        .parse_string()
+        .end()
+        .map(PathBuf::from)
+        .and_then(|dir| {
+            if dir.is_dir() {
+                Some(dir)
+            } else {
+                Err::<(), eyre::Report>(eyre!(
+                    "CCIP-read replay fixture directory {} does not exist",
+                    dir.display()
+                ))
+                .take_err(err, || &p.cwp + "replay_fixture_dir");
+                None
+            }
+        });
+
+    let replay_live_fallback = p
+        .chain(err)
+        .get_opt_key("replayLiveFallback")
+        .parse_bool()
+        .unwrap_or(false);
+
+    let fallback_metadata_dir = p
+        .chain(err)
+        .get_opt_key("fallbackMetadataDir")
+        .parse_string()
+        .end()
+        .map(PathBuf::from)
+        .and_then(|dir| {
+            if dir.is_dir() {
+                Some(dir)
+            } else {
+                Err::<(), eyre::Report>(eyre!(
+                    "CCIP-read fallback metadata directory {} does not exist",
+                    dir.display()
+                ))
+                .take_err(err, || &p.cwp + "fallback_metadata_dir");
+                None
+            }
+        });
+
+    let stuck_message_webhook = p
+        .chain(err)
+        .get_opt_key("stuckMessageWebhook")
+        .parse_string()
+        .end()
+        .and_then(|webhook| {
+            Url::parse(webhook)
+                .context("Invalid CCIP-read stuck message webhook URL")
+                .take_err(err, || &p.cwp + "stuck_message_webhook")
+        });
+
+    let stuck_message_failures = p
+        .chain(err)
+        .get_opt_key("stuckMessageFailures")
+        .parse_u32()
+        .unwrap_or(DEFAULT_STUCK_MESSAGE_FAILURES);
+    if stuck_message_failures == 0 {
+        Err::<(), eyre::Report>(eyre!(
+            "CCIP-read stuck message failures must be at least one"
+        ))
+        .take_err(err, || &p.cwp + "stuck_message_failures");
+    }
+
+    // WebSocket gateways are connected to directly, resolved by the system
+    // and with the default TLS configuration, and their responses aren't
+    // buffered by the HTTP client
+    if websocket_gateways {
+        let unsupported: Vec<_> = [
+            ("proxy", proxy.is_some()),
+            ("clientCertPath", client_identity.is_some()),
+            ("pinnedCertificates", !pinned_certificates.is_empty()),
+            ("hostOverrides", !host_overrides.is_empty()),
+            ("gatewayMirror", gateway_mirror.is_some()),
+            (
+                "maxBufferedResponseBytes",
+                max_buffered_response_bytes.is_some(),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(key, set)| set.then_some(key))
+        .collect();
+        if !unsupported.is_empty() {
+            Err::<(), eyre::Report>(eyre!(
+                "CCIP-read WebSocket gateways can't be used with {}",
+                unsupported.join(", ")
+            ))
+            .take_err(err, || &p.cwp + "websocket_gateways");
+        }
+    }
+
+    CcipReadConf {
+        gateway_timeout,
+        max_attempts,
+        retry_base_delay,
+        concurrent_gateways,
+        gateway_quorum,
+        gateway_headers,
+        gateway_headers_file,
+        gateway_headers_reload_interval,
+        gateway_methods,
+        gateway_base_url,
+        include_destination_state,
+        websocket_gateways,
+        user_agent,
+        response_format,
+        response_data_pointer,
+        response_decoders,
+        max_response_bytes,
+        max_buffered_response_bytes,
+        max_response_pages,
+        gateway_hosts,
+        max_redirects,
+        negative_cache_ttl,
+        verify_metadata,
+        ism_metadata_lengths,
+        ism_metadata_expiry,
+        ism_metadata_hashes,
+        offchain_lookup_cache_ttl,
+        message_independent_isms,
+        callback_encoded_isms,
+        ism_gateway_urls,
+        ism_gateway_priorities,
+        rotate_gateways,
+        persist_offchain_lookups,
+        metadata_cache_ttl,
+        metadata_cache_min_ttl,
+        metadata_cache_max_ttl,
+        metadata_stale_grace,
+        cache_ttl_jitter,
+        max_cache_entries,
+        max_gateway_urls,
+        ipfs_gateway,
+        max_in_flight_per_host,
+        max_in_flight_total,
+        max_requests_per_second_per_host,
+        proxy,
+        no_proxy,
+        gateway_mirror,
+        client_identity,
+        pinned_certificates,
+        cert_expiry_warning,
+        host_overrides,
+        circuit_breaker_failures,
+        circuit_breaker_window,
+        circuit_breaker_cooldown,
+        batch_build_concurrency,
+        replay_fixture_dir,
+        replay_live_fallback,
+        fallback_metadata_dir,
+        stuck_message_webhook,
+        stuck_message_failures,
+    }
+}
+
+/// Reads a PEM certificate and PKCS#8 key, checking they can be used as a
+/// TLS client identity so a bad config fails at startup
+fn load_client_identity(cert_path: &str, key_path: &str) -> eyre::Result<ClientIdentity> {
+    let cert_pem = fs::read(cert_path)
+        .with_context(|| format!("Failed to read CCIP-read client certificate at {cert_path}"))?;
+    let key_pem = fs::read(key_path)
+        .with_context(|| format!("Failed to read CCIP-read client key at {key_path}"))?;
+    Identity::from_pkcs8_pem(&cert_pem, &key_pem).context(
+        "Invalid CCIP-read client certificate or key, expected PEM with a PKCS#8 private key",
+    )?;
+    Ok(ClientIdentity { cert_pem, key_pem })
+}
+
+/// Parses a list of host patterns, given as an array or stringified JSON array
+fn parse_host_patterns(
+    p: &ValueParser,
+    key: &str,
+    err: &mut ConfigParsingError,
+) -> Vec<HostPattern> {
+    p.chain(err)
+        .get_opt_key(key)
+        .end()
+        .and_then(parse_json_array)
+        .map(|(cwp, value)| {
+            ValueParser::new(cwp, &value)
+                .into_array_iter()
+                .map(|itr| {
+                    itr.filter_map(|entry| {
+                        entry
+                            .chain(err)
+                            .parse_string()
+                            .end()?
+                            .parse::<HostPattern>()
+                            .take_err(err, || entry.cwp.clone())
+                    })
+                    .collect()
+                })
+                .unwrap_or_default()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads gateway headers from a JSON file listing them like `gatewayHeaders`
+pub fn load_gateway_headers(path: &Path) -> eyre::Result<HashMap<String, HeaderMap>> {
+    let contents = fs::read_to_string(path).with_context(|| {
+        format!(
+            "Failed to read CCIP-read gateway headers at {}",
+            path.display()
+        )
+    })?;
+    let value = serde_json::from_str(&contents).context("Invalid JSON")?;
+    // Like config, so the file's keys are cased the same way
+    let value = recase_json_value(value, Case::Flat);
+    let mut err = ConfigParsingError::default();
+    let headers = parse_gateway_headers(ValueParser::new(ConfigPath::default(), &value), &mut err);
+    Ok(err.into_result(headers)?)
+}
+
+/// Parses a list of `{ host, bearerToken?, headers?: [{ name, value }] }`
+/// entries. Header names are given as values rather than object keys since
+/// config keys are recased when loaded.
+fn parse_gateway_headers(
+    p: ValueParser,
+    err: &mut ConfigParsingError,
+) -> HashMap<String, HeaderMap> {
+    p.into_array_iter()
+        .map(|itr| {
+            itr.filter_map(|entry| {
+                let host = normalize_host(entry.chain(err).get_key("host").parse_string().end()?);
+
+                let mut headers = HeaderMap::new();
+                if let Some(token) = entry
+                    .chain(err)
+                    .get_opt_key("bearerToken")
+                    .parse_string()
+                    .end()
+                {
+                    if let Some(value) = sensitive_header_value(&format!("Bearer {token}"))
+                        .take_err(err, || &entry.cwp + "bearer_token")
+                    {
+                        headers.insert(AUTHORIZATION, value);
+                    }
+                }
+
+                for header in entry
+                    .chain(err)
+                    .get_opt_key("headers")
+                    .into_array_iter()
+                    .into_iter()
+                    .flatten()
+                {
+                    let name = header
+                        .chain(err)
+                        .get_key("name")
+                        .parse_string()
+                        .end()
+                        .and_then(|name| {
+                            HeaderName::from_bytes(name.as_bytes())
+                                .context("Invalid header name")
+                                .take_err(err, || &header.cwp + "name")
+                        });
+                    let value = header
+                        .chain(err)
+                        .get_key("value")
+                        .parse_string()
+                        .end()
+                        .and_then(|value| {
+                            sensitive_header_value(value).take_err(err, || &header.cwp + "value")
+                        });
+                    if let (Some(name), Some(value)) = (name, value) {
+                        headers.insert(name, value);
+                    }
+                }
+
+                Some((host, headers))
+            })
+            .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parses a list of `{ host, address }` entries. A host may be given several
+/// times to resolve to several addresses.
+fn parse_host_overrides(
+    p: ValueParser,
+    err: &mut ConfigParsingError,
+) -> HashMap<String, Vec<IpAddr>> {
+    let mut overrides: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    for entry in p.into_array_iter().into_iter().flatten() {
+        let host = entry.chain(err).get_key("host").parse_string().end();
+        let address = entry
+            .chain(err)
+            .get_key("address")
+            .parse_string()
+            .end()
+            .and_then(|address| {
+                address
+                    .parse::<IpAddr>()
+                    .context("Invalid IP address")
+                    .take_err(err, || &entry.cwp + "address")
+            });
+        if let (Some(host), Some(address)) = (host, address) {
+            overrides
+                .entry(host.to_lowercase())
+                .or_default()
+                .push(address);
+        }
+    }
+    overrides
+}
+
+/// Parses a list of `{ host, sha256 }` entries, where `sha256` is the hex
+/// fingerprint of a certificate, optionally colon separated as printed by
+/// `openssl x509 -fingerprint -sha256`. A host may be given several times to
+/// accept several certificates.
+fn parse_pinned_certificates(
+    p: ValueParser,
+    err: &mut ConfigParsingError,
+) -> HashMap<String, Vec<[u8; 32]>> {
+    let mut pins: HashMap<String, Vec<[u8; 32]>> = HashMap::new();
+    for entry in p.into_array_iter().into_iter().flatten() {
+        let host = entry.chain(err).get_key("host").parse_string().end();
+        let fingerprint = entry
+            .chain(err)
+            .get_key("sha256")
+            .parse_string()
+            .end()
+            .and_then(|fingerprint| {
+                parse_fingerprint(fingerprint).take_err(err, || &entry.cwp + "sha256")
+            });
+        if let (Some(host), Some(fingerprint)) = (host, fingerprint) {
+            pins.entry(normalize_host(host))
+                .or_default()
+                .push(fingerprint);
+        }
+    }
+    pins
+}
+
+fn parse_fingerprint(fingerprint: &str) -> eyre::Result<[u8; 32]> {
+    let digits: String = fingerprint.chars().filter(|c| *c != ':').collect();
+    let bytes = hex::decode(digits.strip_prefix("0x").unwrap_or(&digits))
+        .context("Invalid certificate fingerprint, expected hex")?;
+    bytes
+        .try_into()
+        .map_err(|_| eyre!("Invalid certificate fingerprint, expected 32 bytes of SHA-256"))
+}
+
+/// Parses a list of `{ host, method }` entries, where `method` is `get` or
+/// `post`
+fn parse_gateway_methods(
+    p: ValueParser,
+    err: &mut ConfigParsingError,
+) -> HashMap<String, GatewayMethod> {
+    let mut methods = HashMap::new();
+    for entry in p.into_array_iter().into_iter().flatten() {
+        let host = entry.chain(err).get_key("host").parse_string().end();
+        let method = match entry.chain(err).get_key("method").parse_string().end() {
+            Some("get") => Some(GatewayMethod::Get),
+            Some("post") => Some(GatewayMethod::Post),
+            Some(_) => {
+                Err::<(), eyre::Report>(eyre!(
+                    "Unknown CCIP-read gateway method, expected `get` or `post`"
+                ))
+                .take_err(err, || &entry.cwp + "method");
+                None
+            }
+            None => None,
+        };
+        if let (Some(host), Some(method)) = (host, method) {
+            methods.insert(normalize_host(host), method);
+        }
+    }
+    methods
+}
+
+/// Parses a list of `{ ism, urls, mode? }` entries, where `mode` is either
+/// `replace`, the default, or `append`
+fn parse_ism_gateway_urls(
+    p: ValueParser,
+    err: &mut ConfigParsingError,
+) -> HashMap<H256, GatewayUrlOverride> {
+    let mut overrides = HashMap::new();
+    for entry in p.into_array_iter().into_iter().flatten() {
+        let ism = entry.chain(err).get_key("ism").parse_address_hash().end();
+        let urls: Vec<String> = entry
+            .chain(err)
+            .get_key("urls")
+            .into_array_iter()
+            .into_iter()
+            .flatten()
+            .filter_map(|url| url.chain(err).parse_string().end().map(str::to_owned))
+            .collect();
+        let append = match entry.chain(err).get_opt_key("mode").parse_string().end() {
+            None | Some("replace") => Some(false),
+            Some("append") => Some(true),
+            Some(_) => {
+                Err::<(), eyre::Report>(eyre!(
+                    "Unknown CCIP-read gateway URL override mode, expected `replace` or `append`"
+                ))
+                .take_err(err, || &entry.cwp + "mode");
+                None
+            }
+        };
+        if urls.is_empty() {
+            Err::<(), eyre::Report>(eyre!("CCIP-read gateway URL override without any URL"))
+                .take_err(err, || &entry.cwp + "urls");
+            continue;
+        }
+        if let (Some(ism), Some(append)) = (ism, append) {
+            overrides.insert(ism, GatewayUrlOverride { urls, append });
+        }
+    }
+    overrides
+}
+
+/// Parses a list of `{ ism, encoding }` or `{ host, encoding }` entries,
+/// where `encoding` is `hex`, `base64` or `abiBytes`
+fn parse_response_decoders(
+    p: ValueParser,
+    err: &mut ConfigParsingError,
+) -> HashMap<ResponseDecoderScope, ResponseDataEncoding> {
+    let mut decoders = HashMap::new();
+    for entry in p.into_array_iter().into_iter().flatten() {
+        let ism = entry
+            .chain(err)
+            .get_opt_key("ism")
+            .parse_address_hash()
+            .end();
+        let host = entry.chain(err).get_opt_key("host").parse_string().end();
+        let scope = match (ism, host) {
+            (Some(ism), None) => Some(ResponseDecoderScope::Ism(ism)),
+            (None, Some(host)) => Some(ResponseDecoderScope::Host(normalize_host(host))),
+            _ => {
+                Err::<(), eyre::Report>(eyre!(
+                    "CCIP-read response decoder must be given for either an `ism` or a `host`"
+                ))
+                .take_err(err, || &entry.cwp + "ism");
+                None
+            }
+        };
+        let encoding = match entry.chain(err).get_key("encoding").parse_string().end() {
+            Some("hex") => Some(ResponseDataEncoding::Hex),
+            Some("base64") => Some(ResponseDataEncoding::Base64),
+            Some("abiBytes") => Some(ResponseDataEncoding::AbiBytes),
+            Some("raw") => Some(ResponseDataEncoding::Raw),
+            Some(_) => {
+                Err::<(), eyre::Report>(eyre!(
+                    "Unknown CCIP-read response encoding, expected `hex`, `base64`, `abiBytes` or `raw`"
+                ))
+                .take_err(err, || &entry.cwp + "encoding");
+                None
+            }
+            None => None,
+        };
+        if let (Some(scope), Some(encoding)) = (scope, encoding) {
+            decoders.insert(scope, encoding);
+        }
+    }
+    decoders
+}
+
+/// Parses a list of `{ ism, hosts }` entries, with the hosts in the order
+/// they are tried
+fn parse_ism_gateway_priorities(
+    p: ValueParser,
+    err: &mut ConfigParsingError,
+) -> HashMap<H256, Vec<String>> {
+    let mut priorities = HashMap::new();
+    for entry in p.into_array_iter().into_iter().flatten() {
+        let ism = entry.chain(err).get_key("ism").parse_address_hash().end();
+        let hosts: Vec<String> = entry
+            .chain(err)
+            .get_key("hosts")
+            .into_array_iter()
+            .into_iter()
+            .flatten()
+            .filter_map(|host| host.chain(err).parse_string().end().map(normalize_host))
+            .collect();
+        if let Some(ism) = ism {
+            priorities.insert(ism, hosts);
+        }
+    }
+    priorities
+}
+
+/// Parses a list of `{ ism, length }` or `{ ism, minLength, maxLength }`
+/// entries, where either bound may be left out
+fn parse_ism_metadata_lengths(
+    p: ValueParser,
+    err: &mut ConfigParsingError,
+) -> HashMap<H256, MetadataLength> {
+    let mut lengths = HashMap::new();
+    for entry in p.into_array_iter().into_iter().flatten() {
+        let ism = entry.chain(err).get_key("ism").parse_address_hash().end();
+        let exact = entry.chain(err).get_opt_key("length").parse_u64().end();
+        let min = entry.chain(err).get_opt_key("minLength").parse_u64().end();
+        let max = entry.chain(err).get_opt_key("maxLength").parse_u64().end();
+        let length = match exact {
+            Some(length) => MetadataLength {
+                min: length as usize,
+                max: length as usize,
+            },
+            None => MetadataLength {
+                min: min.map_or(0, |min| min as usize),
+                max: max.map_or(usize::MAX, |max| max as usize),
+            },
+        };
+        if length.min > length.max {
+            Err::<(), eyre::Report>(eyre!(
+                "CCIP-read metadata min length must not exceed the max length"
+            ))
+            .take_err(err, || &entry.cwp + "min_length");
+            continue;
+        }
+        if let Some(ism) = ism {
+            lengths.insert(ism, length);
+        }
+    }
+    lengths
+}
+
+/// Parses a list of `{ ism, offset, length }` entries, where `length`
+/// defaults to the 32 bytes of a `uint256`
+fn parse_ism_metadata_expiry(
+    p: ValueParser,
+    err: &mut ConfigParsingError,
+) -> HashMap<H256, MetadataExpiry> {
+    let mut expiries = HashMap::new();
+    for entry in p.into_array_iter().into_iter().flatten() {
+        let ism = entry.chain(err).get_key("ism").parse_address_hash().end();
+        let offset = entry.chain(err).get_key("offset").parse_u64().end();
+        let length = entry
+            .chain(err)
+            .get_opt_key("length")
+            .parse_u64()
+            .unwrap_or(32);
+        if !(1..=32).contains(&length) {
+            Err::<(), eyre::Report>(eyre!(
+                "CCIP-read metadata expiry length must be between 1 and 32 bytes"
+            ))
+            .take_err(err, || &entry.cwp + "length");
+            continue;
+        }
+        if let (Some(ism), Some(offset)) = (ism, offset) {
+            let expiry = MetadataExpiry {
+                offset: offset as usize,
+                length: length as usize,
+            };
+            expiries.insert(ism, expiry);
+        }
+    }
+    expiries
+}
+
+/// Parses a list of `{ ism, hash }` entries
+fn parse_ism_metadata_hashes(p: ValueParser, err: &mut ConfigParsingError) -> HashMap<H256, H256> {
+    let mut hashes = HashMap::new();
+    for entry in p.into_array_iter().into_iter().flatten() {
+        let ism = entry.chain(err).get_key("ism").parse_address_hash().end();
+        let hash = entry
+            .chain(err)
+            .get_key("hash")
+            .parse_value::<H256>("Expected a 32 byte hex hash")
+            .end();
+        if let (Some(ism), Some(hash)) = (ism, hash) {
+            hashes.insert(ism, hash);
+        }
+    }
+    hashes
+}
+
+/// Header values may hold credentials, so they are never printed
+fn sensitive_header_value(value: &str) -> eyre::Result<HeaderValue> {
+    let mut value = HeaderValue::from_str(value).context("Invalid header value")?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use crate::test_utils::client_identity::{CLIENT_CERT_PEM, CLIENT_KEY_PEM};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_host_overrides() {
+        let value = json!([
+            { "host": "Gateway.Example.com", "address": "10.0.0.1" },
+            { "host": "gateway.example.com", "address": "::1" },
+            { "host": "other.example.com", "address": "not an ip" }
+        ]);
+        let mut err = ConfigParsingError::default();
+        let parsed =
+            parse_host_overrides(ValueParser::new(ConfigPath::default(), &value), &mut err);
+        assert!(!err.is_ok());
+        assert_eq!(
+            parsed["gateway.example.com"],
+            vec![
+                "10.0.0.1".parse::<IpAddr>().unwrap(),
+                "::1".parse().unwrap()
+            ]
+        );
+        assert!(!parsed.contains_key("other.example.com"));
+    }
+
+    #[test]
+    fn test_websocket_gateways_reject_unsupported_options() {
+        let parse = |value: Value| {
+            let mut err = ConfigParsingError::default();
+            parse_ccip_read_conf(&ValueParser::new(ConfigPath::default(), &value), &mut err);
+            err.is_ok()
+        };
+        assert!(parse(json!({ "websocketGateways": true })));
+        assert!(parse(json!({ "proxy": "http://proxy.example.com" })));
+        assert!(!parse(json!({
+            "websocketGateways": true,
+            "proxy": "http://proxy.example.com"
+        })));
+        assert!(parse(json!({
+            "hostOverrides": [{ "host": "gateway.example.com", "address": "10.0.0.1" }]
+        })));
+        assert!(!parse(json!({
+            "websocketGateways": true,
+            "hostOverrides": [{ "host": "gateway.example.com", "address": "10.0.0.1" }]
+        })));
+        assert!(!parse(json!({
+            "websocketGateways": true,
+            "gatewayMirror": "https://mirror.example.com"
+        })));
+    }
+
+    #[test]
+    fn test_parse_pinned_certificates() {
+        let value = json!([
+            { "host": "Gateway.Example.com", "sha256": "11".repeat(32) },
+            {
+                "host": "gateway.example.com",
+                "sha256": vec!["AB"; 32].join(":")
+            },
+            { "host": "other.example.com", "sha256": "1234" }
+        ]);
+        let mut err = ConfigParsingError::default();
+        let parsed =
+            parse_pinned_certificates(ValueParser::new(ConfigPath::default(), &value), &mut err);
+        assert!(!err.is_ok());
+        assert_eq!(parsed["gateway.example.com"], vec![[0x11; 32], [0xab; 32]]);
+        assert!(!parsed.contains_key("other.example.com"));
+    }
+
+    #[test]
+    fn test_parse_gateway_methods() {
+        let value = json!([
+            { "host": "Get.Example.com", "method": "get" },
+            { "host": "post.example.com", "method": "post" },
+            { "host": "other.example.com", "method": "put" }
+        ]);
+        let mut err = ConfigParsingError::default();
+        let parsed =
+            parse_gateway_methods(ValueParser::new(ConfigPath::default(), &value), &mut err);
+        assert!(!err.is_ok());
+        assert_eq!(
+            parsed,
+            HashMap::from([
+                ("get.example.com".to_owned(), GatewayMethod::Get),
+                ("post.example.com".to_owned(), GatewayMethod::Post),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_load_client_identity() {
+        let dir = std::env::temp_dir().join(format!("ccip-read-identity-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        fs::write(&cert_path, CLIENT_CERT_PEM).unwrap();
+        fs::write(&key_path, CLIENT_KEY_PEM).unwrap();
+        let (cert_path, key_path) = (cert_path.to_str().unwrap(), key_path.to_str().unwrap());
+
+        let identity = load_client_identity(cert_path, key_path).unwrap();
+        assert_eq!(identity.cert_pem, CLIENT_CERT_PEM.as_bytes());
+        assert!(!format!("{identity:?}").contains("PRIVATE KEY"));
+
+        // The certificate is not a key
+        assert!(load_client_identity(cert_path, cert_path).is_err());
+        let missing = dir.join("missing.pem");
+        assert!(load_client_identity(cert_path, missing.to_str().unwrap()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_gateway_headers() {
+        let value = json!([{
+            "host": "Gateway.Example.com",
+            "bearertoken": "secret-token",
+            "headers": [{ "name": "X-Api-Key", "value": "secret-key" }]
+        }]);
+        let mut err = ConfigParsingError::default();
+        let parsed =
+            parse_gateway_headers(ValueParser::new(ConfigPath::default(), &value), &mut err);
+        assert!(err.is_ok());
+
+        let headers = &parsed["gateway.example.com"];
+        assert_eq!(headers[AUTHORIZATION], "Bearer secret-token");
+        assert_eq!(headers["x-api-key"], "secret-key");
+
+        let debug = format!("{parsed:?}");
+        assert!(!debug.contains("secret-token"));
+        assert!(!debug.contains("secret-key"));
+    }
+
+    #[test]
+    fn test_parse_ism_metadata_lengths() {
+        let value = json!([
+            { "ism": "0x0000000000000000000000000000000000000000000000000000000000000001", "length": 65 },
+            { "ism": "0x0000000000000000000000000000000000000000000000000000000000000002", "minLength": 32 },
+            { "ism": "0x0000000000000000000000000000000000000000000000000000000000000003", "minLength": 2, "maxLength": 1 }
+        ]);
+        let mut err = ConfigParsingError::default();
+        let parsed =
+            parse_ism_metadata_lengths(ValueParser::new(ConfigPath::default(), &value), &mut err);
+        assert!(!err.is_ok());
+        assert_eq!(
+            parsed,
+            HashMap::from([
+                (
+                    H256::from_low_u64_be(1),
+                    MetadataLength { min: 65, max: 65 }
+                ),
+                (
+                    H256::from_low_u64_be(2),
+                    MetadataLength {
+                        min: 32,
+                        max: usize::MAX
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_response_decoders() {
+        let value = json!([
+            { "ism": "0x0000000000000000000000000000000000000000000000000000000000000001", "encoding": "base64" },
+            { "host": "Gateway.Example.com", "encoding": "abiBytes" },
+            { "host": "other.example.com", "encoding": "rot13" },
+            { "host": "raw.example.com", "encoding": "raw" },
+            { "encoding": "hex" }
+        ]);
+        let mut err = ConfigParsingError::default();
+        let parsed =
+            parse_response_decoders(ValueParser::new(ConfigPath::default(), &value), &mut err);
+        assert!(!err.is_ok());
+        assert_eq!(
+            parsed,
+            HashMap::from([
+                (
+                    ResponseDecoderScope::Ism(H256::from_low_u64_be(1)),
+                    ResponseDataEncoding::Base64
+                ),
+                (
+                    ResponseDecoderScope::Host("gateway.example.com".to_owned()),
+                    ResponseDataEncoding::AbiBytes
+                ),
+                (
+                    ResponseDecoderScope::Host("raw.example.com".to_owned()),
+                    ResponseDataEncoding::Raw
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_ism_gateway_priorities() {
+        let value = json!([
+            { "ism": "0x0000000000000000000000000000000000000000000000000000000000000001", "hosts": ["Fast.Example.com", "[::1]"] },
+            { "ism": "not an address", "hosts": ["a.example.com"] }
+        ]);
+        let mut err = ConfigParsingError::default();
+        let parsed =
+            parse_ism_gateway_priorities(ValueParser::new(ConfigPath::default(), &value), &mut err);
+        assert!(!err.is_ok());
+        assert_eq!(
+            parsed,
+            HashMap::from([(
+                H256::from_low_u64_be(1),
+                vec!["fast.example.com".to_owned(), "::1".to_owned()]
+            )])
+        );
+    }
+
+    #[test]
+    fn test_parse_ism_gateway_urls() {
+        let value = json!([
+            { "ism": "0x0000000000000000000000000000000000000000000000000000000000000001", "urls": ["https://a.example.com/{data}"] },
+            { "ism": "0x0000000000000000000000000000000000000000000000000000000000000002", "urls": ["https://b.example.com/"], "mode": "append" },
+            { "ism": "0x0000000000000000000000000000000000000000000000000000000000000003", "urls": [] }
+        ]);
+        let mut err = ConfigParsingError::default();
+        let parsed =
+            parse_ism_gateway_urls(ValueParser::new(ConfigPath::default(), &value), &mut err);
+        assert!(!err.is_ok());
+        assert_eq!(
+            parsed,
+            HashMap::from([
+                (
+                    H256::from_low_u64_be(1),
+                    GatewayUrlOverride {
+                        urls: vec!["https://a.example.com/{data}".to_owned()],
+                        append: false,
+                    }
+                ),
+                (
+                    H256::from_low_u64_be(2),
+                    GatewayUrlOverride {
+                        urls: vec!["https://b.example.com/".to_owned()],
+                        append: true,
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_reads_metadata_expiry() {
+        let expiry = MetadataExpiry {
+            offset: 1,
+            length: 4,
+        };
+        assert_eq!(expiry.expires_at(&[0xff, 0, 0, 1, 0, 0xff]), Some(256));
+        assert_eq!(expiry.expires_at(&[0xff, 0, 0, 1]), None);
+
+        let word = MetadataExpiry {
+            offset: 0,
+            length: 32,
+        };
+        assert_eq!(word.expires_at(&[0xff; 32]), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_parse_ism_metadata_hashes() {
+        let value = json!([
+            {
+                "ism": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "hash": "0x00000000000000000000000000000000000000000000000000000000000000aa"
+            },
+            {
+                "ism": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                "hash": "0xaa"
+            }
+        ]);
+        let mut err = ConfigParsingError::default();
+        let parsed =
+            parse_ism_metadata_hashes(ValueParser::new(ConfigPath::default(), &value), &mut err);
+        assert!(!err.is_ok());
+        assert_eq!(
+            parsed,
+            HashMap::from([(H256::from_low_u64_be(1), H256::from_low_u64_be(0xaa))])
+        );
+    }
+}
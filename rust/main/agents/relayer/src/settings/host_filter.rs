@@ -0,0 +1,263 @@
+//! Restricts which hosts the relayer is willing to send requests to when the
+//! URLs come from untrusted onchain configuration rather than the operator,
+//! e.g. CCIP-read gateway URLs.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use eyre::{eyre, Context};
+use url::{Host, Url};
+
+/// A single allowlist or denylist entry.
+///
+/// Valid options are
+/// - an exact hostname, e.g. `gateway.example.com` or `localhost`
+/// - a wildcard subdomain, e.g. `*.example.com`
+/// - an IP address, e.g. `169.254.169.254`
+/// - a CIDR range, e.g. `10.0.0.0/8` or `fc00::/7`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostPattern {
+    Domain(String),
+    Subdomains(String),
+    Cidr { network: IpAddr, prefix_len: u8 },
+}
+
+impl FromStr for HostPattern {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        if let Some((network, prefix_len)) = s.split_once('/') {
+            let network: IpAddr = unbracket(network)
+                .parse()
+                .context("Invalid CIDR network address")?;
+            let prefix_len: u8 = prefix_len.parse().context("Invalid CIDR prefix length")?;
+            if prefix_len > max_prefix_len(&network) {
+                return Err(eyre!("CIDR prefix length {prefix_len} is too long"));
+            }
+            Ok(Self::Cidr {
+                network,
+                prefix_len,
+            })
+        } else if let Ok(network) = unbracket(&s).parse::<IpAddr>() {
+            Ok(Self::Cidr {
+                prefix_len: max_prefix_len(&network),
+                network,
+            })
+        } else if let Some(domain) = s.strip_prefix("*.") {
+            Ok(Self::Subdomains(domain.to_owned()))
+        } else if s.is_empty() {
+            Err(eyre!("Host pattern cannot be empty"))
+        } else {
+            Ok(Self::Domain(s))
+        }
+    }
+}
+
+impl HostPattern {
+    fn matches(&self, host: &Host<&str>) -> bool {
+        match (self, host) {
+            (Self::Domain(domain), Host::Domain(host)) => domain.eq_ignore_ascii_case(host),
+            (Self::Subdomains(domain), Host::Domain(host)) => {
+                let host = host.to_lowercase();
+                host.strip_suffix(domain.as_str())
+                    .map_or(false, |sub| sub.ends_with('.'))
+            }
+            (
+                Self::Cidr {
+                    network,
+                    prefix_len,
+                },
+                Host::Ipv4(ip),
+            ) => cidr_contains(network, *prefix_len, &IpAddr::V4(*ip)),
+            (
+                Self::Cidr {
+                    network,
+                    prefix_len,
+                },
+                Host::Ipv6(ip),
+            ) => {
+                // IPv4-mapped addresses like `::ffff:127.0.0.1` are matched as IPv4
+                let ip = ip.to_ipv4_mapped().map_or(IpAddr::V6(*ip), IpAddr::V4);
+                cidr_contains(network, *prefix_len, &ip)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Canonical form of a host given in config or taken from a URL, so the two
+/// can be compared: lowercase, with IPv6 literals unbracketed and compressed,
+/// e.g. `[2001:DB8:0::1]` becomes `2001:db8::1`
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    match unbracket(host).parse::<IpAddr>() {
+        Ok(ip) => ip.to_string(),
+        Err(_) => host.to_lowercase(),
+    }
+}
+
+/// Strips the brackets around an IPv6 literal as written in URLs
+fn unbracket(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+fn max_prefix_len(ip: &IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn cidr_contains(network: &IpAddr, prefix_len: u8, ip: &IpAddr) -> bool {
+    let (network, ip, bits) = match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            (u32::from(*network) as u128, u32::from(*ip) as u128, 32)
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(*network), u128::from(*ip), 128),
+        _ => return false,
+    };
+    let shift = bits - prefix_len as u32;
+    shift >= 128 || network >> shift == ip >> shift
+}
+
+/// Hosts that requests may or may not be sent to. A URL is permitted if its
+/// host matches no denylist entry and, when an allowlist is set, matches an
+/// allowlist entry. Hostnames are not resolved, so a domain pointing at a
+/// private address is only caught by an allowlist.
+#[derive(Debug, Clone, Default)]
+pub struct HostFilter {
+    pub allowed: Vec<HostPattern>,
+    pub denied: Vec<HostPattern>,
+}
+
+impl HostFilter {
+    /// Whether any restrictions are configured at all
+    pub fn is_empty(&self) -> bool {
+        self.allowed.is_empty() && self.denied.is_empty()
+    }
+
+    /// Whether a request may be sent to `url`. URLs without a host are only
+    /// permitted if no restrictions are configured.
+    pub fn permits(&self, url: &str) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let Some(url) = Url::parse(url).ok() else {
+            return false;
+        };
+        let Some(host) = url.host() else {
+            return false;
+        };
+        if self.denied.iter().any(|pattern| pattern.matches(&host)) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|pattern| pattern.matches(&host))
+    }
+}
+
+/// Denies loopback, link-local (incl. cloud metadata endpoints) and private
+/// ranges, a reasonable starting point for a denylist.
+pub fn private_network_patterns() -> Vec<HostPattern> {
+    let cidr = |network: IpAddr, prefix_len| HostPattern::Cidr {
+        network,
+        prefix_len,
+    };
+    vec![
+        HostPattern::Domain("localhost".to_owned()),
+        cidr(Ipv4Addr::new(0, 0, 0, 0).into(), 8),
+        cidr(Ipv4Addr::new(10, 0, 0, 0).into(), 8),
+        cidr(Ipv4Addr::new(127, 0, 0, 0).into(), 8),
+        cidr(Ipv4Addr::new(169, 254, 0, 0).into(), 16),
+        cidr(Ipv4Addr::new(172, 16, 0, 0).into(), 12),
+        cidr(Ipv4Addr::new(192, 168, 0, 0).into(), 16),
+        cidr(Ipv6Addr::LOCALHOST.into(), 128),
+        cidr(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0).into(), 7),
+        cidr(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0).into(), 10),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn filter(allowed: &[&str], denied: &[&str]) -> HostFilter {
+        HostFilter {
+            allowed: allowed.iter().map(|p| p.parse().unwrap()).collect(),
+            denied: denied.iter().map(|p| p.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parses_patterns() {
+        assert_eq!(
+            "Gateway.Example.com".parse::<HostPattern>().unwrap(),
+            HostPattern::Domain("gateway.example.com".to_owned())
+        );
+        assert_eq!(
+            "*.example.com".parse::<HostPattern>().unwrap(),
+            HostPattern::Subdomains("example.com".to_owned())
+        );
+        assert_eq!(
+            "10.0.0.0/8".parse::<HostPattern>().unwrap(),
+            HostPattern::Cidr {
+                network: Ipv4Addr::new(10, 0, 0, 0).into(),
+                prefix_len: 8
+            }
+        );
+        assert!("10.0.0.0/33".parse::<HostPattern>().is_err());
+        assert!("nonsense/8".parse::<HostPattern>().is_err());
+        assert!("".parse::<HostPattern>().is_err());
+    }
+
+    #[test]
+    fn test_denies_private_ips() {
+        let filter = HostFilter {
+            allowed: vec![],
+            denied: private_network_patterns(),
+        };
+        assert!(!filter.permits("http://169.254.169.254/latest/meta-data"));
+        assert!(!filter.permits("http://10.1.2.3:8080/{data}"));
+        assert!(!filter.permits("http://localhost/{data}"));
+        assert!(!filter.permits("http://[::1]/{data}"));
+        assert!(!filter.permits("http://[::ffff:127.0.0.1]/{data}"));
+        assert!(filter.permits("https://gateway.example.com/{data}"));
+        assert!(filter.permits("https://8.8.8.8/{data}"));
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let filter = filter(&["*.example.com", "203.0.113.0/24"], &["bad.example.com"]);
+        assert!(filter.permits("https://gateway.example.com/{data}"));
+        assert!(filter.permits("https://203.0.113.7/{data}"));
+        assert!(!filter.permits("https://example.com/{data}"));
+        assert!(!filter.permits("https://notexample.com/{data}"));
+        assert!(!filter.permits("https://bad.example.com/{data}"));
+        assert!(!filter.permits("not a url"));
+    }
+
+    #[test]
+    fn test_ipv6_hosts() {
+        assert_eq!(
+            "[2001:db8::1]".parse::<HostPattern>().unwrap(),
+            "2001:db8::1/128".parse::<HostPattern>().unwrap()
+        );
+        let filter = filter(&["[2001:db8::]/32"], &["2001:db8::dead"]);
+        assert!(filter.permits("http://[2001:db8::1]:8545/{data}"));
+        assert!(filter.permits("http://[2001:DB8:0:0::2]/{data}"));
+        assert!(!filter.permits("http://[2001:db8::dead]:8545/{data}"));
+        assert!(!filter.permits("http://[2001:db9::1]:8545/{data}"));
+
+        assert_eq!(normalize_host("[2001:DB8:0::1]"), "2001:db8::1");
+        assert_eq!(normalize_host("Gateway.Example.com"), "gateway.example.com");
+    }
+
+    #[test]
+    fn test_no_restrictions_permits_everything() {
+        assert!(HostFilter::default().permits("http://127.0.0.1/{data}"));
+    }
+}
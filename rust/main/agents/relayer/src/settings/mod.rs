@@ -4,7 +4,7 @@
 //! and validations it defines are not applied here, we should mirror them.
 //! ANY CHANGES HERE NEED TO BE REFLECTED IN THE TYPESCRIPT SDK.
 
-use std::{collections::HashSet, path::PathBuf};
+use std::{collections::HashSet, path::PathBuf, time::Duration};
 
 use convert_case::Case;
 use derive_more::{AsMut, AsRef, Deref, DerefMut};
@@ -23,9 +23,17 @@ use serde::Deserialize;
 use serde_json::Value;
 
 use crate::{
-    msg::pending_message::DEFAULT_MAX_MESSAGE_RETRIES, settings::matching_list::MatchingList,
+    msg::pending_message::{
+        DEFAULT_MAX_MESSAGE_RETRIES, DEFAULT_METADATA_BUILD_TIMEOUT, ISM_MAX_DEPTH,
+    },
+    settings::{
+        ccip_read::{parse_ccip_read_conf, CcipReadConf},
+        matching_list::MatchingList,
+    },
 };
 
+pub mod ccip_read;
+pub mod host_filter;
 pub mod matching_list;
 
 /// Settings for `Relayer`
@@ -65,6 +73,13 @@ pub struct RelayerSettings {
     pub metric_app_contexts: Vec<(MatchingList, String)>,
     /// Maximum number of retries per operation
     pub max_retries: u32,
+    /// Maximum depth of nested ISMs metadata is built for, which keeps
+    /// cyclic ISM configurations from recursing without bound
+    pub max_ism_depth: u32,
+    /// Deadline for building a message's metadata, including all nested ISMs
+    pub metadata_build_timeout: Duration,
+    /// How CCIP-read offchain gateways are queried
+    pub ccip_read: CcipReadConf,
 }
 
 /// Config for gas payment enforcement
@@ -324,6 +339,21 @@ impl FromRawConf<RawRelayerSettings> for RelayerSettings {
             .parse_u32()
             .unwrap_or(DEFAULT_MAX_MESSAGE_RETRIES);
 
+        let max_ism_depth = p
+            .chain(&mut err)
+            .get_opt_key("maxIsmDepth")
+            .parse_u32()
+            .unwrap_or(ISM_MAX_DEPTH);
+
+        let metadata_build_timeout = p
+            .chain(&mut err)
+            .get_opt_key("metadataBuildTimeout")
+            .parse_u64()
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_METADATA_BUILD_TIMEOUT);
+
+        let ccip_read = parse_ccip_read_conf(&p, &mut err);
+
         err.into_result(RelayerSettings {
             base,
             db,
@@ -338,6 +368,9 @@ impl FromRawConf<RawRelayerSettings> for RelayerSettings {
             allow_local_checkpoint_syncers,
             metric_app_contexts,
             max_retries: max_message_retries,
+            max_ism_depth,
+            metadata_build_timeout,
+            ccip_read,
         })
     }
 }
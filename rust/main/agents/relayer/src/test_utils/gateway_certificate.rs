@@ -0,0 +1,19 @@
+//! A self-signed gateway certificate valid for a single day
+
+pub const SHORT_LIVED_CERT_PEM: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBgjCCASmgAwIBAgIUQzfEHx/aZXuC0uPrIdR6/ydqOk4wCgYIKoZIzj0EAwIw
+FzEVMBMGA1UEAwwMZ2F0ZXdheS50ZXN0MB4XDTI2MTAxNDA0NDM1M1oXDTI2MTAx
+NTA0NDM1M1owFzEVMBMGA1UEAwwMZ2F0ZXdheS50ZXN0MFkwEwYHKoZIzj0CAQYI
+KoZIzj0DAQcDQgAEVist0haKMOSasFEc+kRaI12IXZZDo9nu5YMk0aoaXPuiAyDl
+JL5wnXODcGc5/Rk0z+C8EOLnykUOwivS5wG8BKNTMFEwHQYDVR0OBBYEFAg1m5kt
+9D8QA3KZwm1n1kFf4yuUMB8GA1UdIwQYMBaAFAg1m5kt9D8QA3KZwm1n1kFf4yuU
+MA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDRwAwRAIgQ0ahOawL2pjoT2wS
+SAOlx91N8Hx2BxlX7spR/RHFfPwCIGSpyoA63afHuP6y53ZPd1KdfUJryxBIk3yZ
+I7NYUyut
+-----END CERTIFICATE-----
+";
+
+/// `notAfter` of [`SHORT_LIVED_CERT_PEM`], 2026-10-15T04:43:53Z, as a unix
+/// timestamp
+pub const SHORT_LIVED_CERT_NOT_AFTER: i64 = 1_792_039_433;
@@ -9,7 +9,7 @@ use hyperlane_core::{
     HyperlaneMessage, InterchainSecurityModule, MultisigIsm, RoutingIsm, H256,
 };
 
-use crate::msg::metadata::{BuildsBaseMetadata, IsmAwareAppContextClassifier};
+use crate::msg::metadata::{BuildsBaseMetadata, CcipReadContext, IsmAwareAppContextClassifier};
 
 type ResponseList<T> = Arc<Mutex<VecDeque<T>>>;
 
@@ -18,6 +18,7 @@ pub struct MockBaseMetadataBuilderResponses {
     pub origin_domain: Option<HyperlaneDomain>,
     pub destination_domain: Option<HyperlaneDomain>,
     pub app_context_classifier: Option<IsmAwareAppContextClassifier>,
+    pub ccip_read_context: Option<CcipReadContext>,
     pub get_proof: ResponseList<eyre::Result<Proof>>,
     pub highest_known_leaf_index: ResponseList<Option<u32>>,
     pub get_merkle_leaf_id_by_message_id: ResponseList<eyre::Result<Option<u32>>>,
@@ -35,6 +36,8 @@ pub struct MockBaseMetadataBuilderResponses {
     pub build_multisig_ism: ResponseList<eyre::Result<Box<dyn MultisigIsm>>>,
     pub build_aggregation_ism: ResponseList<eyre::Result<Box<dyn AggregationIsm>>>,
     pub build_ccip_read_ism: ResponseList<eyre::Result<Box<dyn CcipReadIsm>>>,
+    pub destination_checkpoint_root: ResponseList<eyre::Result<H256>>,
+    pub destination_block_number: ResponseList<eyre::Result<u64>>,
     pub build_checkpoint_syncer:
         ResponseList<Result<MultisigCheckpointSyncer, CheckpointSyncerBuildError>>,
 }
@@ -87,6 +90,12 @@ impl BuildsBaseMetadata for MockBaseMetadataBuilder {
             .as_ref()
             .expect("No mock app_context_classifier response set")
     }
+    fn ccip_read_context(&self) -> &CcipReadContext {
+        self.responses
+            .ccip_read_context
+            .as_ref()
+            .expect("No mock ccip_read_context response set")
+    }
 
     async fn get_proof(&self, _leaf_index: u32, _checkpoint: Checkpoint) -> eyre::Result<Proof> {
         self.responses
@@ -157,6 +166,22 @@ impl BuildsBaseMetadata for MockBaseMetadataBuilder {
             .pop_front()
             .expect("No mock build_ccip_read_ism response set")
     }
+    async fn destination_checkpoint_root(&self) -> eyre::Result<H256> {
+        self.responses
+            .destination_checkpoint_root
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("No mock destination_checkpoint_root response set")
+    }
+    async fn destination_block_number(&self) -> eyre::Result<u64> {
+        self.responses
+            .destination_block_number
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("No mock destination_block_number response set")
+    }
     async fn build_checkpoint_syncer(
         &self,
         _message: &HyperlaneMessage,
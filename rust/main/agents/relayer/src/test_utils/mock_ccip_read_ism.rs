@@ -0,0 +1,51 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use hyperlane_core::{
+    CcipReadIsm, ChainResult, HyperlaneChain, HyperlaneContract, HyperlaneDomain, H256,
+};
+
+type ResponseList<T> = Arc<Mutex<VecDeque<T>>>;
+
+#[derive(Debug, Default)]
+pub struct MockCcipReadIsmResponses {
+    pub get_offchain_verify_info: ResponseList<ChainResult<()>>,
+    pub domain: Option<HyperlaneDomain>,
+}
+
+#[derive(Debug, Default)]
+pub struct MockCcipReadIsm {
+    pub responses: MockCcipReadIsmResponses,
+}
+
+#[async_trait::async_trait]
+impl CcipReadIsm for MockCcipReadIsm {
+    async fn get_offchain_verify_info(&self, _message: Vec<u8>) -> ChainResult<()> {
+        self.responses
+            .get_offchain_verify_info
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("No mock get_offchain_verify_info response set")
+    }
+}
+
+impl HyperlaneContract for MockCcipReadIsm {
+    fn address(&self) -> H256 {
+        H256::zero()
+    }
+}
+
+impl HyperlaneChain for MockCcipReadIsm {
+    fn domain(&self) -> &hyperlane_core::HyperlaneDomain {
+        self.responses
+            .domain
+            .as_ref()
+            .expect("No mock domain response set")
+    }
+    fn provider(&self) -> Box<dyn hyperlane_core::HyperlaneProvider> {
+        unimplemented!()
+    }
+}
@@ -0,0 +1,41 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::msg::metadata::{Clock, SystemClock};
+
+/// Clock that only moves when told to
+#[derive(Debug)]
+pub struct MockClock {
+    started_at: Instant,
+    /// Time since the Unix epoch when the clock was created
+    started_at_unix: Duration,
+    elapsed: Mutex<Duration>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            started_at_unix: SystemClock.unix_time(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+}
+
+impl MockClock {
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.started_at + *self.elapsed.lock().unwrap()
+    }
+
+    fn unix_time(&self) -> Duration {
+        self.started_at_unix + *self.elapsed.lock().unwrap()
+    }
+}
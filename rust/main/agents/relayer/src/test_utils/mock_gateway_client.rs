@@ -0,0 +1,57 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use serde_json::Value;
+
+use crate::msg::metadata::{GatewayClient, GatewayError};
+
+type ResponseList<T> = Arc<Mutex<VecDeque<T>>>;
+
+/// Responses are set per URL so tests don't depend on the order in which
+/// concurrent requests are sent
+#[derive(Debug, Default)]
+pub struct MockGatewayClientResponses {
+    pub fetch: Arc<Mutex<HashMap<String, VecDeque<Result<Vec<u8>, GatewayError>>>>>,
+}
+
+impl MockGatewayClientResponses {
+    pub fn push_fetch_response(&self, url: &str, res: Result<Vec<u8>, GatewayError>) {
+        self.fetch
+            .lock()
+            .unwrap()
+            .entry(url.to_owned())
+            .or_default()
+            .push_back(res);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MockGatewayClient {
+    pub responses: MockGatewayClientResponses,
+    /// Every request sent, as `(url, body)`
+    pub requests: ResponseList<(String, Option<Value>)>,
+}
+
+#[async_trait::async_trait]
+impl GatewayClient for MockGatewayClient {
+    async fn fetch(
+        &self,
+        url: &str,
+        body: Option<&Value>,
+        _request_id: Option<&str>,
+    ) -> Result<Vec<u8>, GatewayError> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push_back((url.to_owned(), body.cloned()));
+        self.responses
+            .fetch
+            .lock()
+            .unwrap()
+            .get_mut(url)
+            .and_then(|responses| responses.pop_front())
+            .unwrap_or_else(|| panic!("No mock fetch response set for {url}"))
+    }
+}
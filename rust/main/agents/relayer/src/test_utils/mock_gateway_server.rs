@@ -0,0 +1,115 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    http::{StatusCode, Uri},
+    Router,
+};
+use serde_json::json;
+
+/// What a `MockGatewayServer` responds with at a path
+#[derive(Clone, Debug)]
+pub struct MockGatewayResponse {
+    pub status: StatusCode,
+    pub body: String,
+    /// How long the server waits before responding
+    pub delay: Duration,
+}
+
+impl MockGatewayResponse {
+    /// A successful response with `body` as is
+    pub fn body(body: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::OK,
+            body: body.into(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// A successful EIP-3668 `{"data": ...}` response
+    pub fn data(data: &str) -> Self {
+        Self::body(json!({ "data": data }).to_string())
+    }
+
+    /// An error page with `status`
+    pub fn status(status: StatusCode) -> Self {
+        Self {
+            status,
+            ..Self::body("<html>Error</html>")
+        }
+    }
+
+    pub fn delayed(self, delay: Duration) -> Self {
+        Self { delay, ..self }
+    }
+}
+
+/// Local HTTP gateway serving programmable responses by path, for tests to
+/// exercise gateway scenarios without a router of their own. Paths without
+/// a response are answered with a 404.
+#[derive(Debug)]
+pub struct MockGatewayServer {
+    addr: SocketAddr,
+    responses: Arc<Mutex<HashMap<String, MockGatewayResponse>>>,
+    /// Path of every request received, in order
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockGatewayServer {
+    /// Starts serving in the background, on a random port
+    pub fn start() -> Self {
+        let responses: Arc<Mutex<HashMap<String, MockGatewayResponse>>> = Default::default();
+        let requests: Arc<Mutex<Vec<String>>> = Default::default();
+        let router = {
+            let responses = responses.clone();
+            let requests = requests.clone();
+            Router::new().fallback(move |uri: Uri| {
+                let responses = responses.clone();
+                let requests = requests.clone();
+                async move {
+                    requests.lock().unwrap().push(uri.path().to_owned());
+                    let response = responses.lock().unwrap().get(uri.path()).cloned();
+                    let Some(response) = response else {
+                        return (StatusCode::NOT_FOUND, String::new());
+                    };
+                    tokio::time::sleep(response.delay).await;
+                    (response.status, response.body)
+                }
+            })
+        };
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        Self {
+            addr,
+            responses,
+            requests,
+        }
+    }
+
+    /// Responds to every request for `path`, e.g. `/gateway/0x010203`, with
+    /// `response`
+    pub fn respond(&self, path: &str, response: MockGatewayResponse) -> &Self {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), response);
+        self
+    }
+
+    /// URL of `path` on this server, which may be a template like
+    /// `/gateway/{data}`
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+
+    /// Paths requested so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
@@ -1,4 +1,10 @@
+pub mod client_identity;
+pub mod gateway_certificate;
 pub mod mock_aggregation_ism;
 pub mod mock_base_builder;
+pub mod mock_ccip_read_ism;
+pub mod mock_clock;
+pub mod mock_gateway_client;
+pub mod mock_gateway_server;
 pub mod mock_ism;
 pub mod mock_routing_ism;
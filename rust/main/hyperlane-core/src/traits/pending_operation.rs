@@ -249,6 +249,9 @@ pub enum ReprepareReason {
     #[strum(to_string = "Could not fetch metadata")]
     /// Could not fetch metadata
     CouldNotFetchMetadata,
+    #[strum(to_string = "Awaiting offchain gateway data")]
+    /// The offchain gateways of a CCIP-read ISM did not return metadata yet
+    AwaitingOffchainGatewayData,
     #[strum(to_string = "Error estimating costs for process call")]
     /// Error estimating costs for process call
     ErrorEstimatingGas,